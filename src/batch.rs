@@ -0,0 +1,415 @@
+//! Apply an operation to many images at once.
+//!
+//! A batch input is either a directory or a glob pattern. Every matching
+//! image is processed independently, so one unreadable file doesn't stop
+//! the others, and the outcome is collected in a [`BatchSummary`].
+
+use crate::{metadata, Operation};
+use anyhow::{Context, Result};
+use image::ImageFormat;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Output name used when no template is given: keep the input file name.
+pub const DEFAULT_TEMPLATE: &str = "{stem}.{ext}";
+
+/// One image found for a batch run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchInput {
+    /// Path of the image to read.
+    pub path: PathBuf,
+    /// Directory of the image relative to the batch root, recreated under
+    /// the output directory so the input tree is mirrored.
+    pub relative_dir: PathBuf,
+}
+
+/// Whether `input` names a batch (an existing directory or a glob pattern)
+/// rather than a single file.
+pub fn is_batch_input(input: &str) -> bool {
+    // ---
+    Path::new(input).is_dir() || is_glob(input)
+}
+
+fn is_glob(input: &str) -> bool {
+    // ---
+    input.contains(['*', '?', '['])
+}
+
+/// Collect the images named by `input`, sorted by path.
+///
+/// A directory yields the files whose extension is a known image format,
+/// descending into subdirectories when `recursive` is set. A glob pattern
+/// yields every matching file; use `**` in the pattern to match across
+/// directories, as `recursive` is rejected for patterns.
+///
+/// Files below `outdir`, where the results are written, are left out when
+/// it lies inside the input tree, so that a later run doesn't pick up the
+/// results of an earlier one. An `outdir` that is the input directory
+/// itself is left to [`check_outputs`].
+pub fn find_inputs(input: &str, recursive: bool, outdir: &Path) -> Result<Vec<BatchInput>> {
+    // ---
+    anyhow::ensure!(
+        !(recursive && is_glob(input)),
+        "--recursive only applies to directories, use ** in {} to match subdirectories",
+        input
+    );
+    let root = if is_glob(input) {
+        glob_root(input)
+    } else {
+        PathBuf::from(input)
+    };
+
+    let mut paths = Vec::new();
+    if is_glob(input) {
+        for entry in glob::glob(input).context(format!("Invalid glob pattern {}", input))? {
+            let path = entry?;
+            if path.is_file() {
+                paths.push(path);
+            }
+        }
+    } else {
+        walk_dir(&root, recursive, &mut paths)?;
+    }
+    if let Ok(outdir) = fs::canonicalize(outdir) {
+        if fs::canonicalize(&root).is_ok_and(|root| root != outdir) {
+            paths
+                .retain(|path| !fs::canonicalize(path).is_ok_and(|path| path.starts_with(&outdir)));
+        }
+    }
+    paths.sort();
+
+    Ok(paths
+        .into_iter()
+        .map(|path| {
+            let relative_dir = path
+                .parent()
+                .and_then(|parent| parent.strip_prefix(&root).ok())
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            BatchInput { path, relative_dir }
+        })
+        .collect())
+}
+
+fn walk_dir(dir: &Path, recursive: bool, paths: &mut Vec<PathBuf>) -> Result<()> {
+    // ---
+    let entries = fs::read_dir(dir).context(format!("Failed to read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            if recursive {
+                walk_dir(&path, recursive, paths)?;
+            }
+        } else if ImageFormat::from_path(&path).is_ok() {
+            paths.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// The leading part of a glob pattern that contains no wildcards, used as
+/// the root when mirroring the matched files' directories.
+fn glob_root(pattern: &str) -> PathBuf {
+    // ---
+    let path = Path::new(pattern);
+    let literal: PathBuf = path
+        .components()
+        .take_while(|component| !is_glob(&component.as_os_str().to_string_lossy()))
+        .collect();
+
+    // A pattern like `photos/*.jpg` has no wildcard in its last component
+    // only if it names a file, in which case the root is its directory.
+    if literal == path {
+        literal.parent().map(Path::to_path_buf).unwrap_or_default()
+    } else if literal.as_os_str().is_empty() {
+        PathBuf::from(Component::CurDir.as_os_str())
+    } else {
+        literal
+    }
+}
+
+/// Output file name pattern for batch runs.
+///
+/// `{stem}` is replaced by the input file name without extension, `{ext}`
+/// by the input extension and `{op}` by the operation name, e.g.
+/// `{stem}_blur.{ext}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate(String);
+
+impl OutputTemplate {
+    // ---
+
+    /// Check that `template` only uses known placeholders and names a file.
+    pub fn new(template: &str) -> Result<Self> {
+        // ---
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .context(format!("Unclosed `{{` in output template {}", template))?;
+            let name = &rest[start + 1..start + end];
+            anyhow::ensure!(
+                matches!(name, "stem" | "ext" | "op"),
+                "Unknown placeholder {{{}}} in output template {}, use {{stem}}, {{ext}} or {{op}}",
+                name,
+                template
+            );
+            rest = &rest[start + end + 1..];
+        }
+        anyhow::ensure!(
+            !template.contains(['/', '\\']),
+            "Output template {} must be a file name, not a path",
+            template
+        );
+        Ok(Self(template.to_string()))
+    }
+
+    /// Output file name for `input` processed by the operation `op`.
+    pub fn render(&self, input: &Path, op: &str) -> String {
+        // ---
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let ext = input.extension().unwrap_or_default().to_string_lossy();
+        self.0
+            .replace("{stem}", &stem)
+            .replace("{ext}", &ext)
+            .replace("{op}", op)
+    }
+}
+
+impl Default for OutputTemplate {
+    // ---
+    fn default() -> Self {
+        Self(DEFAULT_TEMPLATE.to_string())
+    }
+}
+
+/// Outcome of a batch run.
+#[derive(Debug, Default)]
+pub struct BatchSummary {
+    /// Output files written successfully.
+    pub succeeded: Vec<PathBuf>,
+    /// Input files that failed, with the reason.
+    pub failed: Vec<(PathBuf, anyhow::Error)>,
+}
+
+impl BatchSummary {
+    // ---
+
+    /// Whether every input was processed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+
+impl fmt::Display for BatchSummary {
+    // ---
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // ---
+        write!(
+            f,
+            "{} of {} files succeeded, {} failed",
+            self.succeeded.len(),
+            self.succeeded.len() + self.failed.len(),
+            self.failed.len()
+        )
+    }
+}
+
+/// Path below `outdir` that `input` is written to.
+fn output_path(
+    input: &BatchInput,
+    outdir: &Path,
+    template: &OutputTemplate,
+    op_name: &str,
+) -> PathBuf {
+    // ---
+    outdir
+        .join(&input.relative_dir)
+        .join(template.render(&input.path, op_name))
+}
+
+/// Check the outputs of a batch run before anything is written: no two
+/// inputs may be written to the same file, and an output may only replace
+/// the input it is made from when `force` is set.
+pub fn check_outputs(
+    inputs: &[BatchInput],
+    outdir: &Path,
+    template: &OutputTemplate,
+    op_name: &str,
+    force: bool,
+) -> Result<()> {
+    // ---
+    let mut sources: HashMap<PathBuf, &Path> = HashMap::new();
+    for input in inputs {
+        let outfile = output_path(input, outdir, template, op_name);
+        if let Some(other) = sources.insert(outfile.clone(), &input.path) {
+            anyhow::bail!(
+                "{} and {} would both be written to {}, use an output name with {{ext}}",
+                other.display(),
+                input.path.display(),
+                outfile.display()
+            );
+        }
+
+        // Only an existing output can be the input, so a missing one is fine
+        let same_file = match (fs::canonicalize(&outfile), fs::canonicalize(&input.path)) {
+            (Ok(output), Ok(source)) => output == source,
+            _ => false,
+        };
+        anyhow::ensure!(
+            force || !same_file,
+            "{} would overwrite its input, use another output directory or --force",
+            outfile.display()
+        );
+    }
+    Ok(())
+}
+
+/// Apply `op` to every input, writing the results below `outdir` with the
+/// names given by `template`. Inputs are turned upright by their EXIF
+/// orientation first when `auto_orient` is set.
+///
+/// The outputs are checked by [`check_outputs`] before the run starts;
+/// after that, failures are recorded and don't stop the run.
+pub fn run_batch(
+    inputs: &[BatchInput],
+    outdir: &Path,
+    template: &OutputTemplate,
+    op_name: &str,
+    op: &dyn Operation,
+    auto_orient: bool,
+    force: bool,
+) -> Result<BatchSummary> {
+    // ---
+    check_outputs(inputs, outdir, template, op_name, force)?;
+
+    let mut summary = BatchSummary::default();
+    for input in inputs {
+        let dir = outdir.join(&input.relative_dir);
+        let outfile = output_path(input, outdir, template, op_name);
+
+        let result = fs::create_dir_all(&dir)
+            .context(format!("Failed to create {}", dir.display()))
+            .and_then(|_| metadata::open(&input.path, auto_orient))
+            .and_then(|img| op.apply(img))
+            .and_then(|img| {
+                img.save(&outfile)
+                    .context(format!("Failed writing {}.", outfile.display()))
+            });
+
+        match result {
+            Ok(()) => summary.succeeded.push(outfile),
+            Err(err) => summary.failed.push((input.path.clone(), err)),
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_template_render() -> Result<()> {
+        // ---
+
+        let template = OutputTemplate::new("{stem}_{op}.{ext}")?;
+        let name = template.render(Path::new("photos/cat.jpg"), "blur");
+        ensure!(name == "cat_blur.jpg", "Unexpected output name {}", name);
+
+        let name = OutputTemplate::default().render(Path::new("a/b/dog.png"), "invert");
+        ensure!(name == "dog.png", "Default template should keep the name");
+        Ok(())
+    }
+
+    #[test]
+    fn test_template_rejects_bad_placeholders() -> Result<()> {
+        // ---
+
+        ensure!(
+            OutputTemplate::new("{name}.png").is_err(),
+            "Unknown placeholder should be rejected"
+        );
+        ensure!(
+            OutputTemplate::new("{stem.png").is_err(),
+            "Unclosed placeholder should be rejected"
+        );
+        ensure!(
+            OutputTemplate::new("out/{stem}.png").is_err(),
+            "Template with a directory should be rejected"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_glob_root() -> Result<()> {
+        // ---
+
+        ensure!(glob_root("photos/*.jpg") == Path::new("photos"));
+        ensure!(glob_root("photos/**/*.jpg") == Path::new("photos"));
+        ensure!(glob_root("*.jpg") == Path::new("."));
+        ensure!(glob_root("a/b/c.png") == Path::new("a/b"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_outputs() -> Result<()> {
+        // ---
+
+        let dir = tempfile::TempDir::new()?;
+        for name in ["a.png", "a.jpg"] {
+            fs::write(dir.path().join(name), b"")?;
+        }
+        let out = dir.path().join("out");
+        let inputs = find_inputs(&dir.path().to_string_lossy(), false, &out)?;
+        let template = OutputTemplate::default();
+
+        check_outputs(&inputs, &out, &template, "invert", false)?;
+        ensure!(
+            check_outputs(&inputs, dir.path(), &template, "invert", false).is_err(),
+            "Writing over the inputs needs force"
+        );
+        check_outputs(&inputs, dir.path(), &template, "invert", true)?;
+
+        let png = OutputTemplate::new("{stem}.png")?;
+        let clash = check_outputs(&inputs, &out, &png, "invert", true);
+        ensure!(clash.is_err(), "a.png and a.jpg map to the same output");
+
+        let pattern = dir.path().join("*.png");
+        ensure!(find_inputs(&pattern.to_string_lossy(), true, &out).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_inputs_leaves_out_the_output_directory() -> Result<()> {
+        // ---
+
+        let dir = tempfile::TempDir::new()?;
+        let out = dir.path().join("sub/out");
+        fs::create_dir_all(&out)?;
+        for name in ["a.png", "sub/b.png", "sub/out/b.png"] {
+            fs::write(dir.path().join(name), b"")?;
+        }
+        let root = dir.path().to_string_lossy();
+        let names = |inputs: Vec<BatchInput>| -> Vec<PathBuf> {
+            inputs
+                .into_iter()
+                .map(|input| input.path.strip_prefix(dir.path()).unwrap().to_path_buf())
+                .collect()
+        };
+
+        let inputs = find_inputs(&root, true, &out)?;
+        ensure!(names(inputs) == [Path::new("a.png"), Path::new("sub/b.png")]);
+        let pattern = format!("{}/**/*.png", root);
+        ensure!(names(find_inputs(&pattern, false, &out)?).len() == 2);
+
+        // The input directory itself is still searched
+        ensure!(names(find_inputs(&root, true, dir.path())?).len() == 3);
+        Ok(())
+    }
+}
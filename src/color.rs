@@ -0,0 +1,499 @@
+//! Color parsing shared by every command that takes a color.
+//!
+//! Accepted forms are hex (`#rgb`, `#rgba`, `#rrggbb`, `#rrggbbaa`),
+//! `rgb(r, g, b)` / `rgba(r, g, b, a)`, `hsl(h, s%, l%)` / `hsla(h, s%, l%, a)`
+//! and the CSS named colors, including `transparent`.
+
+use anyhow::{Context, Result};
+use image::Rgba;
+
+/// Parse a color in any of the supported syntaxes.
+///
+/// ```
+/// use image::Rgba;
+///
+/// assert_eq!(mirage::parse_color("#ff8000").unwrap(), Rgba([255, 128, 0, 255]));
+/// assert_eq!(mirage::parse_color("rgb(0, 0, 255)").unwrap(), Rgba([0, 0, 255, 255]));
+/// assert_eq!(mirage::parse_color("hsl(120, 100%, 50%)").unwrap(), Rgba([0, 255, 0, 255]));
+/// assert_eq!(mirage::parse_color("RebeccaPurple").unwrap(), Rgba([102, 51, 153, 255]));
+/// ```
+pub fn parse_color(text: &str) -> Result<Rgba<u8>> {
+    // ---
+    let text = text.trim();
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(hex) = lower.strip_prefix('#') {
+        return parse_hex(hex).context(format!("Invalid hex color {}", text));
+    }
+    if let Some((function, args)) = split_function(&lower) {
+        return parse_function(function, args).context(format!("Invalid color {}", text));
+    }
+    if lower == "transparent" {
+        return Ok(Rgba([0, 0, 0, 0]));
+    }
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| *name == lower)
+        .map(|&(_, [r, g, b])| Rgba([r, g, b, 255]))
+        .context(format!(
+            "Unknown color {}, use #rrggbb[aa], rgb(r,g,b), hsl(h,s%,l%) or a CSS color name",
+            text
+        ))
+}
+
+fn parse_hex(hex: &str) -> Result<Rgba<u8>> {
+    // ---
+    anyhow::ensure!(
+        hex.chars().all(|c| c.is_ascii_hexdigit()),
+        "only hex digits are allowed"
+    );
+    let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).map(|d| d * 17);
+    let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+
+    let rgba = match hex.len() {
+        3 => [digit(0)?, digit(1)?, digit(2)?, 255],
+        4 => [digit(0)?, digit(1)?, digit(2)?, digit(3)?],
+        6 => [pair(0)?, pair(2)?, pair(4)?, 255],
+        8 => [pair(0)?, pair(2)?, pair(4)?, pair(6)?],
+        _ => anyhow::bail!("expected 3, 4, 6 or 8 hex digits"),
+    };
+    Ok(Rgba(rgba))
+}
+
+/// Split `name(args)` into its name and argument text.
+fn split_function(text: &str) -> Option<(&str, &str)> {
+    // ---
+    let (name, rest) = text.split_once('(')?;
+    let args = rest.trim_end().strip_suffix(')')?;
+    Some((name.trim(), args))
+}
+
+fn parse_function(function: &str, args: &str) -> Result<Rgba<u8>> {
+    // ---
+    // Both the legacy `rgb(1, 2, 3)` and the modern `rgb(1 2 3 / 50%)`
+    // separators are accepted.
+    let args: Vec<&str> = args
+        .split(|c: char| c == ',' || c == '/' || c.is_whitespace())
+        .filter(|arg| !arg.is_empty())
+        .collect();
+
+    let alpha = match args.len() {
+        3 => 255,
+        4 => to_u8(parse_fraction(args[3])? * 255.0),
+        n => anyhow::bail!("expected 3 or 4 components, got {}", n),
+    };
+
+    let [r, g, b] = match function {
+        "rgb" | "rgba" => [
+            parse_channel(args[0])?,
+            parse_channel(args[1])?,
+            parse_channel(args[2])?,
+        ],
+        "hsl" | "hsla" => {
+            let hue = parse_hue(args[0])?;
+            let saturation = parse_percent(args[1])?;
+            let lightness = parse_percent(args[2])?;
+            hsl_to_rgb(hue, saturation, lightness).map(|c| to_u8(c * 255.0))
+        }
+        _ => anyhow::bail!("unknown color function {}(), use rgb() or hsl()", function),
+    };
+    Ok(Rgba([r, g, b, alpha]))
+}
+
+/// A color channel given as 0-255 or as a percentage.
+fn parse_channel(arg: &str) -> Result<u8> {
+    // ---
+    let value = match arg.strip_suffix('%') {
+        Some(percent) => parse_number(percent)? * 2.55,
+        None => parse_number(arg)?,
+    };
+    anyhow::ensure!(
+        (0.0..=255.0).contains(&value),
+        "channel {} is outside 0-255",
+        arg
+    );
+    Ok(to_u8(value))
+}
+
+/// An alpha value given as 0-1 or as a percentage.
+fn parse_fraction(arg: &str) -> Result<f32> {
+    // ---
+    let value = match arg.strip_suffix('%') {
+        Some(percent) => parse_number(percent)? / 100.0,
+        None => parse_number(arg)?,
+    };
+    anyhow::ensure!((0.0..=1.0).contains(&value), "alpha {} is outside 0-1", arg);
+    Ok(value)
+}
+
+/// A saturation or lightness percentage, returned as 0-1.
+fn parse_percent(arg: &str) -> Result<f32> {
+    // ---
+    let percent = arg
+        .strip_suffix('%')
+        .context(format!("{} should be a percentage", arg))?;
+    let value = parse_number(percent)? / 100.0;
+    anyhow::ensure!((0.0..=1.0).contains(&value), "{} is outside 0-100%", arg);
+    Ok(value)
+}
+
+/// A hue in degrees, optionally suffixed with `deg`, normalized to 0-360.
+fn parse_hue(arg: &str) -> Result<f32> {
+    // ---
+    let degrees = parse_number(arg.strip_suffix("deg").unwrap_or(arg))?;
+    Ok(degrees.rem_euclid(360.0))
+}
+
+fn parse_number(arg: &str) -> Result<f32> {
+    // ---
+    let value: f32 = arg
+        .parse()
+        .map_err(|_| anyhow::anyhow!("`{}` isn't a valid number", arg))?;
+    anyhow::ensure!(value.is_finite(), "`{}` isn't a valid number", arg);
+    Ok(value)
+}
+
+fn to_u8(value: f32) -> u8 {
+    // ---
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+/// Convert hue (degrees), saturation and lightness (0-1) to RGB in 0-1.
+pub(crate) fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+    // ---
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue.rem_euclid(360.0) / 60.0;
+    let second = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let [r, g, b] = match sector as u32 {
+        0 => [chroma, second, 0.0],
+        1 => [second, chroma, 0.0],
+        2 => [0.0, chroma, second],
+        3 => [0.0, second, chroma],
+        4 => [second, 0.0, chroma],
+        _ => [chroma, 0.0, second],
+    };
+    let offset = lightness - chroma / 2.0;
+    [r + offset, g + offset, b + offset]
+}
+
+/// RGB in 0-1 to hue (degrees), saturation and lightness (0-1), the
+/// inverse of [`hsl_to_rgb`].
+pub(crate) fn rgb_to_hsl([r, g, b]: [f32; 3]) -> [f32; 3] {
+    // ---
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let lightness = (max + min) / 2.0;
+    if delta == 0.0 {
+        return [0.0, 0.0, lightness];
+    }
+    let hue = if max == r {
+        ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        (b - r) / delta + 2.0
+    } else {
+        (r - g) / delta + 4.0
+    };
+    let saturation = delta / (1.0 - (2.0 * lightness - 1.0).abs());
+    [hue * 60.0, saturation, lightness]
+}
+
+/// RGB in 0-1 to hue (0-1), saturation and value.
+pub(crate) fn rgb_to_hsv([r, g, b]: [f64; 3]) -> [f64; 3] {
+    // ---
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        ((g - b) / delta).rem_euclid(6.0) / 6.0
+    } else if max == g {
+        ((b - r) / delta + 2.0) / 6.0
+    } else {
+        ((r - g) / delta + 4.0) / 6.0
+    };
+    let saturation = if max > 0.0 { delta / max } else { 0.0 };
+    [hue, saturation, max]
+}
+
+/// Hue (0-1), saturation and value to RGB in 0-1.
+pub(crate) fn hsv_to_rgb([h, s, v]: [f64; 3]) -> [f64; 3] {
+    // ---
+    let sector = h * 6.0;
+    let f = sector - sector.floor();
+    let (p, q, t) = (v * (1.0 - s), v * (1.0 - s * f), v * (1.0 - s * (1.0 - f)));
+    match sector as u32 % 6 {
+        0 => [v, t, p],
+        1 => [q, v, p],
+        2 => [p, v, t],
+        3 => [p, q, v],
+        4 => [t, p, v],
+        _ => [v, p, q],
+    }
+}
+
+/// Linear RGB in 0-1 to OKLab lightness (0-1) and the a and b axes.
+pub(crate) fn linear_to_oklab([r, g, b]: [f32; 3]) -> [f32; 3] {
+    // ---
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+/// OKLab to linear RGB, the inverse of [`linear_to_oklab`]. Colors outside
+/// the RGB gamut come out below 0 or above 1.
+pub(crate) fn oklab_to_linear([lightness, a, b]: [f32; 3]) -> [f32; 3] {
+    // ---
+    let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m = (lightness - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+    [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ]
+}
+
+/// The CSS Color Module Level 4 named colors.
+const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("aliceblue", [240, 248, 255]),
+    ("antiquewhite", [250, 235, 215]),
+    ("aqua", [0, 255, 255]),
+    ("aquamarine", [127, 255, 212]),
+    ("azure", [240, 255, 255]),
+    ("beige", [245, 245, 220]),
+    ("bisque", [255, 228, 196]),
+    ("black", [0, 0, 0]),
+    ("blanchedalmond", [255, 235, 205]),
+    ("blue", [0, 0, 255]),
+    ("blueviolet", [138, 43, 226]),
+    ("brown", [165, 42, 42]),
+    ("burlywood", [222, 184, 135]),
+    ("cadetblue", [95, 158, 160]),
+    ("chartreuse", [127, 255, 0]),
+    ("chocolate", [210, 105, 30]),
+    ("coral", [255, 127, 80]),
+    ("cornflowerblue", [100, 149, 237]),
+    ("cornsilk", [255, 248, 220]),
+    ("crimson", [220, 20, 60]),
+    ("cyan", [0, 255, 255]),
+    ("darkblue", [0, 0, 139]),
+    ("darkcyan", [0, 139, 139]),
+    ("darkgoldenrod", [184, 134, 11]),
+    ("darkgray", [169, 169, 169]),
+    ("darkgreen", [0, 100, 0]),
+    ("darkgrey", [169, 169, 169]),
+    ("darkkhaki", [189, 183, 107]),
+    ("darkmagenta", [139, 0, 139]),
+    ("darkolivegreen", [85, 107, 47]),
+    ("darkorange", [255, 140, 0]),
+    ("darkorchid", [153, 50, 204]),
+    ("darkred", [139, 0, 0]),
+    ("darksalmon", [233, 150, 122]),
+    ("darkseagreen", [143, 188, 143]),
+    ("darkslateblue", [72, 61, 139]),
+    ("darkslategray", [47, 79, 79]),
+    ("darkslategrey", [47, 79, 79]),
+    ("darkturquoise", [0, 206, 209]),
+    ("darkviolet", [148, 0, 211]),
+    ("deeppink", [255, 20, 147]),
+    ("deepskyblue", [0, 191, 255]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("dodgerblue", [30, 144, 255]),
+    ("firebrick", [178, 34, 34]),
+    ("floralwhite", [255, 250, 240]),
+    ("forestgreen", [34, 139, 34]),
+    ("fuchsia", [255, 0, 255]),
+    ("gainsboro", [220, 220, 220]),
+    ("ghostwhite", [248, 248, 255]),
+    ("gold", [255, 215, 0]),
+    ("goldenrod", [218, 165, 32]),
+    ("gray", [128, 128, 128]),
+    ("green", [0, 128, 0]),
+    ("greenyellow", [173, 255, 47]),
+    ("grey", [128, 128, 128]),
+    ("honeydew", [240, 255, 240]),
+    ("hotpink", [255, 105, 180]),
+    ("indianred", [205, 92, 92]),
+    ("indigo", [75, 0, 130]),
+    ("ivory", [255, 255, 240]),
+    ("khaki", [240, 230, 140]),
+    ("lavender", [230, 230, 250]),
+    ("lavenderblush", [255, 240, 245]),
+    ("lawngreen", [124, 252, 0]),
+    ("lemonchiffon", [255, 250, 205]),
+    ("lightblue", [173, 216, 230]),
+    ("lightcoral", [240, 128, 128]),
+    ("lightcyan", [224, 255, 255]),
+    ("lightgoldenrodyellow", [250, 250, 210]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgreen", [144, 238, 144]),
+    ("lightgrey", [211, 211, 211]),
+    ("lightpink", [255, 182, 193]),
+    ("lightsalmon", [255, 160, 122]),
+    ("lightseagreen", [32, 178, 170]),
+    ("lightskyblue", [135, 206, 250]),
+    ("lightslategray", [119, 136, 153]),
+    ("lightslategrey", [119, 136, 153]),
+    ("lightsteelblue", [176, 196, 222]),
+    ("lightyellow", [255, 255, 224]),
+    ("lime", [0, 255, 0]),
+    ("limegreen", [50, 205, 50]),
+    ("linen", [250, 240, 230]),
+    ("magenta", [255, 0, 255]),
+    ("maroon", [128, 0, 0]),
+    ("mediumaquamarine", [102, 205, 170]),
+    ("mediumblue", [0, 0, 205]),
+    ("mediumorchid", [186, 85, 211]),
+    ("mediumpurple", [147, 112, 219]),
+    ("mediumseagreen", [60, 179, 113]),
+    ("mediumslateblue", [123, 104, 238]),
+    ("mediumspringgreen", [0, 250, 154]),
+    ("mediumturquoise", [72, 209, 204]),
+    ("mediumvioletred", [199, 21, 133]),
+    ("midnightblue", [25, 25, 112]),
+    ("mintcream", [245, 255, 250]),
+    ("mistyrose", [255, 228, 225]),
+    ("moccasin", [255, 228, 181]),
+    ("navajowhite", [255, 222, 173]),
+    ("navy", [0, 0, 128]),
+    ("oldlace", [253, 245, 230]),
+    ("olive", [128, 128, 0]),
+    ("olivedrab", [107, 142, 35]),
+    ("orange", [255, 165, 0]),
+    ("orangered", [255, 69, 0]),
+    ("orchid", [218, 112, 214]),
+    ("palegoldenrod", [238, 232, 170]),
+    ("palegreen", [152, 251, 152]),
+    ("paleturquoise", [175, 238, 238]),
+    ("palevioletred", [219, 112, 147]),
+    ("papayawhip", [255, 239, 213]),
+    ("peachpuff", [255, 218, 185]),
+    ("peru", [205, 133, 63]),
+    ("pink", [255, 192, 203]),
+    ("plum", [221, 160, 221]),
+    ("powderblue", [176, 224, 230]),
+    ("purple", [128, 0, 128]),
+    ("rebeccapurple", [102, 51, 153]),
+    ("red", [255, 0, 0]),
+    ("rosybrown", [188, 143, 143]),
+    ("royalblue", [65, 105, 225]),
+    ("saddlebrown", [139, 69, 19]),
+    ("salmon", [250, 128, 114]),
+    ("sandybrown", [244, 164, 96]),
+    ("seagreen", [46, 139, 87]),
+    ("seashell", [255, 245, 238]),
+    ("sienna", [160, 82, 45]),
+    ("silver", [192, 192, 192]),
+    ("skyblue", [135, 206, 235]),
+    ("slateblue", [106, 90, 205]),
+    ("slategray", [112, 128, 144]),
+    ("slategrey", [112, 128, 144]),
+    ("snow", [255, 250, 250]),
+    ("springgreen", [0, 255, 127]),
+    ("steelblue", [70, 130, 180]),
+    ("tan", [210, 180, 140]),
+    ("teal", [0, 128, 128]),
+    ("thistle", [216, 191, 216]),
+    ("tomato", [255, 99, 71]),
+    ("turquoise", [64, 224, 208]),
+    ("violet", [238, 130, 238]),
+    ("wheat", [245, 222, 179]),
+    ("white", [255, 255, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("yellow", [255, 255, 0]),
+    ("yellowgreen", [154, 205, 50]),
+];
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_parse_hex_forms() -> Result<()> {
+        // ---
+
+        ensure!(parse_color("#f80")? == Rgba([255, 136, 0, 255]));
+        ensure!(parse_color("#f808")? == Rgba([255, 136, 0, 136]));
+        ensure!(parse_color("#FF8800")? == Rgba([255, 136, 0, 255]));
+        ensure!(parse_color("#ff880080")? == Rgba([255, 136, 0, 128]));
+        ensure!(parse_color("#ff888").is_err(), "Odd length should fail");
+        ensure!(parse_color("#gg8800").is_err(), "Non hex digit should fail");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_rgb_functions() -> Result<()> {
+        // ---
+
+        ensure!(parse_color("rgb(10, 20, 30)")? == Rgba([10, 20, 30, 255]));
+        ensure!(parse_color("rgba(10,20,30,0.5)")? == Rgba([10, 20, 30, 128]));
+        ensure!(parse_color("rgb(100% 0% 50% / 25%)")? == Rgba([255, 0, 128, 64]));
+        ensure!(
+            parse_color("rgb(256, 0, 0)").is_err(),
+            "Out of range should fail"
+        );
+        ensure!(
+            parse_color("rgb(1, 2)").is_err(),
+            "Missing component should fail"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_hsl_functions() -> Result<()> {
+        // ---
+
+        ensure!(parse_color("hsl(0, 100%, 50%)")? == Rgba([255, 0, 0, 255]));
+        ensure!(parse_color("hsl(240deg 100% 50%)")? == Rgba([0, 0, 255, 255]));
+        ensure!(parse_color("hsla(-60, 100%, 50%, 1)")? == Rgba([255, 0, 255, 255]));
+        ensure!(parse_color("hsl(0, 0%, 100%)")? == Rgba([255, 255, 255, 255]));
+        ensure!(
+            parse_color("hsl(0, 100, 50%)").is_err(),
+            "Saturation needs %"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_named_colors() -> Result<()> {
+        // ---
+
+        ensure!(parse_color("red")? == Rgba([255, 0, 0, 255]));
+        ensure!(parse_color("  CornflowerBlue ")? == Rgba([100, 149, 237, 255]));
+        ensure!(parse_color("transparent")? == Rgba([0, 0, 0, 0]));
+        ensure!(
+            parse_color("notacolor").is_err(),
+            "Unknown name should fail"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_oklab_round_trip() -> Result<()> {
+        // ---
+
+        let white = linear_to_oklab([1.0, 1.0, 1.0]);
+        ensure!((white[0] - 1.0).abs() < 1e-3 && white[1].abs() < 1e-3 && white[2].abs() < 1e-3);
+        for rgb in [[0.2, 0.5, 0.9], [1.0, 0.0, 0.0], [0.0, 0.0, 0.0]] {
+            let back = oklab_to_linear(linear_to_oklab(rgb));
+            ensure!(
+                back.iter().zip(rgb).all(|(a, b)| (a - b).abs() < 1e-3),
+                "{:?} came back as {:?}",
+                rgb,
+                back
+            );
+        }
+        Ok(())
+    }
+}
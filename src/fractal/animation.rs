@@ -0,0 +1,332 @@
+//! Fractal animations: zooms between two keyframes and palette cycling.
+//!
+//! The zoom is interpolated exponentially, so every frame magnifies by the
+//! same factor, and the center moves along with it so that the view closes
+//! in on the end keyframe at a steady speed on screen. Centers are
+//! interpolated with [`BigComplex`] arithmetic so deep zooms stay exact.
+
+use super::{fractal, BigComplex, FractalKind, FractalParams};
+use crate::check_size;
+use anyhow::{Context, Result};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, DynamicImage, Frame};
+use num_complex::Complex64;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// The view at one end of an animation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Keyframe {
+    pub center: BigComplex,
+    pub zoom: f64,
+    /// Constant `c` of the Julia set, unused for Mandelbrot.
+    pub julia: Complex64,
+}
+
+impl From<&FractalParams> for Keyframe {
+    // ---
+    fn from(params: &FractalParams) -> Self {
+        Self {
+            center: params
+                .deep_center
+                .clone()
+                .unwrap_or_else(|| params.center.into()),
+            zoom: params.zoom,
+            julia: params.julia,
+        }
+    }
+}
+
+/// How the frames of an animation are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum AnimationFormat {
+    /// animated GIF, quantized to 256 colors per frame
+    Gif,
+    /// animated PNG with full color
+    Apng,
+    /// one PNG per frame, named by a printf-style pattern such as `frame_%05d.png`
+    Frames,
+}
+
+impl AnimationFormat {
+    // ---
+
+    /// Guess the format from an output path: a `%` pattern means numbered
+    /// frames, otherwise the extension decides.
+    pub fn from_path(path: &str) -> Option<Self> {
+        // ---
+        if path.contains('%') {
+            return Some(Self::Frames);
+        }
+        let extension = Path::new(path).extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "gif" => Some(Self::Gif),
+            "png" | "apng" => Some(Self::Apng),
+            _ => None,
+        }
+    }
+}
+
+/// An animation from `start` to `end` over `frames` frames, optionally
+/// cycling the palette `palette_cycles` times on the way.
+///
+/// The other rendering settings (fractal kind, iterations, coloring) come
+/// from the [`FractalParams`] passed to [`params_at`](Self::params_at) and
+/// [`save`](Self::save). When the start and end keyframes are the same the
+/// animation is a pure palette cycle, which loops seamlessly.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Animation {
+    pub start: Keyframe,
+    pub end: Keyframe,
+    pub frames: u32,
+    pub palette_cycles: f64,
+    /// Display time of each frame in milliseconds.
+    pub delay_ms: u32,
+}
+
+impl Animation {
+    // ---
+
+    /// A still animation of `frames` frames at the view of `params`.
+    pub fn new(params: &FractalParams, frames: u32) -> Self {
+        // ---
+        let start = Keyframe::from(params);
+        Self {
+            end: start.clone(),
+            start,
+            frames,
+            palette_cycles: 0.0,
+            delay_ms: 40,
+        }
+    }
+
+    /// The parameters of frame `index`, counting from 0.
+    ///
+    /// Deep zoom is used for a Mandelbrot frame when `base` asks for it or
+    /// when the frame's zoom needs it.
+    pub fn params_at(&self, base: &FractalParams, index: u32) -> FractalParams {
+        // ---
+        let t = match self.frames {
+            0 | 1 => 0.0,
+            frames => index as f64 / (frames - 1) as f64,
+        };
+        let (start, end) = (&self.start, &self.end);
+
+        // With the zoom going exponentially from z0 to z1, moving the center
+        // by `w = (1 - r^t) / (1 - r)` of the way, r = z0 / z1, keeps one
+        // point of the plane fixed on screen: the view zooms straight in.
+        // Zooming in, the center is taken from the end so its error shrinks
+        // with the view; zooming out, from the start.
+        let ratio = start.zoom / end.zoom;
+        let zoom = start.zoom * (end.zoom / start.zoom).powf(t);
+        let center = if (ratio - 1.0).abs() < 1e-12 {
+            start.center.lerp(&end.center, t)
+        } else if ratio < 1.0 {
+            end.center
+                .lerp(&start.center, (ratio.powf(t) - ratio) / (1.0 - ratio))
+        } else {
+            start
+                .center
+                .lerp(&end.center, (1.0 - ratio.powf(t)) / (1.0 - ratio))
+        };
+
+        let mut params = FractalParams {
+            julia: start.julia + (end.julia - start.julia) * t,
+            center: center.to_complex64(),
+            zoom,
+            deep_center: None,
+            ..base.clone()
+        };
+        params.coloring.offset += self.palette_cycles * index as f64 / self.frames.max(1) as f64;
+        if params.kind == FractalKind::Mandelbrot
+            && (base.deep_center.is_some() || params.needs_deep_zoom())
+        {
+            params.deep_center = Some(center);
+        }
+        params
+    }
+
+    /// Render every frame and write them to `path`, calling
+    /// `progress(frames_done, total_frames)` after each one.
+    pub fn save(
+        &self,
+        path: &str,
+        format: AnimationFormat,
+        width: u32,
+        height: u32,
+        base: &FractalParams,
+        mut progress: impl FnMut(u32, u32),
+    ) -> Result<()> {
+        // ---
+        check_size((width, height))?;
+        if format == AnimationFormat::Frames {
+            frame_path(path, 0).context(format!("{} needs a frame number such as %05d", path))?;
+        }
+        let frames = (0..self.frames).map(|index| {
+            let img = fractal(width, height, &self.params_at(base, index));
+            progress(index + 1, self.frames);
+            img
+        });
+
+        match format {
+            AnimationFormat::Gif => {
+                let file = File::create(path).context(format!("Failed to create {}", path))?;
+                let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+                encoder.set_repeat(Repeat::Infinite)?;
+                let delay = Delay::from_numer_denom_ms(self.delay_ms, 1);
+                for img in frames {
+                    let rgba = DynamicImage::ImageRgb8(img?).into_rgba8();
+                    encoder.encode_frame(Frame::from_parts(rgba, 0, 0, delay))?;
+                }
+            }
+
+            AnimationFormat::Apng => {
+                let file = File::create(path).context(format!("Failed to create {}", path))?;
+                let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(self.frames, 0)?;
+                encoder.set_frame_delay(self.delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+                let mut writer = encoder.write_header()?;
+                for img in frames {
+                    writer.write_image_data(img?.as_raw())?;
+                }
+                writer.finish()?;
+            }
+
+            AnimationFormat::Frames => {
+                for (index, img) in frames.enumerate() {
+                    let name = frame_path(path, index as u32).unwrap_or_default();
+                    img?.save(&name)
+                        .context(format!("Failed writing {}.", name))?;
+                }
+            }
+        } // match
+
+        Ok(())
+    }
+}
+
+/// Substitute `index` into the first `%d` or `%0Nd` of `pattern`.
+fn frame_path(pattern: &str, index: u32) -> Option<String> {
+    // ---
+    let start = pattern.find('%')?;
+    let rest = &pattern[start + 1..];
+    let end = rest.find('d')?;
+    let spec = &rest[..end];
+    let width = match spec {
+        "" => 0,
+        _ if spec.starts_with('0') => spec.parse().ok()?,
+        _ => return None,
+    };
+    Some(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        index,
+        &rest[end + 1..],
+        width = width
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_frame_path_patterns() -> Result<()> {
+        // ---
+
+        ensure!(frame_path("frame_%05d.png", 42).as_deref() == Some("frame_00042.png"));
+        ensure!(frame_path("out/%d.png", 7).as_deref() == Some("out/7.png"));
+        ensure!(frame_path("frame.png", 1).is_none(), "No pattern");
+        ensure!(
+            frame_path("frame_%5d.png", 1).is_none(),
+            "Only zero padding"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_from_path() -> Result<()> {
+        // ---
+
+        ensure!(AnimationFormat::from_path("zoom.GIF") == Some(AnimationFormat::Gif));
+        ensure!(AnimationFormat::from_path("zoom.png") == Some(AnimationFormat::Apng));
+        ensure!(AnimationFormat::from_path("f_%03d.png") == Some(AnimationFormat::Frames));
+        ensure!(AnimationFormat::from_path("zoom.jpg").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_zoom_is_exponential_and_ends_on_keyframes() -> Result<()> {
+        // ---
+
+        let end_point = || Complex64::new(-0.7436, 0.1318);
+        let base = FractalParams::mandelbrot();
+        let mut animation = Animation::new(&base, 5);
+        animation.end = Keyframe {
+            center: "-0.7436,0.1318".parse()?,
+            zoom: 10000.0,
+            julia: base.julia,
+        };
+
+        let zooms: Vec<f64> = (0..5).map(|i| animation.params_at(&base, i).zoom).collect();
+        for (zoom, expected) in zooms.iter().zip([1.0, 10.0, 100.0, 1000.0, 10000.0]) {
+            ensure!(
+                (zoom / expected - 1.0).abs() < 1e-9,
+                "Expected zoom {}, got {}",
+                expected,
+                zoom
+            );
+        }
+
+        let first = animation.params_at(&base, 0);
+        let last = animation.params_at(&base, 4);
+        ensure!(first.center == base.center, "First frame is the start view");
+        ensure!(
+            (last.center - end_point()).norm() < 1e-12,
+            "Last frame is the end view"
+        );
+
+        // The point the zoom closes in on keeps its place on screen
+        let ratio = 1.0 / 10000.0;
+        let fixed = (end_point() - base.center * ratio) / (1.0 - ratio);
+        let offsets: Vec<Complex64> = (0..5)
+            .map(|i| {
+                let params = animation.params_at(&base, i);
+                (fixed - params.center) * params.zoom
+            })
+            .collect();
+        ensure!(
+            offsets
+                .windows(2)
+                .all(|pair| (pair[0] - pair[1]).norm() < 1e-9),
+            "View should zoom straight in: {:?}",
+            offsets
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_palette_cycle_loops() -> Result<()> {
+        // ---
+
+        let base = FractalParams::default();
+        let mut animation = Animation::new(&base, 8);
+        animation.palette_cycles = 1.0;
+        let offsets: Vec<f64> = (0..8)
+            .map(|i| animation.params_at(&base, i).coloring.offset)
+            .collect();
+        ensure!(offsets[0] == 0.0 && offsets[4] == 0.5);
+        ensure!(
+            (offsets[7] - 0.875).abs() < 1e-12,
+            "Last frame stops one step short of a full cycle"
+        );
+        ensure!(animation.params_at(&base, 3).center == base.center);
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! Mapping escape-time samples to colors.
+
+use super::palette::Palette;
+use image::{Rgb, RgbImage};
+use num_complex::Complex64;
+
+/// How iteration results are turned into colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorMode {
+    /// escape count in green over a red/blue background
+    Classic,
+    /// continuous (normalized) iteration count, free of banding
+    Smooth,
+    /// palette spread evenly over the pixels by histogram equalization
+    Histogram,
+    /// distance of each orbit's closest approach to the trap point
+    OrbitTrap,
+}
+
+/// Coloring settings of a fractal render.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Coloring {
+    pub mode: ColorMode,
+    pub palette: Palette,
+    /// Palette cycle length: iterations per cycle in smooth mode, and
+    /// `cycle / 64` plane units per cycle in orbit-trap mode, so the
+    /// default of 64 means one unit.
+    pub cycle: f64,
+    /// Shift of the palette position, as a fraction of a cycle.
+    pub offset: f64,
+    /// Point whose distance to the orbit is measured in orbit-trap mode.
+    pub trap: Complex64,
+    /// Color of points that never escape, except in orbit-trap mode.
+    pub interior: Rgb<u8>,
+}
+
+impl Default for Coloring {
+    // ---
+    fn default() -> Self {
+        Self {
+            mode: ColorMode::Classic,
+            palette: Palette::default(),
+            cycle: 64.0,
+            offset: 0.0,
+            trap: Complex64::new(0.0, 0.0),
+            interior: Rgb([0, 0, 0]),
+        }
+    }
+}
+
+/// The outcome of iterating one pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Sample {
+    /// Iterations performed before escaping or reaching the limit.
+    pub iterations: u32,
+    /// Whether the orbit left the bailout radius.
+    pub escaped: bool,
+    /// Continuous iteration count, equal to `iterations` when not escaped.
+    pub smooth: f64,
+    /// Smallest distance between the orbit and the trap point.
+    pub trap_distance: f64,
+}
+
+impl Sample {
+    // ---
+
+    /// The sample of an orbit that stopped at `z` after `iterations`.
+    pub(crate) fn from_orbit(
+        iterations: u32,
+        z: Complex64,
+        bailout_sqr: f64,
+        trap_distance: f64,
+    ) -> Self {
+        // ---
+        let escaped = z.norm_sqr() > bailout_sqr;
+        let smooth = if escaped {
+            // Normalized iteration count: log2(log2|z|) measures how far
+            // past the bailout radius the last step went.
+            let log_modulus = z.norm_sqr().ln() / 2.0;
+            iterations as f64 + 1.0 - (log_modulus / std::f64::consts::LN_2).log2()
+        } else {
+            iterations as f64
+        };
+
+        Self {
+            iterations,
+            escaped,
+            smooth,
+            trap_distance,
+        }
+    }
+}
+
+impl Coloring {
+    // ---
+
+    /// Squared bailout radius. Smooth coloring needs a large radius for the
+    /// normalized count to be accurate; classic keeps the radius of 2 that
+    /// its escape counts were designed around.
+    pub(crate) fn bailout_sqr(&self) -> f64 {
+        // ---
+        match self.mode {
+            ColorMode::Classic => 4.0,
+            _ => 256.0 * 256.0,
+        }
+    }
+
+    /// Color all `samples`, laid out row by row, into a `width` x `height`
+    /// image.
+    pub(crate) fn paint(
+        &self,
+        samples: &[Sample],
+        width: u32,
+        height: u32,
+        max_iterations: u32,
+    ) -> RgbImage {
+        // ---
+        let histogram = match self.mode {
+            ColorMode::Histogram => Some(cumulative_histogram(samples, max_iterations)),
+            _ => None,
+        };
+
+        RgbImage::from_fn(width, height, |x, y| {
+            let sample = &samples[(y as usize) * (width as usize) + x as usize];
+            match self.mode {
+                ColorMode::Classic => classic(x, y, sample, max_iterations),
+                ColorMode::OrbitTrap => self
+                    .palette
+                    .sample_cyclic(sample.trap_distance / self.cycle_units() + self.offset),
+                _ if !sample.escaped => self.interior,
+                ColorMode::Smooth => self
+                    .palette
+                    .sample_cyclic(sample.smooth / self.cycle + self.offset),
+                ColorMode::Histogram => {
+                    let cdf = histogram.as_deref().unwrap_or_default();
+                    let t = equalized(cdf, sample.smooth);
+                    if self.offset == 0.0 {
+                        self.palette.sample(t)
+                    } else {
+                        self.palette.sample_cyclic(t + self.offset)
+                    }
+                }
+            }
+        })
+    }
+
+    fn cycle_units(&self) -> f64 {
+        // ---
+        self.cycle / 64.0
+    }
+}
+
+/// The original coloring: red and blue ramp with the pixel position and
+/// green is the escape count scaled to 0-255.
+fn classic(x: u32, y: u32, sample: &Sample, max_iterations: u32) -> Rgb<u8> {
+    // ---
+    let red = (0.3 * x as f32) as u8;
+    let blue = (0.3 * y as f32) as u8;
+    let green = (sample.iterations as u64 * 255 / max_iterations.max(1) as u64) as u8;
+    Rgb([red, green, blue])
+}
+
+/// Fraction of escaped pixels with an iteration count below each count.
+fn cumulative_histogram(samples: &[Sample], max_iterations: u32) -> Vec<f64> {
+    // ---
+    let mut counts = vec![0u64; max_iterations as usize + 2];
+    for sample in samples.iter().filter(|s| s.escaped) {
+        counts[sample.iterations as usize] += 1;
+    }
+
+    let total = counts.iter().sum::<u64>().max(1) as f64;
+    let mut running = 0;
+    counts
+        .iter()
+        .map(|count| {
+            let below = running;
+            running += count;
+            below as f64 / total
+        })
+        .collect()
+}
+
+/// Palette position of a continuous count, interpolating between the
+/// cumulative fractions of the neighbouring integer counts.
+fn equalized(cdf: &[f64], smooth: f64) -> f64 {
+    // ---
+    if cdf.is_empty() {
+        return 0.0;
+    }
+    let last = cdf.len() - 1;
+    let smooth = smooth.max(0.0);
+    let index = (smooth.floor() as usize).min(last);
+    let next = (index + 1).min(last);
+    cdf[index] + (cdf[next] - cdf[index]) * (smooth - index as f64).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    fn escaped(iterations: u32) -> Sample {
+        Sample {
+            iterations,
+            escaped: true,
+            smooth: iterations as f64,
+            trap_distance: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_histogram_spreads_counts() -> Result<()> {
+        // ---
+
+        // Most pixels escape at 2, a few at 10; equalization gives the
+        // common count the lower half of the palette
+        let samples: Vec<Sample> = [2, 2, 2, 2, 10, 10, 10, 10].map(escaped).to_vec();
+        let cdf = cumulative_histogram(&samples, 20);
+        ensure!(cdf[2] == 0.0 && cdf[3] == 0.5 && cdf[10] == 0.5 && cdf[11] == 1.0);
+        ensure!((equalized(&cdf, 2.5) - 0.25).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_interior_color() -> Result<()> {
+        // ---
+
+        let coloring = Coloring {
+            mode: ColorMode::Smooth,
+            interior: Rgb([1, 2, 3]),
+            ..Coloring::default()
+        };
+        let inside = Sample {
+            escaped: false,
+            ..escaped(50)
+        };
+        let img = coloring.paint(&[inside, escaped(5)], 2, 1, 50);
+        ensure!(img.get_pixel(0, 0) == &Rgb([1, 2, 3]));
+        ensure!(img.get_pixel(1, 0) != &Rgb([1, 2, 3]));
+        Ok(())
+    }
+}
@@ -0,0 +1,544 @@
+//! Deep-zoom Mandelbrot rendering by perturbation.
+//!
+//! Past a zoom of about 1e13 neighbouring pixels are closer together than
+//! `f64` can resolve. Instead of iterating every pixel at high precision,
+//! one reference orbit is computed at the image center with arbitrary
+//! precision, and every pixel iterates only its small difference from that
+//! orbit in `f64`:
+//!
+//! ```text
+//! z = Z + dz,  c = C + dc,  dz' = 2 Z dz + dz² + dc
+//! ```
+//!
+//! A series approximation in `dc`, checked against exactly iterated probe
+//! pixels, skips the iterations that all pixels share, and pixels whose
+//! difference grows larger than the orbit itself (a "glitch", where the
+//! reference no longer represents them) are rebased onto the start of the
+//! reference orbit.
+
+use super::{ColorMode, FractalParams, Sample};
+use anyhow::{Context, Result};
+use num_bigint::{BigInt, Sign};
+use num_complex::Complex64;
+use num_traits::ToPrimitive;
+use std::fmt;
+use std::str::FromStr;
+
+/// Extra bits of precision kept beyond what the pixel spacing needs.
+const GUARD_BITS: u64 = 64;
+
+/// Largest power of ten a number may be written with. A zoom within the
+/// range of `f64` never needs more than about 1100 bits, or 330 digits, so
+/// anything smaller is zero at the working precision and anything larger
+/// is far outside the set; rejecting them keeps the powers of ten small.
+const MAX_EXPONENT: i64 = 400;
+
+/// Largest relative error between the series and an exact probe for the
+/// series to be trusted.
+const SERIES_TOLERANCE: f64 = 1e-9;
+
+/// A complex number written as decimal strings, kept exactly so that deep
+/// zoom centers don't lose digits.
+///
+/// Parsed from `RE,IM`, where each part is a decimal number with an
+/// optional exponent, e.g. `-1.7499999999999999999999999999999999,0`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigComplex {
+    re: String,
+    im: String,
+}
+
+impl FromStr for BigComplex {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let (re, im) = text
+            .split_once(',')
+            .context(format!("Invalid complex number {}, use RE,IM", text))?;
+        let (re, im) = (re.trim(), im.trim());
+        for part in [re, im] {
+            Decimal::parse(part).context(format!("Invalid number {}", part))?;
+        }
+        Ok(Self {
+            re: re.to_string(),
+            im: im.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for BigComplex {
+    // ---
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.re, self.im)
+    }
+}
+
+impl From<Complex64> for BigComplex {
+    // ---
+    fn from(c: Complex64) -> Self {
+        Self {
+            re: format!("{:e}", c.re),
+            im: format!("{:e}", c.im),
+        }
+    }
+}
+
+impl BigComplex {
+    // ---
+
+    /// The nearest `f64` complex number.
+    pub fn to_complex64(&self) -> Complex64 {
+        // ---
+        let parse = |part: &str| part.parse::<f64>().unwrap_or_default();
+        Complex64::new(parse(&self.re), parse(&self.im))
+    }
+
+    fn to_fixed(&self, bits: u64) -> (Fixed, Fixed) {
+        // ---
+        let fixed = |part: &str| {
+            Decimal::parse(part)
+                .map(|decimal| decimal.to_fixed(bits))
+                .unwrap_or_default()
+        };
+        (fixed(&self.re), fixed(&self.im))
+    }
+
+    /// The point a fraction `t` of the way from `self` to `other`, exact to
+    /// the digits of both ends so that deep zoom animations don't drift.
+    pub fn lerp(&self, other: &BigComplex, t: f64) -> BigComplex {
+        // ---
+        let digits = [&self.re, &self.im, &other.re, &other.im]
+            .into_iter()
+            .filter_map(|part| Decimal::parse(part).ok())
+            .map(|decimal| (-decimal.exponent).max(0) as u64)
+            .max()
+            .unwrap_or_default()
+            + 17;
+        let bits = (digits as f64 * std::f64::consts::LOG2_10).ceil() as u64 + GUARD_BITS;
+
+        let t = Decimal::parse(&format!("{:e}", t))
+            .map(|decimal| decimal.to_fixed(bits))
+            .unwrap_or_default();
+        let ((from_re, from_im), (to_re, to_im)) = (self.to_fixed(bits), other.to_fixed(bits));
+        let lerp = |from: Fixed, to: Fixed| {
+            let step = Fixed(to.0 - &from.0).mul(&t, bits);
+            Fixed(from.0 + step.0).to_decimal(bits, digits)
+        };
+        Self {
+            re: lerp(from_re, to_re),
+            im: lerp(from_im, to_im),
+        }
+    }
+}
+
+/// A decimal number as an integer mantissa and a power of ten.
+struct Decimal {
+    mantissa: BigInt,
+    exponent: i64,
+}
+
+impl Decimal {
+    // ---
+
+    fn parse(text: &str) -> Result<Self> {
+        // ---
+        let lower = text.to_ascii_lowercase();
+        let (number, exponent) = match lower.split_once('e') {
+            Some((number, exponent)) => (number, exponent.parse::<i64>()?),
+            None => (lower.as_str(), 0),
+        };
+        anyhow::ensure!(
+            exponent.abs() <= MAX_EXPONENT,
+            "expected an exponent within ±{}",
+            MAX_EXPONENT
+        );
+        let (negative, number) = match number.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, number.strip_prefix('+').unwrap_or(number)),
+        };
+        let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+        let digits = format!("{whole}{fraction}");
+        anyhow::ensure!(
+            !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()),
+            "expected a decimal number"
+        );
+
+        let mantissa: BigInt = digits.parse()?;
+        Ok(Self {
+            mantissa: if negative { -mantissa } else { mantissa },
+            exponent: exponent - fraction.len() as i64,
+        })
+    }
+
+    fn to_fixed(&self, bits: u64) -> Fixed {
+        // ---
+        let scaled = self.mantissa.clone() << bits;
+        let power = BigInt::from(10).pow(self.exponent.unsigned_abs() as u32);
+        Fixed(if self.exponent >= 0 {
+            scaled * power
+        } else {
+            scaled / power
+        })
+    }
+}
+
+/// A fixed-point number: the integer value divided by 2^bits, with the
+/// number of fraction bits passed alongside.
+#[derive(Debug, Clone, Default)]
+struct Fixed(BigInt);
+
+impl Fixed {
+    // ---
+
+    fn mul(&self, other: &Fixed, bits: u64) -> Fixed {
+        Fixed((&self.0 * &other.0) >> bits)
+    }
+
+    fn to_f64(&self, bits: u64) -> f64 {
+        // ---
+        // Keep only the 64 most significant bits so the integer always fits
+        // in an f64, then scale by the power of two that was dropped.
+        let drop = self.0.bits().saturating_sub(64);
+        let kept = (&self.0 >> drop).to_f64().unwrap_or_default();
+        kept * 2f64.powi(drop as i32 - bits as i32)
+    }
+
+    /// Format with `digits` decimal places, rounded to nearest, dropping
+    /// trailing zeros.
+    fn to_decimal(&self, bits: u64, digits: u64) -> String {
+        // ---
+        let power = BigInt::from(10).pow(digits as u32);
+        let half = BigInt::from(1) << bits.saturating_sub(1);
+        let scaled = ((self.0.magnitude() * power.magnitude()) + half.magnitude()) >> bits;
+        let text = format!("{:0>width$}", scaled, width = digits as usize + 1);
+        let (whole, fraction) = text.split_at(text.len() - digits as usize);
+        let fraction = fraction.trim_end_matches('0');
+        let sign = if self.0.sign() == Sign::Minus && scaled.bits() > 0 {
+            "-"
+        } else {
+            ""
+        };
+        match fraction {
+            "" => format!("{sign}{whole}"),
+            _ => format!("{sign}{whole}.{fraction}"),
+        }
+    }
+}
+
+/// Fraction bits needed to resolve pixels at `zoom` in an image whose
+/// shorter side has `size` pixels.
+pub(crate) fn precision_bits(zoom: f64, size: u32) -> u64 {
+    // ---
+    let pixels_per_unit = zoom * size.max(1) as f64 / 3.0;
+    pixels_per_unit.log2().ceil().max(0.0) as u64 + GUARD_BITS
+}
+
+/// Iterate the reference orbit of `center` at high precision, returning
+/// the orbit points from Z₀ = 0 until escape or the iteration limit.
+fn reference_orbit(
+    center: &BigComplex,
+    bits: u64,
+    max_iterations: u32,
+    bailout_sqr: f64,
+) -> Vec<Complex64> {
+    // ---
+    let (cr, ci) = center.to_fixed(bits);
+    let (mut zr, mut zi) = (Fixed::default(), Fixed::default());
+    let mut orbit = vec![Complex64::new(0.0, 0.0)];
+
+    for _ in 0..max_iterations {
+        let zr2 = zr.mul(&zr, bits);
+        let zi2 = zi.mul(&zi, bits);
+        let two_zr_zi = Fixed(zr.mul(&zi, bits).0 << 1u32);
+        zr = Fixed(zr2.0 - zi2.0 + &cr.0);
+        zi = Fixed(two_zr_zi.0 + &ci.0);
+
+        let z = Complex64::new(zr.to_f64(bits), zi.to_f64(bits));
+        orbit.push(z);
+        if z.norm_sqr() > bailout_sqr {
+            break;
+        }
+    }
+    orbit
+}
+
+/// Cubic series dz ≈ a·u + b·u² + c·u³ in the pixel offset u = dc / scale.
+#[derive(Debug, Clone, Copy)]
+struct Series {
+    a: Complex64,
+    b: Complex64,
+    c: Complex64,
+}
+
+impl Series {
+    // ---
+
+    fn eval(&self, u: Complex64) -> Complex64 {
+        u * (self.a + u * (self.b + u * self.c))
+    }
+
+    /// Find how many iterations the series can skip for offsets up to
+    /// `scale`, by stepping the coefficients alongside exact perturbation
+    /// of `probes` until the two disagree.
+    fn fit(orbit: &[Complex64], scale: f64, probes: &[Complex64]) -> (Series, usize) {
+        // ---
+        let zero = Complex64::new(0.0, 0.0);
+        let mut series = Series {
+            a: zero,
+            b: zero,
+            c: zero,
+        };
+        let mut exact = vec![zero; probes.len()];
+        let mut skipped = 0;
+
+        // Stop one short of the last point so that perturbation always has
+        // a step of the reference left, even when it escapes early
+        for (n, &z) in orbit.iter().enumerate().take(orbit.len().saturating_sub(2)) {
+            let next = Series {
+                a: 2.0 * z * series.a + scale,
+                b: 2.0 * z * series.b + series.a * series.a,
+                c: 2.0 * z * series.c + 2.0 * series.a * series.b,
+            };
+            for (dz, &u) in exact.iter_mut().zip(probes) {
+                *dz = 2.0 * z * *dz + *dz * *dz + u * scale;
+            }
+
+            let accurate = exact.iter().zip(probes).all(|(dz, &u)| {
+                let error = (next.eval(u) - dz).norm();
+                error <= SERIES_TOLERANCE * dz.norm() && (orbit[n + 1] + dz).norm() > dz.norm()
+            });
+            if !accurate || !next.a.is_finite() || !next.c.is_finite() {
+                break;
+            }
+            series = next;
+            skipped = n + 1;
+        }
+        (series, skipped)
+    }
+}
+
+/// Render the escape-time samples of a deep-zoom Mandelbrot image around
+/// the exact `center`, calling `row_done` after each row.
+pub(crate) fn samples(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    center: &BigComplex,
+    row_done: impl Fn() + Sync,
+) -> Vec<Sample> {
+    // ---
+    use rayon::prelude::*;
+
+    let bailout_sqr = params.coloring.bailout_sqr();
+    let bits = precision_bits(params.zoom, width.min(height));
+    let orbit = reference_orbit(center, bits, params.max_iterations, bailout_sqr);
+
+    // Offsets are measured from the image center in plane units
+    let origin = Complex64::new(0.0, 0.0);
+    let offset = |x: u32, y: u32| {
+        let shifted = FractalParams {
+            center: origin,
+            ..params.clone()
+        };
+        shifted.pixel_to_point(x, y, width, height)
+    };
+
+    let (w, h) = (width.saturating_sub(1), height.saturating_sub(1));
+    let corners = [offset(0, 0), offset(w, 0), offset(0, h), offset(w, h)];
+    let scale = corners
+        .iter()
+        .map(|c| c.norm())
+        .fold(f64::MIN_POSITIVE, f64::max);
+    let probes: Vec<Complex64> = corners
+        .iter()
+        .chain(&[
+            offset(w / 2, 0),
+            offset(0, h / 2),
+            offset(w, h / 2),
+            offset(w / 2, h),
+        ])
+        .map(|c| c / scale)
+        .collect();
+    let (series, skipped) = Series::fit(&orbit, scale, &probes);
+
+    let mut samples = vec![Sample::default(); width as usize * height as usize];
+    samples
+        .par_chunks_mut(width.max(1) as usize)
+        .enumerate()
+        .for_each(|(y, row)| {
+            for (x, sample) in row.iter_mut().enumerate() {
+                let dc = offset(x as u32, y as u32);
+                *sample = perturb(params, &orbit, &series, skipped, dc, scale, bailout_sqr);
+            }
+            row_done();
+        });
+    samples
+}
+
+/// Iterate one pixel at offset `dc` from the reference.
+fn perturb(
+    params: &FractalParams,
+    orbit: &[Complex64],
+    series: &Series,
+    skipped: usize,
+    dc: Complex64,
+    scale: f64,
+    bailout_sqr: f64,
+) -> Sample {
+    // ---
+    let track_trap = params.coloring.mode == ColorMode::OrbitTrap;
+    let trap = params.coloring.trap;
+
+    let mut dz = series.eval(dc / scale);
+    let mut m = skipped;
+    let mut iterations = skipped as u32;
+    let mut z = orbit[m] + dz;
+    let mut trap_distance = f64::INFINITY;
+
+    while iterations < params.max_iterations && z.norm_sqr() <= bailout_sqr {
+        // Rebase when the pixel's orbit gets closer to zero than its
+        // difference from the reference, or when the reference ends; the
+        // reference starts at zero, so the full value becomes the new
+        // difference.
+        if m + 1 >= orbit.len() || z.norm_sqr() < dz.norm_sqr() {
+            dz = z;
+            m = 0;
+        }
+
+        dz = 2.0 * orbit[m] * dz + dz * dz + dc;
+        m += 1;
+        iterations += 1;
+        z = orbit[m] + dz;
+
+        if track_trap {
+            trap_distance = trap_distance.min((z - trap).norm());
+        }
+    }
+
+    Sample::from_orbit(iterations, z, bailout_sqr, trap_distance)
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_big_complex_parsing() -> Result<()> {
+        // ---
+
+        let center: BigComplex = "-1.25e-1, 0.000000000000000000000000000000000000001".parse()?;
+        let approx = center.to_complex64();
+        ensure!(approx.re == -0.125 && (approx.im - 1e-39).abs() < 1e-52);
+        ensure!(center.to_string() == "-1.25e-1,0.000000000000000000000000000000000000001");
+
+        ensure!(
+            "1.5".parse::<BigComplex>().is_err(),
+            "Missing IM should fail"
+        );
+        ensure!(
+            "1.5,x".parse::<BigComplex>().is_err(),
+            "Bad digits should fail"
+        );
+        ensure!(
+            "1..5,0".parse::<BigComplex>().is_err(),
+            "Two points should fail"
+        );
+        ensure!(
+            "1e-99999999,0".parse::<BigComplex>().is_err(),
+            "Exponents beyond any working precision should fail"
+        );
+        ensure!("1e-400,0".parse::<BigComplex>().is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_fixed_point_keeps_digits() -> Result<()> {
+        // ---
+
+        // 1 + 1e-60 survives at 256 bits; its difference from 1 is exact
+        let bits = 256;
+        let one = Decimal::parse("1")?.to_fixed(bits);
+        let near =
+            Decimal::parse("1.000000000000000000000000000000000000000000000000000000000001")?
+                .to_fixed(bits);
+        let difference = Fixed(near.0 - one.0).to_f64(bits);
+        ensure!(
+            (difference - 1e-60).abs() < 1e-70,
+            "Expected 1e-60, got {}",
+            difference
+        );
+
+        let product = Decimal::parse("-1.5")?
+            .to_fixed(bits)
+            .mul(&Decimal::parse("2")?.to_fixed(bits), bits);
+        ensure!(product.to_f64(bits) == -3.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_big_complex_lerp_keeps_digits() -> Result<()> {
+        // ---
+
+        let from: BigComplex = "-1,0.5".parse()?;
+        let to: BigComplex = "-1.00000000000000000000000000000000000000002,-0.5".parse()?;
+        let middle = from.lerp(&to, 0.5);
+        ensure!(
+            middle.to_string() == "-1.00000000000000000000000000000000000000001,0",
+            "Unexpected midpoint {}",
+            middle
+        );
+        ensure!(from.lerp(&to, 0.0) == from, "t = 0 should give the start");
+        ensure!(from.lerp(&to, 1.0) == to, "t = 1 should give the end");
+        Ok(())
+    }
+
+    #[test]
+    fn test_series_skips_shared_iterations() -> Result<()> {
+        // ---
+
+        // Deep in the main cardioid every pixel follows the reference closely
+        let center: BigComplex = "-0.1,0.1".parse()?;
+        let orbit = reference_orbit(&center, 128, 200, 4.0);
+        let probes = [
+            Complex64::new(1.0, 0.0),
+            Complex64::new(0.0, 1.0),
+            Complex64::new(-0.7, -0.7),
+        ];
+        let (series, skipped) = Series::fit(&orbit, 1e-12, &probes);
+        ensure!(
+            skipped > 10,
+            "Series should skip iterations, got {}",
+            skipped
+        );
+        ensure!(series.a.is_finite());
+        Ok(())
+    }
+
+    #[test]
+    fn test_series_leaves_a_step_of_an_escaping_orbit() -> Result<()> {
+        // ---
+
+        // Left of the set the reference escapes at its first step
+        let center: BigComplex = "-2.1,0".parse()?;
+        let orbit = reference_orbit(&center, 128, 200, 4.0);
+        ensure!(orbit.len() == 2, "Orbit {:?}", orbit);
+        let probes = [Complex64::new(1.0, 0.0), Complex64::new(0.0, 1.0)];
+        let (_, skipped) = Series::fit(&orbit, 1e-12, &probes);
+        ensure!(skipped + 2 <= orbit.len(), "Skipped {}", skipped);
+
+        for zoom in [10.0, 1e4] {
+            let params = FractalParams {
+                zoom,
+                ..FractalParams::default()
+            };
+            let samples = samples(60, 40, &params, &center, || {});
+            ensure!(samples.len() == 60 * 40, "Renders without panicking");
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,361 @@
+//! Escape-time fractal rendering.
+
+mod animation;
+mod coloring;
+mod deep;
+mod palette;
+
+pub use animation::{Animation, AnimationFormat, Keyframe};
+pub use coloring::{ColorMode, Coloring, Sample};
+pub use deep::BigComplex;
+pub use palette::Palette;
+
+use crate::check_size;
+use anyhow::Result;
+use image::RgbImage;
+use num_complex::Complex64;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// Orbits closer than this to an earlier point are taken as periodic.
+const PERIODICITY_EPSILON_SQR: f64 = 1e-28;
+
+/// Zoom beyond which `f64` can no longer tell neighbouring pixels apart.
+pub const DEEP_ZOOM_THRESHOLD: f64 = 1e13;
+
+/// Which escape-time fractal to render.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum FractalKind {
+    /// iterate z = z² + c from z = 0 with c taken from the pixel
+    Mandelbrot,
+    /// iterate z = z² + c from the pixel with a fixed constant c
+    Julia,
+}
+
+/// Parameters of a fractal render.
+///
+/// The viewport is centered on `center`; at `zoom` 1 the shorter image
+/// side spans 3 units of the complex plane and every doubling of `zoom`
+/// halves that. Pixels are square, so wide or tall outputs show more of
+/// the plane instead of stretching it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FractalParams {
+    pub kind: FractalKind,
+    /// Constant `c` of the Julia set, unused for Mandelbrot.
+    pub julia: Complex64,
+    pub center: Complex64,
+    pub zoom: f64,
+    pub max_iterations: u32,
+    pub coloring: Coloring,
+    /// Exact center for deep zooms. When set, a Mandelbrot render uses
+    /// perturbation around this point instead of `center`, which then only
+    /// serves as its `f64` approximation. Ignored for Julia sets.
+    pub deep_center: Option<BigComplex>,
+}
+
+impl Default for FractalParams {
+    // ---
+    fn default() -> Self {
+        Self {
+            kind: FractalKind::Julia,
+            julia: Complex64::new(-0.4, 0.6),
+            center: Complex64::new(0.0, 0.0),
+            zoom: 1.0,
+            max_iterations: 255,
+            coloring: Coloring::default(),
+            deep_center: None,
+        }
+    }
+}
+
+impl FractalParams {
+    // ---
+
+    /// Parameters for the whole Mandelbrot set.
+    pub fn mandelbrot() -> Self {
+        Self {
+            kind: FractalKind::Mandelbrot,
+            center: Complex64::new(-0.75, 0.0),
+            ..Self::default()
+        }
+    }
+
+    /// The point of the complex plane at the center of pixel (`x`, `y`) in
+    /// a `width` x `height` image, with the imaginary axis pointing up.
+    pub fn pixel_to_point(&self, x: u32, y: u32, width: u32, height: u32) -> Complex64 {
+        // ---
+        let scale = 3.0 / (self.zoom * width.min(height).max(1) as f64);
+        let dx = (x as f64 + 0.5 - width as f64 / 2.0) * scale;
+        let dy = (y as f64 + 0.5 - height as f64 / 2.0) * scale;
+        self.center + Complex64::new(dx, -dy)
+    }
+
+    /// Number of iterations before the orbit of `point` escapes, or
+    /// `max_iterations` if it never does.
+    pub fn escape_time(&self, point: Complex64) -> u32 {
+        // ---
+        self.sample(point).iterations
+    }
+
+    /// Iterate the orbit of `point`, recording what the coloring needs.
+    ///
+    /// Points known to be inside the Mandelbrot set (main cardioid and
+    /// period-2 bulb) are rejected without iterating, and orbits that
+    /// return to an earlier point are stopped early since they can never
+    /// escape. Both shortcuts are skipped for orbit-trap coloring, which
+    /// needs the whole orbit.
+    pub fn sample(&self, point: Complex64) -> Sample {
+        // ---
+        let track_trap = self.coloring.mode == ColorMode::OrbitTrap;
+        let interior = Sample {
+            iterations: self.max_iterations,
+            escaped: false,
+            smooth: self.max_iterations as f64,
+            trap_distance: f64::INFINITY,
+        };
+
+        let (mut z, c) = match self.kind {
+            FractalKind::Mandelbrot => {
+                if !track_trap && in_main_bulbs(point) {
+                    return interior;
+                }
+                (Complex64::new(0.0, 0.0), point)
+            }
+            FractalKind::Julia => (point, self.julia),
+        };
+        let bailout_sqr = self.coloring.bailout_sqr();
+        let trap = self.coloring.trap;
+
+        // Brent-style cycle detection: compare against a saved point and
+        // save a new one after every doubling of the check interval.
+        let mut saved = z;
+        let mut check_interval = 8;
+        let mut since_saved = 0;
+
+        let mut trap_distance = f64::INFINITY;
+        let mut iterations = 0;
+        while iterations < self.max_iterations && z.norm_sqr() <= bailout_sqr {
+            z = z * z + c;
+            iterations += 1;
+
+            if track_trap {
+                trap_distance = trap_distance.min((z - trap).norm());
+                continue;
+            }
+            if (z - saved).norm_sqr() < PERIODICITY_EPSILON_SQR {
+                return interior;
+            }
+            since_saved += 1;
+            if since_saved == check_interval {
+                saved = z;
+                since_saved = 0;
+                check_interval *= 2;
+            }
+        }
+
+        Sample::from_orbit(iterations, z, bailout_sqr, trap_distance)
+    }
+
+    /// Whether the zoom is too deep for plain `f64` rendering, so that
+    /// [`deep_center`](Self::deep_center) should be set.
+    pub fn needs_deep_zoom(&self) -> bool {
+        self.zoom > DEEP_ZOOM_THRESHOLD
+    }
+}
+
+/// Whether `c` lies in the Mandelbrot set's main cardioid or the period-2
+/// bulb to its left, where every point is inside the set.
+fn in_main_bulbs(c: Complex64) -> bool {
+    // ---
+    let x = c.re - 0.25;
+    let y_sqr = c.im * c.im;
+    let q = x * x + y_sqr;
+    let in_cardioid = q * (q + x) <= 0.25 * y_sqr;
+    let in_bulb = (c.re + 1.0) * (c.re + 1.0) + y_sqr <= 1.0 / 16.0;
+    in_cardioid || in_bulb
+}
+
+/// Render a `width` x `height` fractal described by `params`.
+///
+/// With the default classic coloring the escape-time count is drawn in the
+/// green channel over a red/blue gradient background; the other
+/// [`ColorMode`]s map the iteration results onto `params.coloring.palette`.
+/// Sizes outside the limits of [`check_size`] are an error.
+// This code was adapted from https://github.com/PistonDevelopers/image
+pub fn fractal(width: u32, height: u32, params: &FractalParams) -> Result<RgbImage> {
+    // ---
+    fractal_with_progress(width, height, params, |_, _| {})
+}
+
+/// Like [`fractal`], calling `progress(rows_done, total_rows)` as rows
+/// finish.
+///
+/// Rows are rendered in parallel on the current rayon thread pool. Each
+/// pixel is computed independently, so the result is identical for any
+/// number of threads.
+pub fn fractal_with_progress(
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    progress: impl Fn(u32, u32) + Sync,
+) -> Result<RgbImage> {
+    // ---
+    check_size((width, height))?;
+    let rows_done = AtomicU32::new(0);
+    let row_done = || progress(rows_done.fetch_add(1, Ordering::Relaxed) + 1, height);
+
+    let samples = match (&params.deep_center, params.kind) {
+        (Some(center), FractalKind::Mandelbrot) => {
+            deep::samples(width, height, params, center, row_done)
+        }
+        _ => {
+            let mut samples = vec![Sample::default(); width as usize * height as usize];
+            samples
+                .par_chunks_mut(width.max(1) as usize)
+                .enumerate()
+                .for_each(|(y, row)| {
+                    for (x, sample) in row.iter_mut().enumerate() {
+                        let point = params.pixel_to_point(x as u32, y as u32, width, height);
+                        *sample = params.sample(point);
+                    }
+                    row_done();
+                });
+            samples
+        }
+    };
+
+    Ok(params
+        .coloring
+        .paint(&samples, width, height, params.max_iterations))
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_viewport_keeps_square_pixels() -> Result<()> {
+        // ---
+
+        let params = FractalParams::default();
+
+        // A 2:1 output spans twice as much of the real axis as the imaginary
+        let left = params.pixel_to_point(0, 50, 200, 100);
+        let right = params.pixel_to_point(199, 50, 200, 100);
+        let top = params.pixel_to_point(100, 0, 200, 100);
+        let bottom = params.pixel_to_point(100, 99, 200, 100);
+        let width = right.re - left.re;
+        let height = top.im - bottom.im;
+        ensure!(
+            (width / height - 2.0).abs() < 0.05,
+            "Viewport should keep the aspect ratio, got {} x {}",
+            width,
+            height
+        );
+        ensure!(top.im > 0.0, "Imaginary axis should point up");
+        Ok(())
+    }
+
+    #[test]
+    fn test_zoom_shrinks_viewport() -> Result<()> {
+        // ---
+
+        let params = FractalParams {
+            center: Complex64::new(1.0, -2.0),
+            zoom: 4.0,
+            ..FractalParams::default()
+        };
+        let corner = params.pixel_to_point(0, 0, 100, 100);
+        ensure!(
+            (corner.re - (1.0 - 0.375)).abs() < 0.01 && (corner.im - (-2.0 + 0.375)).abs() < 0.01,
+            "Zoom 4 should span 0.75 units around the center, got corner {}",
+            corner
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_smooth_count_is_continuous() -> Result<()> {
+        // ---
+
+        let params = FractalParams {
+            coloring: Coloring {
+                mode: ColorMode::Smooth,
+                ..Coloring::default()
+            },
+            ..FractalParams::mandelbrot()
+        };
+
+        // Neighbouring points outside the set have close smooth counts even
+        // when their integer counts differ
+        let a = params.sample(Complex64::new(-0.75, 0.2));
+        let b = params.sample(Complex64::new(-0.75, 0.2001));
+        ensure!(a.escaped && b.escaped, "Both points should escape");
+        ensure!(
+            (a.smooth - b.smooth).abs() < 0.1,
+            "Smooth counts should be close, got {} and {}",
+            a.smooth,
+            b.smooth
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_shortcuts_match_full_iteration() -> Result<()> {
+        // ---
+
+        let params = FractalParams {
+            max_iterations: 500,
+            ..FractalParams::mandelbrot()
+        };
+
+        // Plain escape-time loop without the cardioid, bulb and
+        // periodicity shortcuts
+        let reference = |c: Complex64| {
+            let mut z = Complex64::new(0.0, 0.0);
+            let mut iterations = 0;
+            while iterations < params.max_iterations && z.norm_sqr() <= 4.0 {
+                z = z * z + c;
+                iterations += 1;
+            }
+            iterations
+        };
+
+        for y in -20..=20 {
+            for x in -40..=10 {
+                let point = Complex64::new(x as f64 * 0.05, y as f64 * 0.05);
+                ensure!(
+                    params.escape_time(point) == reference(point),
+                    "Shortcuts changed the escape time at {}",
+                    point
+                );
+            }
+        }
+        ensure!(in_main_bulbs(Complex64::new(0.0, 0.0)));
+        ensure!(in_main_bulbs(Complex64::new(-1.0, 0.1)));
+        ensure!(!in_main_bulbs(Complex64::new(0.3, 0.5)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape_time_mandelbrot() -> Result<()> {
+        // ---
+
+        let params = FractalParams {
+            max_iterations: 100,
+            ..FractalParams::mandelbrot()
+        };
+        ensure!(
+            params.escape_time(Complex64::new(0.0, 0.0)) == 100,
+            "Origin is inside the Mandelbrot set"
+        );
+        ensure!(
+            params.escape_time(Complex64::new(2.0, 2.0)) == 1,
+            "Far points escape after one iteration"
+        );
+        Ok(())
+    }
+}
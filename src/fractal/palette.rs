@@ -0,0 +1,472 @@
+//! Color palettes for fractal rendering.
+//!
+//! A palette is a table of evenly spaced colors sampled with linear
+//! interpolation. It can be one of the built-in presets or loaded from a
+//! GIMP gradient (`.ggr`) or Fractint color map (`.map`) file.
+
+use crate::color::{hsv_to_rgb, rgb_to_hsv};
+use anyhow::{Context, Result};
+use image::Rgb;
+use std::fs;
+use std::path::Path;
+
+/// Number of entries used when a palette is built from a continuous gradient.
+const TABLE_SIZE: usize = 1024;
+
+/// A palette color at a position in 0-1.
+type Stop = (f32, [u8; 3]);
+
+/// Built-in palettes as (position, color) stops.
+const PRESETS: &[(&str, &[Stop])] = &[
+    (
+        "ultra",
+        &[
+            (0.0, [0, 7, 100]),
+            (0.16, [32, 107, 203]),
+            (0.42, [237, 255, 255]),
+            (0.6425, [255, 170, 0]),
+            (0.8575, [0, 2, 0]),
+            (1.0, [0, 7, 100]),
+        ],
+    ),
+    (
+        "fire",
+        &[
+            (0.0, [0, 0, 0]),
+            (0.3, [160, 20, 0]),
+            (0.6, [255, 140, 0]),
+            (0.85, [255, 240, 120]),
+            (1.0, [255, 255, 255]),
+        ],
+    ),
+    (
+        "ocean",
+        &[
+            (0.0, [0, 10, 30]),
+            (0.35, [0, 80, 140]),
+            (0.7, [60, 190, 210]),
+            (1.0, [230, 255, 250]),
+        ],
+    ),
+    (
+        "electric",
+        &[
+            (0.0, [10, 0, 40]),
+            (0.25, [120, 0, 200]),
+            (0.5, [255, 0, 150]),
+            (0.75, [0, 220, 255]),
+            (1.0, [10, 0, 40]),
+        ],
+    ),
+    (
+        "rainbow",
+        &[
+            (0.0, [255, 0, 0]),
+            (1.0 / 6.0, [255, 255, 0]),
+            (2.0 / 6.0, [0, 255, 0]),
+            (3.0 / 6.0, [0, 255, 255]),
+            (4.0 / 6.0, [0, 0, 255]),
+            (5.0 / 6.0, [255, 0, 255]),
+            (1.0, [255, 0, 0]),
+        ],
+    ),
+    ("grayscale", &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])]),
+];
+
+/// A color table sampled by a position in 0-1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Palette {
+    /// Preset name or file the palette came from.
+    pub name: String,
+    colors: Vec<[f32; 3]>,
+}
+
+impl Default for Palette {
+    // ---
+    fn default() -> Self {
+        Self::preset("ultra").expect("built-in palette")
+    }
+}
+
+impl Palette {
+    // ---
+
+    /// Names of the built-in palettes.
+    pub fn preset_names() -> impl Iterator<Item = &'static str> {
+        PRESETS.iter().map(|(name, _)| *name)
+    }
+
+    /// A built-in palette by name.
+    pub fn preset(name: &str) -> Result<Self> {
+        // ---
+        let (_, stops) = PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .context(format!(
+                "Unknown palette {}, use a .ggr or .map file or one of: {}",
+                name,
+                Self::preset_names().collect::<Vec<_>>().join(", ")
+            ))?;
+
+        let colors = (0..TABLE_SIZE)
+            .map(|i| {
+                let t = i as f32 / (TABLE_SIZE - 1) as f32;
+                let next = stops.partition_point(|(position, _)| *position <= t);
+                let (p0, c0) = stops[next.saturating_sub(1)];
+                let (p1, c1) = stops[next.min(stops.len() - 1)];
+                let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 0.0 };
+                std::array::from_fn(|k| c0[k] as f32 + (c1[k] as f32 - c0[k] as f32) * f)
+            })
+            .collect();
+
+        Ok(Self {
+            name: name.to_ascii_lowercase(),
+            colors,
+        })
+    }
+
+    /// A preset name, or a path to a `.ggr` or `.map` file.
+    pub fn load(spec: &str) -> Result<Self> {
+        // ---
+        let path = Path::new(spec);
+        let extension = path
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_ascii_lowercase());
+
+        let mut palette = match extension.as_deref() {
+            Some("ggr") => Self::from_ggr(&read(path)?),
+            Some("map") => Self::from_map(&read(path)?),
+            _ => return Self::preset(spec),
+        }
+        .context(format!("Invalid palette file {}", spec))?;
+
+        palette.name = spec.to_string();
+        Ok(palette)
+    }
+
+    /// Parse a Fractint color map: one `red green blue` line per color,
+    /// anything after the third number is a comment.
+    pub fn from_map(text: &str) -> Result<Self> {
+        // ---
+        let mut colors = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().take(3).collect();
+            if fields.is_empty() || fields[0].starts_with(';') {
+                continue;
+            }
+            anyhow::ensure!(
+                fields.len() == 3,
+                "line {}: expected red, green and blue values",
+                number + 1
+            );
+            let mut color = [0.0; 3];
+            for (channel, field) in color.iter_mut().zip(&fields) {
+                let value: u8 = field
+                    .parse()
+                    .map_err(|_| anyhow::anyhow!("line {}: `{}` isn't 0-255", number + 1, field))?;
+                *channel = value as f32;
+            }
+            colors.push(color);
+        }
+        anyhow::ensure!(!colors.is_empty(), "no colors found");
+
+        Ok(Self {
+            name: String::from("map"),
+            colors,
+        })
+    }
+
+    /// Parse a GIMP gradient, honoring each segment's blending function and
+    /// RGB or HSV color interpolation. Alpha is ignored.
+    pub fn from_ggr(text: &str) -> Result<Self> {
+        // ---
+        let mut lines = text.lines().map(str::trim).filter(|line| !line.is_empty());
+        anyhow::ensure!(
+            lines.next() == Some("GIMP Gradient"),
+            "missing `GIMP Gradient` header"
+        );
+
+        let mut line = lines.next().context("missing segment count")?;
+        if line.starts_with("Name:") {
+            line = lines.next().context("missing segment count")?;
+        }
+        let count: usize = line
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid segment count `{}`", line))?;
+
+        let segments = lines
+            .take(count)
+            .map(Segment::parse)
+            .collect::<Result<Vec<_>>>()?;
+        anyhow::ensure!(
+            count > 0 && segments.len() == count,
+            "expected {} segments, found {}",
+            count,
+            segments.len()
+        );
+
+        let colors = (0..TABLE_SIZE)
+            .map(|i| {
+                let t = i as f64 / (TABLE_SIZE - 1) as f64;
+                let segment = segments
+                    .iter()
+                    .find(|segment| t <= segment.right)
+                    .unwrap_or(&segments[segments.len() - 1]);
+                segment
+                    .color_at(t)
+                    .map(|c| (c * 255.0).clamp(0.0, 255.0) as f32)
+            })
+            .collect();
+
+        Ok(Self {
+            name: String::from("ggr"),
+            colors,
+        })
+    }
+
+    /// Color at `t`, clamped to the ends of the palette.
+    pub fn sample(&self, t: f64) -> Rgb<u8> {
+        // ---
+        let last = self.colors.len() - 1;
+        let position = t.clamp(0.0, 1.0) * last as f64;
+        let index = (position.floor() as usize).min(last);
+        self.interpolate(index, (index + 1).min(last), position - index as f64)
+    }
+
+    /// Color at `t`, repeating the palette every unit so that colors wrap
+    /// around from the last entry to the first.
+    pub fn sample_cyclic(&self, t: f64) -> Rgb<u8> {
+        // ---
+        let len = self.colors.len();
+        let position = t.rem_euclid(1.0) * len as f64;
+        let index = (position.floor() as usize).min(len - 1);
+        self.interpolate(index, (index + 1) % len, position - index as f64)
+    }
+
+    fn interpolate(&self, from: usize, to: usize, f: f64) -> Rgb<u8> {
+        // ---
+        let (c0, c1) = (self.colors[from], self.colors[to]);
+        let f = f as f32;
+        Rgb(std::array::from_fn(|k| {
+            (c0[k] + (c1[k] - c0[k]) * f).round().clamp(0.0, 255.0) as u8
+        }))
+    }
+}
+
+fn read(path: &Path) -> Result<String> {
+    // ---
+    fs::read_to_string(path).context(format!("Failed to open {}", path.display()))
+}
+
+/// One segment of a GIMP gradient.
+#[derive(Debug)]
+struct Segment {
+    left: f64,
+    middle: f64,
+    right: f64,
+    left_color: [f64; 3],
+    right_color: [f64; 3],
+    blend: u32,
+    coloring: u32,
+}
+
+impl Segment {
+    // ---
+
+    fn parse(line: &str) -> Result<Self> {
+        // ---
+        let fields = line
+            .split_whitespace()
+            .map(|field| {
+                field
+                    .parse::<f64>()
+                    .map_err(|_| anyhow::anyhow!("invalid number `{}` in segment", field))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        anyhow::ensure!(
+            fields.len() >= 13,
+            "segment needs 13 values, found {}",
+            fields.len()
+        );
+
+        Ok(Self {
+            left: fields[0],
+            middle: fields[1],
+            right: fields[2],
+            left_color: [fields[3], fields[4], fields[5]],
+            right_color: [fields[7], fields[8], fields[9]],
+            blend: fields[11] as u32,
+            coloring: fields[12] as u32,
+        })
+    }
+
+    fn color_at(&self, t: f64) -> [f64; 3] {
+        // ---
+        let length = self.right - self.left;
+        let (position, middle) = if length > 0.0 {
+            (
+                ((t - self.left) / length).clamp(0.0, 1.0),
+                (self.middle - self.left) / length,
+            )
+        } else {
+            (0.5, 0.5)
+        };
+
+        let linear = if position <= middle {
+            if middle > 0.0 {
+                0.5 * position / middle
+            } else {
+                0.0
+            }
+        } else if middle < 1.0 {
+            0.5 + 0.5 * (position - middle) / (1.0 - middle)
+        } else {
+            1.0
+        };
+
+        let f = match self.blend {
+            // curved
+            1 => position.powf(0.5f64.ln() / middle.max(1e-10).ln()),
+            // sine
+            2 => ((-std::f64::consts::FRAC_PI_2 + std::f64::consts::PI * linear).sin() + 1.0) / 2.0,
+            // sphere increasing
+            3 => (1.0 - (linear - 1.0).powi(2)).sqrt(),
+            // sphere decreasing
+            4 => 1.0 - (1.0 - linear * linear).sqrt(),
+            // step
+            5 => {
+                if position >= middle {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            // linear
+            _ => linear,
+        };
+
+        match self.coloring {
+            1 | 2 => {
+                let [h0, s0, v0] = rgb_to_hsv(self.left_color);
+                let [h1, s1, v1] = rgb_to_hsv(self.right_color);
+                // 1 turns counter-clockwise (increasing hue), 2 clockwise
+                let mut delta = h1 - h0;
+                if self.coloring == 1 && delta < 0.0 {
+                    delta += 1.0;
+                } else if self.coloring == 2 && delta > 0.0 {
+                    delta -= 1.0;
+                }
+                hsv_to_rgb([
+                    (h0 + delta * f).rem_euclid(1.0),
+                    s0 + (s1 - s0) * f,
+                    v0 + (v1 - v0) * f,
+                ])
+            }
+            _ => std::array::from_fn(|k| {
+                self.left_color[k] + (self.right_color[k] - self.left_color[k]) * f
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_presets_load() -> Result<()> {
+        // ---
+
+        for name in Palette::preset_names() {
+            let palette = Palette::preset(name)?;
+            ensure!(
+                palette.colors.len() == TABLE_SIZE,
+                "{} has a full table",
+                name
+            );
+        }
+        let gray = Palette::preset("grayscale")?;
+        ensure!(gray.sample(0.0) == Rgb([0, 0, 0]));
+        ensure!(gray.sample(1.0) == Rgb([255, 255, 255]));
+        ensure!(
+            Palette::preset("nope").is_err(),
+            "Unknown preset should fail"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sample_cyclic_wraps() -> Result<()> {
+        // ---
+
+        let gray = Palette::preset("grayscale")?;
+        ensure!(gray.sample_cyclic(0.25) == gray.sample_cyclic(1.25));
+        ensure!(gray.sample_cyclic(-0.75) == gray.sample_cyclic(0.25));
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_map() -> Result<()> {
+        // ---
+
+        let palette = Palette::from_map("0 0 0 black\n; comment\n255 0 0\n\n0 0 255 blue\n")?;
+        ensure!(palette.colors.len() == 3, "Three colors expected");
+        ensure!(palette.sample(0.5) == Rgb([255, 0, 0]));
+        ensure!(palette.sample(1.0) == Rgb([0, 0, 255]));
+        ensure!(
+            Palette::from_map("1 2\n").is_err(),
+            "Short line should fail"
+        );
+        ensure!(
+            Palette::from_map("1 2 300\n").is_err(),
+            "Out of range should fail"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_ggr() -> Result<()> {
+        // ---
+
+        let text = "GIMP Gradient\n\
+                    Name: Test\n\
+                    2\n\
+                    0.0 0.25 0.5 1 0 0 1 0 1 0 1 0 0\n\
+                    0.5 0.75 1.0 0 1 0 1 0 0 1 1 0 0\n";
+        let palette = Palette::from_ggr(text)?;
+        ensure!(palette.sample(0.0) == Rgb([255, 0, 0]));
+        ensure!(palette.sample(0.5) == Rgb([0, 255, 0]));
+        ensure!(palette.sample(1.0) == Rgb([0, 0, 255]));
+
+        let quarter = palette.sample(0.25);
+        ensure!(
+            (120..=135).contains(&quarter.0[0]) && (120..=135).contains(&quarter.0[1]),
+            "Linear blend should be halfway at the midpoint, got {:?}",
+            quarter
+        );
+        ensure!(
+            Palette::from_ggr("GIMP Gradient\n2\n0 0.5 1 0 0 0 1 1 1 1 1 0 0\n").is_err(),
+            "Missing segment should fail"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_ggr_hsv_blending() -> Result<()> {
+        // ---
+
+        // Red to blue turning counter-clockwise passes through green
+        let text = "GIMP Gradient\n1\n0 0.5 1 1 0 0 1 0 0 1 1 0 1\n";
+        let palette = Palette::from_ggr(text)?;
+        let middle = palette.sample(0.5).0;
+        ensure!(
+            middle[1] > 200 && middle[0] < 60 && middle[2] < 60,
+            "HSV blend should pass through green, got {:?}",
+            middle
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,300 @@
+//! Solid color and gradient image generation.
+
+use crate::check_size;
+use crate::color::parse_color;
+use anyhow::{Context, Result};
+use image::{Rgba, RgbaImage};
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+/// What to paint a generated image with.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fill {
+    Solid(Rgba<u8>),
+    Gradient(Gradient),
+}
+
+impl From<Rgba<u8>> for Fill {
+    // ---
+    fn from(color: Rgba<u8>) -> Self {
+        Self::Solid(color)
+    }
+}
+
+impl From<Gradient> for Fill {
+    // ---
+    fn from(gradient: Gradient) -> Self {
+        Self::Gradient(gradient)
+    }
+}
+
+/// Shape of a gradient, following the CSS gradient functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum GradientKind {
+    /// colors change along a line at `angle`
+    Linear,
+    /// colors change with the distance from `center`
+    Radial,
+    /// colors change with the angle around `center`
+    Conic,
+}
+
+/// A color at a position along a gradient.
+///
+/// Parsed from `COLOR` or `COLOR@POSITION`, where the position is a
+/// percentage (`30%`) or a fraction (`0.3`). Stops without a position are
+/// spread evenly between their neighbours, as in CSS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub color: Rgba<u8>,
+    pub position: Option<f32>,
+}
+
+impl FromStr for ColorStop {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let Some((color, position)) = text.rsplit_once('@') else {
+            return Ok(Self {
+                color: parse_color(text)?,
+                position: None,
+            });
+        };
+
+        let position = position.trim();
+        let value = match position.strip_suffix('%') {
+            Some(percent) => percent.parse::<f32>().map(|p| p / 100.0),
+            None => position.parse::<f32>(),
+        }
+        .ok()
+        .filter(|value| value.is_finite())
+        .context(format!("Invalid color stop position {}", position))?;
+
+        Ok(Self {
+            color: parse_color(color)?,
+            position: Some(value),
+        })
+    }
+}
+
+impl fmt::Display for ColorStop {
+    // ---
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [r, g, b, a] = self.color.0;
+        write!(f, "#{r:02x}{g:02x}{b:02x}{a:02x}")?;
+        match self.position {
+            Some(position) => write!(f, "@{position}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A multi-stop gradient.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    pub kind: GradientKind,
+    pub stops: Vec<ColorStop>,
+    /// Direction of a linear gradient, or start of a conic one, in degrees
+    /// clockwise from the top (`0` runs bottom to top, `90` left to right).
+    pub angle: f32,
+    /// Center of radial and conic gradients as fractions of width and height.
+    pub center: (f32, f32),
+}
+
+impl Gradient {
+    // ---
+
+    /// A gradient of `kind` through `stops`, pointing down and centered.
+    pub fn new(kind: GradientKind, stops: Vec<ColorStop>) -> Self {
+        Self {
+            kind,
+            stops,
+            angle: 180.0,
+            center: (0.5, 0.5),
+        }
+    }
+
+    /// Resolve the stop positions, see [`resolve_stops`].
+    fn resolved_stops(&self) -> Result<Vec<(f32, [f32; 4])>> {
+        resolve_stops(&self.stops)
+    }
+
+    /// Gradient parameter in 0-1 for the pixel center (`px`, `py`).
+    fn parameter(&self, px: f32, py: f32, width: f32, height: f32) -> f32 {
+        // ---
+        let (cx, cy) = (self.center.0 * width, self.center.1 * height);
+        let angle = self.angle.to_radians();
+
+        match self.kind {
+            GradientKind::Linear => {
+                // Like CSS, the gradient line passes through the middle of
+                // the image and is just long enough to reach the corners.
+                let (dx, dy) = (angle.sin(), -angle.cos());
+                let length = (width * dx).abs() + (height * dy).abs();
+                ((px - width / 2.0) * dx + (py - height / 2.0) * dy) / length + 0.5
+            }
+            GradientKind::Radial => {
+                let radius = [(0.0, 0.0), (width, 0.0), (0.0, height), (width, height)]
+                    .iter()
+                    .map(|&(x, y)| (x - cx).hypot(y - cy))
+                    .fold(0.0, f32::max);
+                (px - cx).hypot(py - cy) / radius
+            }
+            GradientKind::Conic => {
+                let theta = (px - cx).atan2(cy - py);
+                (theta - angle).rem_euclid(2.0 * PI) / (2.0 * PI)
+            }
+        }
+    }
+}
+
+/// Resolve the positions of `stops`: missing ends default to 0 and 1,
+/// missing middle positions are spread evenly, and positions never
+/// decrease. Colors are returned as 0-255 floats.
+pub(crate) fn resolve_stops(stops: &[ColorStop]) -> Result<Vec<(f32, [f32; 4])>> {
+    // ---
+    anyhow::ensure!(
+        stops.len() >= 2,
+        "A gradient needs at least two color stops"
+    );
+
+    let mut positions: Vec<Option<f32>> = stops.iter().map(|s| s.position).collect();
+    let last = positions.len() - 1;
+    positions[0].get_or_insert(0.0);
+    positions[last].get_or_insert(1.0);
+
+    let mut max = f32::MIN;
+    for position in positions.iter_mut().flatten() {
+        max = max.max(*position);
+        *position = max;
+    }
+
+    let mut start = 0;
+    for i in 1..=last {
+        if let Some(end_pos) = positions[i] {
+            let start_pos = positions[start].unwrap_or(0.0);
+            let gap = (i - start) as f32;
+            for (j, position) in positions.iter_mut().enumerate().take(i).skip(start + 1) {
+                *position = Some(start_pos + (end_pos - start_pos) * (j - start) as f32 / gap);
+            }
+            start = i;
+        }
+    }
+
+    Ok(stops
+        .iter()
+        .zip(positions)
+        .map(|(stop, position)| (position.unwrap_or(0.0), stop.color.0.map(f32::from)))
+        .collect())
+}
+
+/// Interpolate the color at `t` between sorted stops, as 0-255 floats.
+pub(crate) fn interpolate(stops: &[(f32, [f32; 4])], t: f32) -> [f32; 4] {
+    // ---
+    let index = stops.partition_point(|(position, _)| *position <= t);
+    match index {
+        0 => stops[0].1,
+        i if i == stops.len() => stops[i - 1].1,
+        i => {
+            let (p0, c0) = stops[i - 1];
+            let (p1, c1) = stops[i];
+            let f = if p1 > p0 { (t - p0) / (p1 - p0) } else { 1.0 };
+            std::array::from_fn(|k| c0[k] + (c1[k] - c0[k]) * f)
+        }
+    }
+}
+
+/// The 8-bit color at `t` between sorted stops.
+fn color_at(stops: &[(f32, [f32; 4])], t: f32) -> Rgba<u8> {
+    // ---
+    Rgba(interpolate(stops, t).map(|c| c.round().clamp(0.0, 255.0) as u8))
+}
+
+/// Create a `width` x `height` image painted with `fill`, within the
+/// limits of [`check_size`].
+pub fn generate(width: u32, height: u32, fill: &Fill) -> Result<RgbaImage> {
+    // ---
+    check_size((width, height))?;
+    let gradient = match fill {
+        Fill::Solid(color) => return Ok(RgbaImage::from_pixel(width, height, *color)),
+        Fill::Gradient(gradient) => gradient,
+    };
+
+    let stops = gradient.resolved_stops()?;
+    let (w, h) = (width as f32, height as f32);
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let t = gradient.parameter(x as f32 + 0.5, y as f32 + 0.5, w, h);
+        color_at(&stops, t)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+
+    #[test]
+    fn test_color_stop_parsing() -> Result<()> {
+        // ---
+
+        let stop: ColorStop = "red".parse()?;
+        ensure!(stop.color == Rgba([255, 0, 0, 255]) && stop.position.is_none());
+
+        let stop: ColorStop = "rgb(0, 0, 255)@30%".parse()?;
+        ensure!(stop.color == Rgba([0, 0, 255, 255]));
+        ensure!(stop.position == Some(0.3));
+
+        let stop: ColorStop = "#00ff00@0.75".parse()?;
+        ensure!(stop.position == Some(0.75));
+
+        ensure!("red@far".parse::<ColorStop>().is_err(), "Bad position");
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_stop_positions_are_spread_evenly() -> Result<()> {
+        // ---
+
+        let stops = ["black", "gray", "white@0.8", "red"]
+            .iter()
+            .map(|s| s.parse())
+            .collect::<Result<Vec<ColorStop>>>()?;
+        let positions: Vec<f32> = Gradient::new(GradientKind::Linear, stops)
+            .resolved_stops()?
+            .iter()
+            .map(|(p, _)| *p)
+            .collect();
+        ensure!(
+            positions == [0.0, 0.4, 0.8, 1.0],
+            "Unexpected positions {:?}",
+            positions
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_gradient_needs_two_stops() -> Result<()> {
+        // ---
+
+        let gradient = Gradient::new(GradientKind::Radial, vec!["red".parse()?]);
+        ensure!(generate(4, 4, &gradient.into()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_rejects_huge_sizes() -> Result<()> {
+        // ---
+
+        let red = Fill::Solid(Rgba([255, 0, 0, 255]));
+        ensure!(generate(200_000, 200_000, &red).is_err());
+        ensure!(generate(40_000, 40_000, &red).is_err());
+        ensure!(generate(0, 4, &red).is_err());
+        ensure!(generate(4, 4, &red)?.dimensions() == (4, 4));
+        Ok(())
+    }
+}
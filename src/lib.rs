@@ -0,0 +1,48 @@
+//! Mirage image operations as a library.
+//!
+//! Every command of the `mirage` binary is available here as an
+//! [`Operation`] that takes a [`DynamicImage`](image::DynamicImage) and
+//! returns the transformed image, so callers can process images in memory
+//! without going through the command line. The image generators
+//! ([`fractal`] and [`generate`]) return an [`ImageBuffer`](image::ImageBuffer)
+//! that the caller can save or process further.
+//!
+//! ```
+//! use image::{DynamicImage, RgbImage};
+//! use mirage::{Blur, Crop, Grayscale, Operation, Pipeline};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let img = DynamicImage::ImageRgb8(RgbImage::new(64, 48));
+//! let pipeline = Pipeline::new()
+//!     .then(Crop { x: 0, y: 0, width: 32, height: 32 })
+//!     .then(Blur { sigma: 2.0 })
+//!     .then(Grayscale);
+//! let img = pipeline.apply(img)?;
+//! assert_eq!((img.width(), img.height()), (32, 32));
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod batch;
+mod color;
+mod fractal;
+mod generate;
+mod limits;
+pub mod metadata;
+pub mod ops;
+
+pub use color::parse_color;
+pub use fractal::{
+    fractal, fractal_with_progress, Animation, AnimationFormat, BigComplex, ColorMode, Coloring,
+    FractalKind, FractalParams, Keyframe, Palette,
+};
+pub use generate::{generate, ColorStop, Fill, Gradient, GradientKind};
+pub use limits::{check_size, MAX_PIXELS, MAX_SIDE};
+pub use ops::{
+    Adaptive, AdaptiveThreshold, Aspect, Blur, Brighten, Brightness, Canvas, Channels, ColorModel,
+    Colorize, Colors, Contrast, Crop, CropRegion, Curves, Desaturate, Dither, Dithering, Duotone,
+    Exposure, Filter, Fit, Flip, Flop, Gamma, Geometry, Gravity, Grayscale, Hue, Interpolation,
+    Invert, InvertChannels, InvertLightness, Kernel, KernelBlur, Length, Levels, Metric, Modulate,
+    Operation, Orientation, Pipeline, Posterize, Resize, Rotate, Sepia, Sharpen, Size, SmartCrop,
+    SpinBlur, Threshold, Tint, Transpose, Transverse, Trim, Unit, Weights, ZoomBlur,
+};
@@ -0,0 +1,34 @@
+//! Size limits shared by every command that creates an image, checked
+//! before the image is allocated so that a huge size fails with an error
+//! instead of aborting on a failed allocation.
+
+use anyhow::Result;
+
+/// Largest side of a created image, the limit of most image formats.
+pub const MAX_SIDE: u32 = 65_535;
+
+/// Most pixels of a created image, a gigapixel.
+pub const MAX_PIXELS: u64 = 1 << 30;
+
+/// Fail unless a `width` x `height` image has sides of 1 to [`MAX_SIDE`]
+/// pixels and at most [`MAX_PIXELS`] pixels in all.
+///
+/// ```
+/// assert!(mirage::check_size((1920, 1080)).is_ok());
+/// assert!(mirage::check_size((0, 1080)).is_err());
+/// assert!(mirage::check_size((60_000, 60_000)).is_err());
+/// ```
+pub fn check_size((width, height): (u32, u32)) -> Result<()> {
+    // ---
+    anyhow::ensure!(
+        (1..=MAX_SIDE).contains(&width)
+            && (1..=MAX_SIDE).contains(&height)
+            && width as u64 * height as u64 <= MAX_PIXELS,
+        "Invalid size:{}x{} must be 1-{} pixels a side and at most {} pixels in all",
+        width,
+        height,
+        MAX_SIDE,
+        MAX_PIXELS
+    );
+    Ok(())
+}
@@ -1,242 +1,1759 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Parser)]
 struct Args {
     // ---
     #[command(subcommand)]
     command: Command,
+
+    #[command(flatten)]
+    input: InputArgs,
+}
+
+/// Options for reading input images. Batch mode is used when an infile is
+/// a directory or a glob pattern; the outfile is then the output directory.
+#[derive(Debug, clap::Args)]
+struct InputArgs {
+    /// output file name for batch mode, using {stem}, {ext} and {op}
+    #[arg(long, global = true, value_name = "TEMPLATE")]
+    output_name: Option<String>,
+
+    /// descend into subdirectories in batch mode, mirroring the tree
+    #[arg(short, long, global = true)]
+    recursive: bool,
+
+    /// let batch outputs replace the input files they are made from
+    #[arg(long, global = true)]
+    force: bool,
+
+    /// turn photos upright according to their EXIF orientation (default)
+    #[arg(long, global = true, overrides_with = "no_auto_orient")]
+    auto_orient: bool,
+
+    /// keep the pixels as stored, ignoring the EXIF orientation
+    #[arg(long, global = true, overrides_with = "auto_orient")]
+    no_auto_orient: bool,
+}
+
+impl InputArgs {
+    // ---
+
+    /// Whether inputs are turned upright by their EXIF orientation.
+    fn auto_orient(&self) -> bool {
+        // ---
+        !self.no_auto_orient
+    }
+}
+
+/// Options of the fractal renderer, shared by `fractal` and `animate`.
+#[derive(Debug, clap::Args)]
+struct FractalArgs {
+    /// fractal to render
+    #[arg(long = "type", value_enum, default_value = "julia")]
+    kind: FractalKind,
+    /// Julia set constant as RE,IM
+    #[arg(long, value_parser = complex_valid, default_value = "-0.4,0.6", allow_hyphen_values = true)]
+    constant: Complex64,
+    /// point of the complex plane at the image center as RE,IM decimals,
+    /// with as many digits as a deep zoom needs
+    /// [default: 0,0 for julia, -0.75,0 for mandelbrot]
+    #[arg(long, allow_hyphen_values = true)]
+    center: Option<BigComplex>,
+    /// magnification, 1 shows 3 units across the shorter image side
+    #[arg(long, default_value_t = 1.0, value_parser = positive_valid)]
+    zoom: f64,
+    /// iteration limit before a point is considered inside the set
+    #[arg(long, default_value_t = 255, value_parser = clap::value_parser!(u32).range(1..))]
+    max_iterations: u32,
+    /// render a Mandelbrot zoom by arbitrary-precision perturbation,
+    /// automatic beyond zoom 1e13
+    #[arg(long)]
+    deep: bool,
+    /// how iteration counts are turned into colors
+    #[arg(long, value_enum, default_value = "classic")]
+    coloring: ColorMode,
+    /// built-in palette name or a GIMP .ggr / Fractint .map file
+    #[arg(long, default_value = "ultra")]
+    palette: String,
+    /// palette cycle length in iterations (smooth) or 1/64 plane units (orbit-trap)
+    #[arg(long, default_value_t = 64.0, value_parser = positive_valid)]
+    cycle: f64,
+    /// palette shift as a fraction of a cycle
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    offset: f64,
+    /// orbit-trap point as RE,IM
+    #[arg(long, value_parser = complex_valid, default_value = "0,0", allow_hyphen_values = true)]
+    trap: Complex64,
+    /// color of points inside the set
+    #[arg(long, value_parser = mirage::parse_color, default_value = "black")]
+    interior: Rgba<u8>,
+    /// worker threads for rendering [default: one per CPU core]
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+}
+
+impl FractalArgs {
+    // ---
+
+    /// The render parameters, switching to deep zoom when asked for or
+    /// when the zoom needs it.
+    fn params(&self) -> Result<FractalParams> {
+        // ---
+        let center = self.center();
+        let mut params = FractalParams {
+            kind: self.kind,
+            julia: self.constant,
+            center: center.to_complex64(),
+            zoom: self.zoom,
+            max_iterations: self.max_iterations,
+            coloring: Coloring {
+                mode: self.coloring,
+                palette: Palette::load(&self.palette)?,
+                cycle: self.cycle,
+                offset: self.offset,
+                trap: self.trap,
+                interior: self.interior.to_rgb(),
+            },
+            deep_center: None,
+        };
+        if self.deep || params.needs_deep_zoom() {
+            anyhow::ensure!(
+                self.kind == FractalKind::Mandelbrot,
+                "Deep zoom is only supported for the Mandelbrot set"
+            );
+            params.deep_center = Some(center);
+        }
+        Ok(params)
+    }
+
+    /// The `--center` option, or the default center of the fractal type.
+    fn center(&self) -> BigComplex {
+        // ---
+        let defaults = match self.kind {
+            FractalKind::Mandelbrot => FractalParams::mandelbrot(),
+            FractalKind::Julia => FractalParams::default(),
+        };
+        self.center
+            .clone()
+            .unwrap_or_else(|| defaults.center.into())
+    }
+
+    /// Every option that affects the image, spelled out so the render can
+    /// be repeated even if the defaults change.
+    fn arguments(&self) -> Vec<String> {
+        // ---
+        let mut arguments = vec![
+            format!("--type={}", value_name(self.kind)),
+            format!("--constant={}", complex_text(self.constant)),
+            format!("--center={}", self.center()),
+            format!("--zoom={}", self.zoom),
+            format!("--max-iterations={}", self.max_iterations),
+            format!("--coloring={}", value_name(self.coloring)),
+            format!("--palette={}", self.palette),
+            format!("--cycle={}", self.cycle),
+            format!("--offset={}", self.offset),
+            format!("--trap={}", complex_text(self.trap)),
+            format!("--interior={}", color_text(self.interior)),
+        ];
+        if self.deep {
+            arguments.push("--deep".to_string());
+        }
+        arguments
+    }
+
+    /// A thread pool with the requested number of workers.
+    fn thread_pool(&self) -> Result<rayon::ThreadPool> {
+        // ---
+        Ok(rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads.unwrap_or(0) as usize)
+            .build()?)
+    }
 }
 
 use anyhow::{Context, Result};
+use image::{DynamicImage, Pixel, Rgba};
+use mirage::batch::{self, OutputTemplate};
+use mirage::metadata::{self, Parameters};
+use mirage::{Adaptive, AdaptiveThreshold, Posterize, Threshold};
+use mirage::{Animation, AnimationFormat, BigComplex, ColorMode, Coloring, Palette};
+use mirage::{Aspect, Brighten, Canvas, CropRegion, Desaturate, Geometry, Interpolation, Weights};
+use mirage::{Brightness, Channels, Contrast, Curves, Exposure, Gamma, Levels};
+use mirage::{ColorModel, Filter, Fit, Gravity, Hue, Modulate, Pipeline, Resize, Rotate, Size};
+use mirage::{ColorStop, Fill, FractalKind, FractalParams, Gradient, GradientKind};
+use mirage::{Colorize, Duotone, Sepia, Tint};
+use mirage::{Colors, Dither, Dithering};
+use mirage::{Flip, Flop, Transpose, Transverse};
+use mirage::{
+    InvertChannels, InvertLightness, Length, Metric, Operation, Sharpen, SmartCrop, Trim,
+};
+use mirage::{Kernel, KernelBlur, SpinBlur, Unit, ZoomBlur};
+use num_complex::Complex64;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 fn main() -> Result<()> {
     // ---
     let args = Args::parse();
-    args.command.execute()
+    args.command.execute(&args.input)
 }
 
 #[derive(Debug, Subcommand)]
 enum Command {
-    /// blur an image by a given percentage
+    /// blur an image by a gaussian sigma in pixels, or with another kernel
+    /// or unit
     Blur {
         infile: String,
         outfile: String,
-        #[arg(value_parser = clap::value_parser!(u32).range(0..=100))]
-        percent: u32,
+        /// size of the blur in the --unit
+        #[arg(value_parser = blur_valid)]
+        amount: f32,
+        /// unit of the amount
+        #[arg(long, value_enum, default_value = "sigma")]
+        unit: Unit,
+        /// shape of the blur
+        #[arg(long, value_enum, default_value = "gaussian")]
+        kernel: Kernel,
+        /// direction of a motion blur, in degrees clockwise from horizontal
+        #[arg(long, default_value_t = 0.0, value_parser = rotate_valid, allow_negative_numbers = true)]
+        angle: f32,
+        /// number of passes of a box blur; 3 look close to a gaussian
+        #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u32).range(1..))]
+        passes: u32,
+    },
+
+    /// blur in circles around a center, over an arc of the given degrees
+    SpinBlur {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = rotate_valid, allow_negative_numbers = true)]
+        degrees: f32,
+        /// center of the spin as X,Y fractions of the image size
+        #[arg(long, value_parser = center_valid, default_value = "0.5,0.5")]
+        center: (f32, f32),
+    },
+
+    /// blur along lines toward a center, over the given percentage of each
+    /// pixel's distance to it
+    ZoomBlur {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = percent_valid)]
+        percent: f32,
+        /// center of the zoom as X,Y fractions of the image size
+        #[arg(long, value_parser = center_valid, default_value = "0.5,0.5")]
+        center: (f32, f32),
+    },
+
+    /// sharpen an image with an unsharp mask
+    Sharpen {
+        infile: String,
+        outfile: String,
+        /// radius of the mask: the gaussian sigma in pixels
+        #[arg(default_value_t = 1.0, value_parser = positive_valid)]
+        sigma: f64,
+        /// strength in percent of the detail added back
+        #[arg(long, default_value_t = 100.0, value_parser = percent_valid)]
+        amount: f32,
+        /// smallest difference that is sharpened, in percent of the channel range
+        #[arg(long, default_value_t = 0.0, value_parser = percent_valid)]
+        threshold: f32,
     },
 
-    /// brighten an image by given amount
+    /// unsharp mask with every parameter given, e.g. `unsharp in.jpg out.jpg 2 150 3`
+    Unsharp {
+        infile: String,
+        outfile: String,
+        /// radius of the mask: the gaussian sigma in pixels
+        #[arg(value_parser = positive_valid)]
+        sigma: f64,
+        /// strength in percent of the detail added back
+        #[arg(value_parser = percent_valid)]
+        amount: f32,
+        /// smallest difference that is sharpened, in percent of the channel range
+        #[arg(value_parser = percent_valid)]
+        threshold: f32,
+    },
+
+    /// brighten an image by given amount, or by a percentage, exposure
+    /// stops or per-channel percentages; negative values darken
+    #[command(group(
+        clap::ArgGroup::new("mode")
+            .required(true)
+            .multiple(true)
+            .args(["amount", "percent", "exposure", "red", "green", "blue"]),
+    ))]
     Brighten {
         infile: String,
         outfile: String,
-        amount: i32,
+        /// value added to every channel, in steps of the image's bit depth
+        #[arg(
+            allow_negative_numbers = true,
+            conflicts_with_all = ["percent", "exposure", "red", "green", "blue"]
+        )]
+        amount: Option<i32>,
+        /// percent of the channel range added to every color channel
+        #[arg(long, value_parser = offset_valid, allow_negative_numbers = true)]
+        percent: Option<f32>,
+        /// exposure change in stops, applied in linear light; 1 doubles the light
+        #[arg(
+            long,
+            value_parser = stops_valid,
+            allow_negative_numbers = true,
+            conflicts_with_all = ["percent", "red", "green", "blue"]
+        )]
+        exposure: Option<f32>,
+        /// percent of the channel range added to red, on top of --percent
+        #[arg(long, value_parser = offset_valid, allow_negative_numbers = true)]
+        red: Option<f32>,
+        /// percent of the channel range added to green, on top of --percent
+        #[arg(long, value_parser = offset_valid, allow_negative_numbers = true)]
+        green: Option<f32>,
+        /// percent of the channel range added to blue, on top of --percent
+        #[arg(long, value_parser = offset_valid, allow_negative_numbers = true)]
+        blue: Option<f32>,
     },
 
-    /// crop an image to x, y, width, height
+    /// raise or lower contrast by a signed percentage; -100 is flat gray
+    Contrast {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = contrast_valid, allow_negative_numbers = true)]
+        percent: f32,
+    },
+
+    /// gamma correction; above 1 brightens the midtones, below 1 darkens them
+    Gamma {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = positive_valid)]
+        gamma: f64,
+    },
+
+    /// stretch the range from the black point to the white point (in
+    /// percent) to the full range, with an optional midtone gamma
+    Levels {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = percent_valid)]
+        black: f32,
+        #[arg(value_parser = percent_valid)]
+        white: f32,
+        #[arg(default_value_t = 1.0, value_parser = positive_valid)]
+        gamma: f64,
+        /// channels to adjust together, e.g. r or gb
+        #[arg(long, default_value = "rgb")]
+        channels: Channels,
+    },
+
+    /// map values through a smooth curve through IN,OUT points in percent,
+    /// e.g. `curves in.jpg out.jpg 0,0 25,20 75,80 100,100`
+    Curves {
+        infile: String,
+        outfile: String,
+        #[arg(required = true, num_args = 2.., value_parser = curve_point_valid)]
+        points: Vec<(f32, f32)>,
+        /// channels to adjust together, e.g. r or gb
+        #[arg(long, default_value = "rgb")]
+        channels: Channels,
+    },
+
+    /// crop an image to X Y WIDTH HEIGHT or a WxH+X+Y geometry; every part
+    /// may be a percentage of the image size, e.g. 50%x50%+25%+25%
     Crop {
         infile: String,
         outfile: String,
-        x: u32,
-        y: u32,
-        width: u32,
-        height: u32,
+        /// X Y WIDTH HEIGHT, or WxH+X+Y [default: the whole image]
+        #[arg(num_args = 0..=4, value_name = "REGION")]
+        region: Vec<String>,
+        /// narrow the crop to the largest rectangle of this aspect ratio, e.g. 16:9
+        #[arg(long)]
+        aspect: Option<Aspect>,
+        /// alignment of the crop in the image; offsets move it inward from its edges
+        #[arg(long, value_enum, default_value = "north-west")]
+        gravity: Gravity,
     },
 
-    /// rotate an image by the given degrees, valid values 90, 180 or 270
+    /// rotate an image clockwise by the given degrees; multiples of 90 are
+    /// lossless, other angles are resampled
     Rotate {
         infile: String,
         outfile: String,
-        #[arg(value_parser = rotate_valid)]
-        degrees: u32,
+        #[arg(value_parser = rotate_valid, allow_negative_numbers = true)]
+        degrees: f32,
+        /// sampling for angles that aren't a multiple of 90
+        #[arg(long, value_enum, default_value = "bicubic")]
+        interpolation: Interpolation,
+        /// grow the canvas to fit the rotated image or keep the original size
+        #[arg(long, value_enum, default_value = "expand")]
+        canvas: Canvas,
+        /// fill of the canvas outside the rotated image, e.g. white or transparent
+        #[arg(long, value_parser = mirage::parse_color, default_value = "black")]
+        background: Rgba<u8>,
+    },
+
+    /// remove the borders that have the color of the top left corner
+    Trim {
+        infile: String,
+        outfile: String,
+        /// largest difference from the border color still counted as border,
+        /// in percent of the channel range
+        #[arg(long, default_value_t = 0.0, value_parser = percent_valid)]
+        fuzz: f32,
+    },
+
+    /// crop to WxH and/or an aspect ratio where the image has the most detail
+    #[command(group(clap::ArgGroup::new("target").required(true).multiple(true).args(["size", "aspect"])))]
+    Smartcrop {
+        infile: String,
+        outfile: String,
+        /// crop size as WxH; either side may be a percentage
+        #[arg(value_parser = crop_size_valid)]
+        size: Option<(Length, Length)>,
+        /// crop the largest rectangle of this aspect ratio, e.g. 1:1
+        #[arg(long)]
+        aspect: Option<Aspect>,
+        /// how the detail of a candidate crop is measured
+        #[arg(long, value_enum, default_value = "entropy")]
+        metric: Metric,
+    },
+
+    /// resize to WxH, Wx or xH (keeping the aspect ratio) or a percentage
+    /// such as 50%
+    Resize {
+        infile: String,
+        outfile: String,
+        size: Size,
+        /// how a WxH box is filled
+        #[arg(long, value_enum, default_value = "stretch")]
+        fit: Fit,
+        /// resampling filter
+        #[arg(long, value_enum, default_value = "lanczos3")]
+        filter: Filter,
+        /// part of the image kept by cover and placement of contain
+        #[arg(long, value_enum, default_value = "center")]
+        gravity: Gravity,
+        /// letterbox color for contain, e.g. white or transparent
+        #[arg(long, value_parser = mirage::parse_color, default_value = "black")]
+        background: Rgba<u8>,
+        /// never enlarge the image, e.g. for thumbnails
+        #[arg(long)]
+        shrink_only: bool,
     },
 
-    /// generate a new image in outfile
-    Generate { outfile: String, value: i32 },
+    /// mirror an image top to bottom
+    Flip { infile: String, outfile: String },
+
+    /// mirror an image left to right
+    Flop { infile: String, outfile: String },
+
+    /// mirror an image along its top-left to bottom-right diagonal
+    Transpose { infile: String, outfile: String },
+
+    /// mirror an image along its top-right to bottom-left diagonal
+    Transverse { infile: String, outfile: String },
+
+    /// generate a solid color or gradient image in outfile
+    #[command(group(clap::ArgGroup::new("fill").required(true).args(["color", "gradient"])))]
+    Generate {
+        outfile: String,
+        #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+        width: u32,
+        #[arg(long, default_value_t = 256, value_parser = clap::value_parser!(u32).range(1..))]
+        height: u32,
+        /// solid color: #rrggbb[aa], rgb(r,g,b), hsl(h,s%,l%) or a CSS color name
+        #[arg(long, value_parser = mirage::parse_color)]
+        color: Option<Rgba<u8>>,
+        /// gradient shape, painted with two or more --stop colors
+        #[arg(long, value_enum, requires = "stops")]
+        gradient: Option<GradientKind>,
+        /// gradient color stop, repeat for each color; POS is a percentage or 0-1
+        #[arg(long = "stop", value_name = "COLOR[@POS]")]
+        stops: Vec<ColorStop>,
+        /// linear gradient direction or conic start, in degrees clockwise from the top
+        #[arg(long, default_value_t = 180.0, allow_negative_numbers = true)]
+        angle: f32,
+        /// radial or conic gradient center as X,Y fractions of the image size
+        #[arg(long, value_parser = center_valid, default_value = "0.5,0.5")]
+        center: (f32, f32),
+    },
 
     /// invert an image from infile to outfile
-    Invert { infile: String, outfile: String },
+    Invert {
+        infile: String,
+        outfile: String,
+        /// channels to invert, e.g. r,b [default: r,g,b]
+        #[arg(long, conflicts_with = "lightness")]
+        channels: Option<Channels>,
+        /// invert alpha as well
+        #[arg(long, conflicts_with = "lightness")]
+        alpha: bool,
+        /// swap light and dark but keep hues, inverting the lightness in OKLab,
+        /// e.g. for a dark mode version of a diagram
+        #[arg(long)]
+        lightness: bool,
+    },
 
     /// convert an image to grey scale
-    Grayscale { infile: String, outfile: String },
+    Grayscale {
+        infile: String,
+        outfile: String,
+        /// gray value of a color: rec601, rec709, average, lightness, or
+        /// R,G,B channel mixer weights such as 0.5,0.5,0
+        #[arg(long, default_value = "rec709")]
+        weights: Weights,
+        /// how much color to take out, in percent; below 100 keeps RGB
+        #[arg(long, default_value_t = 100.0, value_parser = percent_valid)]
+        amount: f32,
+        /// keep the RGB(A) color type so the result can be tinted later
+        #[arg(long)]
+        keep_rgb: bool,
+    },
+
+    /// turn an image black and white at a fixed level or one found by Otsu's method
+    Threshold {
+        infile: String,
+        outfile: String,
+        /// level in percent of the range; brighter pixels turn white
+        #[arg(value_parser = percent_valid, required_unless_present = "otsu")]
+        level: Option<f32>,
+        /// pick the level that best separates dark and light pixels
+        #[arg(long, conflicts_with = "level")]
+        otsu: bool,
+    },
+
+    /// turn a scan black and white at a level that follows the local brightness
+    AdaptiveThreshold {
+        infile: String,
+        outfile: String,
+        /// how the level is computed from the local mean and deviation
+        #[arg(long, value_enum, default_value = "sauvola")]
+        method: Adaptive,
+        /// side of the square around each pixel, in pixels
+        #[arg(long, default_value_t = 25, value_parser = clap::value_parser!(u32).range(3..))]
+        window: u32,
+        /// how far below the local mean the level lies [default: 0.2 for
+        /// sauvola, -0.2 for niblack]
+        #[arg(long, allow_negative_numbers = true)]
+        k: Option<f32>,
+    },
+
+    /// reduce every color channel to a number of levels
+    Posterize {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = clap::value_parser!(u32).range(2..=256))]
+        levels: u32,
+    },
+
+    /// reduce an image to a few gray levels or a fixed palette by dithering
+    Dither {
+        infile: String,
+        outfile: String,
+        /// bits per pixel of gray: 1, 2, 4 or 8 for 2, 4, 16 or 256 levels [default: 1]
+        #[arg(long, value_parser = bits_valid, conflicts_with = "palette")]
+        bits: Option<u8>,
+        /// colors to use instead of gray levels, e.g. --palette black white red
+        #[arg(long, value_parser = mirage::parse_color, num_args = 2.., value_name = "COLOR")]
+        palette: Vec<Rgba<u8>>,
+        /// error diffusion or ordered (bayer) dithering
+        #[arg(long, value_enum, default_value = "floyd-steinberg")]
+        method: Dithering,
+        /// diffuse errors right to left on every other row
+        #[arg(long)]
+        serpentine: bool,
+    },
+
+    /// rotate the hue of every pixel by the given signed degrees
+    Hue {
+        infile: String,
+        outfile: String,
+        #[arg(value_parser = rotate_valid, allow_negative_numbers = true)]
+        degrees: f32,
+    },
+
+    /// adjust hue, saturation and lightness together
+    Modulate {
+        infile: String,
+        outfile: String,
+        /// hue rotation in degrees
+        #[arg(long, default_value_t = 0.0, value_parser = rotate_valid, allow_negative_numbers = true)]
+        hue: f32,
+        /// saturation in percent of the original, 0 for gray
+        #[arg(long, default_value_t = 100.0, value_parser = percent_valid)]
+        saturation: f32,
+        /// lightness (hsl) or value (hsv) in percent of the original
+        #[arg(long, default_value_t = 100.0, value_parser = percent_valid)]
+        lightness: f32,
+        /// color model for the saturation and lightness adjustments
+        #[arg(long, value_enum, default_value = "hsl")]
+        model: ColorModel,
+    },
+
+    /// give an image the brown tones of an old photograph
+    Sepia {
+        infile: String,
+        outfile: String,
+        /// intensity in percent, 0 leaves the image unchanged
+        #[arg(long, default_value_t = 100.0, value_parser = percent_valid)]
+        amount: f32,
+    },
+
+    /// map dark and light tones onto two colors
+    Duotone {
+        infile: String,
+        outfile: String,
+        /// color of the shadows: #rrggbb[aa], rgb(r,g,b), hsl(h,s%,l%) or a CSS color name
+        #[arg(long, value_parser = mirage::parse_color, default_value = "black")]
+        shadows: Rgba<u8>,
+        /// color of the highlights, in the same syntax
+        #[arg(long, value_parser = mirage::parse_color, default_value = "white")]
+        highlights: Rgba<u8>,
+    },
+
+    /// shift the midtones toward a color, keeping black and white
+    Tint {
+        infile: String,
+        outfile: String,
+        /// tint color: #rrggbb, rgb(r,g,b), hsl(h,s%,l%) or a CSS color name
+        #[arg(value_parser = mirage::parse_color)]
+        color: Rgba<u8>,
+        /// strength in percent
+        #[arg(long, default_value_t = 100.0, value_parser = percent_valid)]
+        amount: f32,
+    },
+
+    /// map luminance onto a gradient of colors, from black to white
+    Colorize {
+        infile: String,
+        outfile: String,
+        /// gradient color stop, repeat for each color; POS is a percentage or 0-1
+        #[arg(long = "stop", value_name = "COLOR[@POS]", required = true)]
+        stops: Vec<ColorStop>,
+    },
 
     /// generate a fractal image in the file provided.
     Fractal {
         outfile: String,
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
         width: u32,
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
         height: u32,
+        #[command(flatten)]
+        fractal: FractalArgs,
     },
-}
 
-macro_rules! imageop {
-    ($file: ident, $op: ident, $arg: expr) => {{
-        let img = (image::open(&$file).context(format!("Failed to open {}", $file))?);
-        img.$op($arg)
-    }};
-    ($file: ident, $op: ident) => {{
-        let img = (image::open(&$file).context(format!("Failed to open {}", $file))?);
-        img.$op()
-    }};
-}
+    /// render a fractal animation in the file provided: a zoom from the
+    /// fractal options to the --to-* keyframe, a palette cycle, or both
+    Animate {
+        /// .gif, .png for an APNG, or a frame pattern such as frame_%05d.png
+        outfile: String,
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        width: u32,
+        #[arg(value_parser = clap::value_parser!(u32).range(1..))]
+        height: u32,
+        #[command(flatten)]
+        fractal: FractalArgs,
+        /// number of frames
+        #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u32).range(1..))]
+        frames: u32,
+        /// center of the last frame as RE,IM [default: the first frame's]
+        #[arg(long, allow_hyphen_values = true)]
+        to_center: Option<BigComplex>,
+        /// zoom of the last frame, reached exponentially [default: the first frame's]
+        #[arg(long, value_parser = positive_valid)]
+        to_zoom: Option<f64>,
+        /// Julia set constant of the last frame [default: the first frame's]
+        #[arg(long, value_parser = complex_valid, allow_hyphen_values = true)]
+        to_constant: Option<Complex64>,
+        /// number of times the palette cycles over the animation
+        #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+        palette_cycles: f64,
+        /// display time of each frame in milliseconds
+        #[arg(long, default_value_t = 40)]
+        delay: u32,
+        /// output format [default: from the outfile name]
+        #[arg(long, value_enum)]
+        format: Option<AnimationFormat>,
+    },
 
-macro_rules! imageop_mut {
-    ($file:  ident,
-     $op:    ident $(, $args: ident)*) => {{
-         let mut img = (image::open(&$file).context(format!("Failed to open {}", $file))?);
-         img.$op($($args),*);
-         img
-    }};
+    /// render a PNG written by `fractal` or `generate` again from the
+    /// parameters stored in it, optionally at a new resolution
+    Rerender {
+        infile: String,
+        outfile: String,
+        /// new width; the height keeps the aspect ratio unless also given
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        width: Option<u32>,
+        /// new height; the width keeps the aspect ratio unless also given
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+        height: Option<u32>,
+    },
+
+    /// apply several operations in order, decoding and encoding only once,
+    /// e.g. `pipeline in.jpg out.png -- crop 0 0 800 600 : blur 3 : grayscale`
+    Pipeline {
+        infile: String,
+        outfile: String,
+        /// operations separated by `:`, each written as its own subcommand
+        /// without the infile/outfile arguments
+        #[arg(last = true, required = true)]
+        steps: Vec<String>,
+    },
 }
 
+/// Token separating the operations of a `pipeline` command.
+const PIPELINE_SEPARATOR: &str = ":";
+
 impl Command {
     // ---
 
-    fn execute(self) -> Result<()> {
+    fn execute(self, input: &InputArgs) -> Result<()> {
         // ---
 
+        let parameters = self.parameters();
         match self {
-            Self::Blur {
-                infile,
+            Self::Fractal {
                 outfile,
-                percent,
+                width,
+                height,
+                fractal: options,
             } => {
-                let img = imageop!(infile, blur, percent as f32);
-                img.save(&outfile)
-                    .context(format!("Failed writing {}.", outfile))
+                let params = options.params()?;
+                options
+                    .thread_pool()?
+                    .install(|| fractal(&outfile, width, height, &params, parameters.as_ref()))
             }
 
-            Self::Brighten {
-                infile,
+            Self::Animate {
                 outfile,
-                amount,
+                width,
+                height,
+                fractal: options,
+                frames,
+                to_center,
+                to_zoom,
+                to_constant,
+                palette_cycles,
+                delay,
+                format,
             } => {
-                let img = imageop!(infile, brighten, amount);
-                img.save(&outfile)
-                    .context(format!("Failed writing {}.", outfile))
+                let params = options.params()?;
+                let mut animation = Animation::new(&params, frames);
+                animation.palette_cycles = palette_cycles;
+                animation.delay_ms = delay;
+                if let Some(center) = to_center {
+                    animation.end.center = center;
+                }
+                animation.end.zoom = to_zoom.unwrap_or(animation.end.zoom);
+                animation.end.julia = to_constant.unwrap_or(animation.end.julia);
+
+                anyhow::ensure!(
+                    animation.end != animation.start || palette_cycles != 0.0,
+                    "Nothing to animate, use --to-center, --to-zoom, --to-constant or --palette-cycles"
+                );
+                anyhow::ensure!(
+                    palette_cycles == 0.0 || options.coloring != ColorMode::Classic,
+                    "--palette-cycles needs a palette, use --coloring smooth, histogram or orbit-trap"
+                );
+                anyhow::ensure!(
+                    options.kind == FractalKind::Mandelbrot
+                        || !animation.params_at(&params, frames - 1).needs_deep_zoom(),
+                    "Deep zoom is only supported for the Mandelbrot set"
+                );
+                let format = format
+                    .or_else(|| AnimationFormat::from_path(&outfile))
+                    .context(format!(
+                        "Can't tell the animation format of {}, use --format",
+                        outfile
+                    ))?;
+
+                options
+                    .thread_pool()?
+                    .install(|| animate(&outfile, format, width, height, &params, &animation))
             }
 
-            Self::Crop {
-                infile,
+            Self::Generate {
                 outfile,
-                x,
-                y,
                 width,
                 height,
+                color,
+                gradient,
+                stops,
+                angle,
+                center,
             } => {
-                let mut img = image::open(&infile).context(format!("Failed to open {}", infile))?;
-                let img = img.crop(x, y, width, height);
-                img.save(&outfile)
-                    .context(format!("Failed writing {}.", outfile))
+                let fill = match (color, gradient) {
+                    (Some(color), _) => Fill::Solid(color),
+                    (None, Some(kind)) => Fill::Gradient(Gradient {
+                        kind,
+                        stops,
+                        angle,
+                        center,
+                    }),
+                    (None, None) => anyhow::bail!("Use --color or --gradient to set the fill"),
+                };
+                generate(&outfile, width, height, &fill, parameters.as_ref())
             }
 
-            Self::Rotate {
+            Self::Rerender {
                 infile,
                 outfile,
-                degrees,
+                width,
+                height,
             } => {
-                let img = imageop!(infile, huerotate, degrees as i32);
-                let img = match degrees {
-                    90 => img.rotate90(),
-                    180 => img.rotate180(),
-                    270 => img.rotate180(),
-                    _ => {
-                        return Err(anyhow::anyhow!(
-                            "Invalid rotation value:{} use 90, 180 or 270",
-                            degrees
-                        ))
+                let stored = Parameters::read(Path::new(&infile))?;
+                if stored.software != metadata::SOFTWARE {
+                    eprintln!(
+                        "rerender: {infile} was made by {}, rendering with {}",
+                        stored.software,
+                        metadata::SOFTWARE
+                    );
+                }
+
+                // The stored command has no outfile, which always follows
+                // the subcommand name
+                let (name, arguments) = stored
+                    .arguments
+                    .split_first()
+                    .context(format!("{} has empty render parameters", infile))?;
+                let argv = ["mirage", name, &outfile]
+                    .into_iter()
+                    .chain(arguments.iter().map(String::as_str));
+                let mut command = Args::try_parse_from(argv)
+                    .map_err(|err| anyhow::anyhow!("{}", err.render()))
+                    .context(format!("Invalid render parameters in {}", infile))?
+                    .command;
+
+                match &mut command {
+                    Self::Fractal {
+                        width: old_width,
+                        height: old_height,
+                        ..
                     }
-                };
-                img.save(&outfile)
-                    .context(format!("Failed writing {}.", outfile))
+                    | Self::Generate {
+                        width: old_width,
+                        height: old_height,
+                        ..
+                    } => {
+                        (*old_width, *old_height) =
+                            resolution((*old_width, *old_height), width, height);
+                    }
+                    _ => anyhow::bail!("`{}` can't be rerendered", command.name()),
+                }
+                command.execute(input)
             }
-            Self::Invert { infile, outfile } => {
-                let img = imageop_mut!(infile, invert);
-                img.save(&outfile)
-                    .context(format!("Failed writing {}.", outfile))
+
+            Self::Pipeline {
+                ref infile,
+                ref outfile,
+                ref steps,
+            } => {
+                let pipeline = parse_pipeline(steps)?
+                    .iter()
+                    .map(Command::operation)
+                    .collect::<Result<Pipeline>>()?;
+                process(infile, outfile, self.name(), &pipeline, input)
             }
 
-            Self::Grayscale { infile, outfile } => {
-                let img = imageop!(infile, grayscale);
-                img.save(&outfile)
-                    .context(format!("Failed writing {}.", outfile))
+            Self::Blur {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::SpinBlur {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::ZoomBlur {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Sharpen {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Unsharp {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Brighten {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Contrast {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Gamma {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Levels {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Curves {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Crop {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Rotate {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Resize {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Trim {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Smartcrop {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Flip {
+                ref infile,
+                ref outfile,
+            }
+            | Self::Flop {
+                ref infile,
+                ref outfile,
+            }
+            | Self::Transpose {
+                ref infile,
+                ref outfile,
             }
+            | Self::Transverse {
+                ref infile,
+                ref outfile,
+            }
+            | Self::Invert {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Grayscale {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Threshold {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::AdaptiveThreshold {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Posterize {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Dither {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Hue {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Modulate {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Sepia {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Duotone {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Tint {
+                ref infile,
+                ref outfile,
+                ..
+            }
+            | Self::Colorize {
+                ref infile,
+                ref outfile,
+                ..
+            } => process(infile, outfile, self.name(), &self.operation()?, input),
+        } // match
+    } // fn execute
+
+    /// The library operation this command performs on its input image.
+    /// Commands that don't transform an input image (`fractal`, `animate`,
+    /// `rerender`, `generate`, `pipeline`) have none.
+    fn operation(&self) -> Result<Box<dyn Operation>> {
+        // ---
 
+        let op: Box<dyn Operation> = match *self {
+            Self::Blur {
+                amount,
+                unit,
+                kernel,
+                angle,
+                passes,
+                ..
+            } => Box::new(KernelBlur {
+                kernel,
+                amount,
+                unit,
+                angle,
+                passes,
+            }),
+
+            Self::SpinBlur {
+                degrees, center, ..
+            } => Box::new(SpinBlur {
+                angle: degrees,
+                center,
+            }),
+
+            Self::ZoomBlur {
+                percent, center, ..
+            } => Box::new(ZoomBlur {
+                amount: percent / 100.0,
+                center,
+            }),
+
+            Self::Sharpen {
+                sigma,
+                amount,
+                threshold,
+                ..
+            }
+            | Self::Unsharp {
+                sigma,
+                amount,
+                threshold,
+                ..
+            } => Box::new(Sharpen {
+                sigma: sigma as f32,
+                amount: amount / 100.0,
+                threshold: threshold / 100.0,
+            }),
+
+            Self::Brighten {
+                amount: Some(amount),
+                ..
+            } => Box::new(Brighten { amount }),
+
+            Self::Brighten {
+                exposure: Some(stops),
+                ..
+            } => Box::new(Exposure { stops }),
+
+            Self::Brighten {
+                percent,
+                red,
+                green,
+                blue,
+                ..
+            } => {
+                let percent = percent.unwrap_or(0.0);
+                let channel = |amount: Option<f32>| (percent + amount.unwrap_or(0.0)) / 100.0;
+                Box::new(Brightness {
+                    red: channel(red),
+                    green: channel(green),
+                    blue: channel(blue),
+                })
+            }
+
+            Self::Contrast { percent, .. } => Box::new(Contrast {
+                amount: percent / 100.0,
+            }),
+
+            Self::Gamma { gamma, .. } => Box::new(Gamma {
+                gamma: gamma as f32,
+            }),
+
+            Self::Levels {
+                black,
+                white,
+                gamma,
+                channels,
+                ..
+            } => Box::new(Levels {
+                black: black / 100.0,
+                white: white / 100.0,
+                gamma: gamma as f32,
+                channels,
+            }),
+
+            Self::Curves {
+                ref points,
+                channels,
+                ..
+            } => Box::new(Curves {
+                points: points
+                    .iter()
+                    .map(|&(x, y)| (x / 100.0, y / 100.0))
+                    .collect(),
+                channels,
+            }),
+
+            Self::Crop {
+                ref region,
+                aspect,
+                gravity,
+                ..
+            } => Box::new(CropRegion {
+                geometry: crop_geometry(region, aspect.is_some())?,
+                aspect,
+                gravity,
+            }),
+
+            Self::Rotate {
+                degrees,
+                interpolation,
+                canvas,
+                background,
+                ..
+            } => Box::new(Rotate {
+                degrees,
+                interpolation,
+                canvas,
+                background,
+            }),
+
+            Self::Trim { fuzz, .. } => Box::new(Trim { fuzz: fuzz / 100.0 }),
+
+            Self::Smartcrop {
+                size,
+                aspect,
+                metric,
+                ..
+            } => {
+                let (width, height) =
+                    size.unwrap_or((Length::Percent(100.0), Length::Percent(100.0)));
+                Box::new(SmartCrop {
+                    width,
+                    height,
+                    aspect,
+                    metric,
+                })
+            }
+
+            Self::Resize {
+                size,
+                fit,
+                filter,
+                gravity,
+                background,
+                shrink_only,
+                ..
+            } => Box::new(Resize {
+                size,
+                fit,
+                filter,
+                gravity,
+                background,
+                shrink_only,
+            }),
+
+            Self::Flip { .. } => Box::new(Flip),
+
+            Self::Flop { .. } => Box::new(Flop),
+
+            Self::Transpose { .. } => Box::new(Transpose),
+
+            Self::Transverse { .. } => Box::new(Transverse),
+
+            Self::Invert {
+                lightness: true, ..
+            } => Box::new(InvertLightness),
+
+            Self::Invert {
+                channels, alpha, ..
+            } => {
+                let mut channels = channels.unwrap_or_default();
+                channels.alpha |= alpha;
+                Box::new(InvertChannels { channels })
+            }
+
+            Self::Grayscale {
+                weights,
+                amount,
+                keep_rgb,
+                ..
+            } => Box::new(Desaturate {
+                weights,
+                amount: amount / 100.0,
+                keep_rgb,
+            }),
+
+            Self::Threshold { level, .. } => Box::new(Threshold {
+                level: level.map(|level| level / 100.0),
+            }),
+
+            Self::AdaptiveThreshold {
+                method, window, k, ..
+            } => Box::new(AdaptiveThreshold {
+                method,
+                window,
+                k: k.unwrap_or(method.k()),
+            }),
+
+            Self::Posterize { levels, .. } => Box::new(Posterize { levels }),
+
+            Self::Dither {
+                bits,
+                ref palette,
+                method,
+                serpentine,
+                ..
+            } => Box::new(Dither {
+                colors: match palette.is_empty() {
+                    true => Colors::Gray(bits.unwrap_or(1)),
+                    false => Colors::Palette(palette.clone()),
+                },
+                method,
+                serpentine,
+            }),
+
+            Self::Hue { degrees, .. } => Box::new(Hue { degrees }),
+
+            Self::Modulate {
+                hue,
+                saturation,
+                lightness,
+                model,
+                ..
+            } => Box::new(Modulate {
+                hue,
+                saturation: saturation / 100.0,
+                lightness: lightness / 100.0,
+                model,
+            }),
+
+            Self::Sepia { amount, .. } => Box::new(Sepia {
+                amount: amount / 100.0,
+            }),
+
+            Self::Duotone {
+                shadows,
+                highlights,
+                ..
+            } => Box::new(Duotone {
+                shadows,
+                highlights,
+            }),
+
+            Self::Tint { color, amount, .. } => Box::new(Tint {
+                color,
+                amount: amount / 100.0,
+            }),
+
+            Self::Colorize { ref stops, .. } => Box::new(Colorize {
+                stops: stops.clone(),
+            }),
+
+            Self::Fractal { .. }
+            | Self::Animate { .. }
+            | Self::Rerender { .. }
+            | Self::Generate { .. }
+            | Self::Pipeline { .. } => {
+                anyhow::bail!("`{}` can't be used as a pipeline step", self.name())
+            }
+        };
+        Ok(op)
+    } // fn operation
+
+    /// The render parameters to store in the output of commands that create
+    /// an image from scratch (`fractal`, `generate`).
+    fn parameters(&self) -> Option<Parameters> {
+        // ---
+        let arguments = match self {
             Self::Fractal {
-                outfile,
                 width,
                 height,
-            } => fractal(&outfile, width, height),
+                fractal,
+                ..
+            } => [
+                self.name().to_string(),
+                width.to_string(),
+                height.to_string(),
+            ]
+            .into_iter()
+            .chain(fractal.arguments())
+            .collect(),
+
+            Self::Generate {
+                width,
+                height,
+                color,
+                gradient,
+                stops,
+                angle,
+                center,
+                ..
+            } => {
+                let mut arguments = vec![
+                    self.name().to_string(),
+                    format!("--width={width}"),
+                    format!("--height={height}"),
+                ];
+                if let Some(color) = color {
+                    arguments.push(format!("--color={}", color_text(*color)));
+                }
+                if let Some(kind) = gradient {
+                    arguments.push(format!("--gradient={}", value_name(*kind)));
+                    arguments.extend(stops.iter().map(|stop| format!("--stop={stop}")));
+                    arguments.push(format!("--angle={angle}"));
+                    arguments.push(format!("--center={},{}", center.0, center.1));
+                }
+                arguments
+            }
 
-            Self::Generate { outfile, value } => generate(&outfile, value),
-        } // match
-    } // fn execute
+            _ => return None,
+        };
+        Some(Parameters::new(arguments))
+    }
+
+    /// The subcommand name as typed on the command line.
+    fn name(&self) -> &'static str {
+        // ---
+        match self {
+            Self::Blur { .. } => "blur",
+            Self::SpinBlur { .. } => "spin-blur",
+            Self::ZoomBlur { .. } => "zoom-blur",
+            Self::Sharpen { .. } => "sharpen",
+            Self::Unsharp { .. } => "unsharp",
+            Self::Brighten { .. } => "brighten",
+            Self::Contrast { .. } => "contrast",
+            Self::Gamma { .. } => "gamma",
+            Self::Levels { .. } => "levels",
+            Self::Curves { .. } => "curves",
+            Self::Crop { .. } => "crop",
+            Self::Rotate { .. } => "rotate",
+            Self::Trim { .. } => "trim",
+            Self::Smartcrop { .. } => "smartcrop",
+            Self::Resize { .. } => "resize",
+            Self::Flip { .. } => "flip",
+            Self::Flop { .. } => "flop",
+            Self::Transpose { .. } => "transpose",
+            Self::Transverse { .. } => "transverse",
+            Self::Generate { .. } => "generate",
+            Self::Invert { .. } => "invert",
+            Self::Grayscale { .. } => "grayscale",
+            Self::Threshold { .. } => "threshold",
+            Self::AdaptiveThreshold { .. } => "adaptive-threshold",
+            Self::Posterize { .. } => "posterize",
+            Self::Dither { .. } => "dither",
+            Self::Hue { .. } => "hue",
+            Self::Modulate { .. } => "modulate",
+            Self::Sepia { .. } => "sepia",
+            Self::Duotone { .. } => "duotone",
+            Self::Tint { .. } => "tint",
+            Self::Colorize { .. } => "colorize",
+            Self::Fractal { .. } => "fractal",
+            Self::Animate { .. } => "animate",
+            Self::Rerender { .. } => "rerender",
+            Self::Pipeline { .. } => "pipeline",
+        }
+    }
+}
+
+/// Apply `op` to a single file, or to every image of a directory or glob
+/// when `infile` names a batch, in which case `outfile` is the output
+/// directory.
+fn process(
+    infile: &str,
+    outfile: &str,
+    name: &str,
+    op: &dyn Operation,
+    input: &InputArgs,
+) -> Result<()> {
+    // ---
+    if !batch::is_batch_input(infile) {
+        anyhow::ensure!(
+            input.output_name.is_none() && !input.recursive && !input.force,
+            "--output-name, --recursive and --force only apply when {} is a directory or glob",
+            infile
+        );
+        let img = metadata::open(Path::new(infile), input.auto_orient())?;
+        let img = op.apply(img)?;
+        return save_image(&img, outfile);
+    }
+
+    let template = match &input.output_name {
+        Some(name) => OutputTemplate::new(name)?,
+        None => OutputTemplate::default(),
+    };
+    let inputs = batch::find_inputs(infile, input.recursive, Path::new(outfile))?;
+    anyhow::ensure!(!inputs.is_empty(), "No images found in {}", infile);
+
+    let summary = batch::run_batch(
+        &inputs,
+        Path::new(outfile),
+        &template,
+        name,
+        op,
+        input.auto_orient(),
+        input.force,
+    )?;
+    for (path, err) in &summary.failed {
+        eprintln!("{}: {:#}", path.display(), err);
+    }
+    println!("{name}: {summary}");
+
+    anyhow::ensure!(
+        summary.is_success(),
+        "{} of {} files failed",
+        summary.failed.len(),
+        inputs.len()
+    );
+    Ok(())
+}
+
+fn save_image(img: &DynamicImage, outfile: &str) -> Result<()> {
+    // ---
+    img.save(outfile)
+        .context(format!("Failed writing {}.", outfile))
+}
+
+/// The crop rectangle of the `crop` REGION arguments: four lengths in the
+/// classic X Y WIDTH HEIGHT order or one WxH+X+Y geometry. Without
+/// arguments the whole image is used, which only makes sense with an
+/// aspect ratio.
+fn crop_geometry(region: &[String], has_aspect: bool) -> Result<Geometry> {
+    // ---
+    match region {
+        [] if has_aspect => Ok(Geometry::default()),
+        [geometry] => geometry.parse(),
+        [x, y, width, height] => Ok(Geometry {
+            width: width.parse()?,
+            height: height.parse()?,
+            x: x.parse()?,
+            y: y.parse()?,
+        }),
+        _ => anyhow::bail!("crop takes X Y WIDTH HEIGHT, a WxH+X+Y geometry or --aspect"),
+    }
+}
+
+/// Split the raw pipeline arguments on `:` and parse every step with the
+/// regular subcommand definitions, so each operation accepts exactly the
+/// same arguments it does when run on its own.
+fn parse_pipeline(tokens: &[String]) -> Result<Vec<Command>> {
+    // ---
+    tokens
+        .split(|token| token == PIPELINE_SEPARATOR)
+        .map(parse_step)
+        .collect()
 }
 
-fn rotate_valid(str: &str) -> Result<u32, String> {
+fn parse_step(step: &[String]) -> Result<Command> {
     // ---
-    let degrees: u32 = str
+    let (name, args) = step
+        .split_first()
+        .context("Empty pipeline step, check for a doubled or trailing `:`")?;
+
+    // Steps don't name files, so fill the infile/outfile slots with
+    // placeholders; the pipeline itself decides what is read and written.
+    let argv = ["mirage", name, "-", "-"]
+        .into_iter()
+        .map(String::from)
+        .chain(args.iter().cloned());
+
+    let args = Args::try_parse_from(argv)
+        .map_err(|err| anyhow::anyhow!("{}", err.render()))
+        .context(format!("Invalid pipeline step `{}`", step.join(" ")))?;
+
+    // Input options apply to the whole pipeline, which reads the input once
+    let input = &args.input;
+    let flags = [
+        ("--output-name", input.output_name.is_some()),
+        ("--recursive", input.recursive),
+        ("--force", input.force),
+        ("--auto-orient", input.auto_orient),
+        ("--no-auto-orient", input.no_auto_orient),
+    ];
+    if let Some((flag, _)) = flags.iter().find(|(_, given)| *given) {
+        anyhow::bail!(
+            "{} applies to the whole pipeline, give it before `--` instead of in `{}`",
+            flag,
+            step.join(" ")
+        );
+    }
+
+    let command = args.command;
+    match command {
+        Command::Fractal { .. }
+        | Command::Animate { .. }
+        | Command::Rerender { .. }
+        | Command::Generate { .. }
+        | Command::Pipeline { .. } => {
+            anyhow::bail!("`{}` can't be used as a pipeline step", command.name())
+        }
+        command => Ok(command),
+    }
+}
+
+fn rotate_valid(str: &str) -> Result<f32, String> {
+    // ---
+    let degrees: f32 = str
         .parse()
         .map_err(|_| format!("`{}` Isn't a valid number.", str))?;
 
     match degrees {
-        val if val == 90 || val == 180 || val == 270 => Ok(val),
+        val if val.is_finite() => Ok(val),
         _ => Err(format!(
-            "Invalid rotation value:{str} must one of: 90, 180 or 270"
+            "Invalid rotation value:{str} must be a finite number of degrees"
         )),
     }
 }
 
-fn generate(outfile: &String, color: i32) -> Result<()> {
-    println!(
-        "\nGenerate: file={}, color={} is not yet implemented",
-        outfile, color
-    );
-    Ok(())
-    // TODO impl
-    // Create an ImageBuffer -- see fractal() for an example
+/// Parse a blur amount, 0 or more in any unit.
+fn blur_valid(str: &str) -> Result<f32, String> {
+    // ---
+    match str.parse::<f32>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(format!("Invalid blur:{str} must be a number of 0 or more")),
+    }
+}
+
+/// Parse a non-negative percentage.
+fn percent_valid(str: &str) -> Result<f32, String> {
+    // ---
+    match str.trim_end_matches('%').parse::<f32>() {
+        Ok(value) if value.is_finite() && value >= 0.0 => Ok(value),
+        _ => Err(format!(
+            "Invalid percentage:{str} must be a number of 0 or more"
+        )),
+    }
+}
+
+/// Parse the bits per pixel of `dither`'s gray levels.
+fn bits_valid(str: &str) -> Result<u8, String> {
+    // ---
+    match str.parse::<u8>() {
+        Ok(bits @ (1 | 2 | 4 | 8)) => Ok(bits),
+        _ => Err(format!("Invalid bits:{str} must be 1, 2, 4 or 8")),
+    }
+}
+
+/// Parse a `smartcrop` size given as `WxH`; the crop picks its own offsets.
+fn crop_size_valid(str: &str) -> Result<(Length, Length), String> {
+    // ---
+    match str.parse::<Geometry>() {
+        Ok(geometry) if !str.contains('+') => Ok((geometry.width, geometry.height)),
+        _ => Err(format!(
+            "Invalid crop size:{str} must be WxH, each side in pixels or percent"
+        )),
+    }
+}
+
+/// Parse a signed contrast change in percent, -100 or more.
+fn contrast_valid(str: &str) -> Result<f32, String> {
+    // ---
+    match str.trim_end_matches('%').parse::<f32>() {
+        Ok(value) if value.is_finite() && value >= -100.0 => Ok(value),
+        _ => Err(format!(
+            "Invalid contrast:{str} must be a number of -100 or more"
+        )),
+    }
+}
+
+/// Parse a signed percentage of the channel range, -100 to 100.
+fn offset_valid(str: &str) -> Result<f32, String> {
+    // ---
+    match str.trim_end_matches('%').parse::<f32>() {
+        Ok(value) if (-100.0..=100.0).contains(&value) => Ok(value),
+        _ => Err(format!(
+            "Invalid brightness:{str} must be a percentage from -100 to 100"
+        )),
+    }
+}
+
+/// Parse an exposure change in stops.
+fn stops_valid(str: &str) -> Result<f32, String> {
+    // ---
+    match str.parse::<f32>() {
+        Ok(value) if value.is_finite() => Ok(value),
+        _ => Err(format!("Invalid exposure:{str} must be a number of stops")),
+    }
+}
+
+/// Parse a curve point given as `IN,OUT` percentages.
+fn curve_point_valid(str: &str) -> Result<(f32, f32), String> {
+    // ---
+    let parse = |value: &str| {
+        let value = value.trim().trim_end_matches('%').parse::<f32>().ok()?;
+        (0.0..=100.0).contains(&value).then_some(value)
+    };
+    str.split_once(',')
+        .and_then(|(x, y)| Some((parse(x)?, parse(y)?)))
+        .ok_or_else(|| format!("Invalid curve point:{str} use IN,OUT percentages such as 25,20"))
+}
+
+/// Parse a gradient center given as `X,Y` fractions of the image size.
+fn center_valid(str: &str) -> Result<(f32, f32), String> {
+    // ---
+    let parse = |value: &str| value.trim().parse::<f32>().ok().filter(|v| v.is_finite());
+    str.split_once(',')
+        .and_then(|(x, y)| Some((parse(x)?, parse(y)?)))
+        .ok_or_else(|| format!("Invalid center:{str} use X,Y fractions such as 0.5,0.5"))
+}
+
+fn generate(
+    outfile: &String,
+    width: u32,
+    height: u32,
+    fill: &Fill,
+    parameters: Option<&Parameters>,
+) -> Result<()> {
+    // ---
+    println!("generate: f:{outfile}, w:{width}, h:{height}");
+    let img = DynamicImage::ImageRgba8(mirage::generate(width, height, fill)?);
+    save_generated(&img, outfile, parameters)
+}
+
+/// Save an image created from scratch, embedding `parameters` in PNGs.
+fn save_generated(
+    img: &DynamicImage,
+    outfile: &str,
+    parameters: Option<&Parameters>,
+) -> Result<()> {
+    // ---
+    match parameters {
+        Some(parameters) => parameters.save(img, Path::new(outfile)),
+        None => save_image(img, outfile),
+    }
+}
+
+/// The output size for a rerender of a `width` x `height` image: a single
+/// new dimension keeps the aspect ratio.
+fn resolution(
+    (width, height): (u32, u32),
+    new_width: Option<u32>,
+    new_height: Option<u32>,
+) -> (u32, u32) {
+    // ---
+    let scaled = |size: u32, from: u32, to: u32| {
+        ((size as f64 * to as f64 / from.max(1) as f64).round() as u32).max(1)
+    };
+    match (new_width, new_height) {
+        (Some(new_width), Some(new_height)) => (new_width, new_height),
+        (Some(new_width), None) => (new_width, scaled(height, width, new_width)),
+        (None, Some(new_height)) => (scaled(width, height, new_height), new_height),
+        (None, None) => (width, height),
+    }
+}
 
-    // Iterate over the coordinates and pixels of the image -- see fractal() for an example
+/// Name of a `ValueEnum` value as typed on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    // ---
+    value
+        .to_possible_value()
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_default()
+}
 
-    // Set the image to some solid color. -- see fractal() for an example
+fn complex_text(c: Complex64) -> String {
+    // ---
+    format!("{},{}", c.re, c.im)
+}
 
-    // Challenge: parse some color data from the command-line, pass it through
-    // to this function to use for the solid color.
+fn color_text(color: Rgba<u8>) -> String {
+    // ---
+    let [r, g, b, a] = color.0;
+    format!("#{r:02x}{g:02x}{b:02x}{a:02x}")
+}
 
-    // Challenge 2: Generate something more interesting!
+/// Parse a complex number given as `RE,IM` or `RE+IMi`.
+fn complex_valid(str: &str) -> Result<Complex64, String> {
+    // ---
+    let parsed = match str.split_once(',') {
+        Some((re, im)) => re
+            .trim()
+            .parse()
+            .and_then(|re| Ok(Complex64::new(re, im.trim().parse()?)))
+            .ok(),
+        None => str.parse::<Complex64>().ok(),
+    };
+    parsed
+        .filter(|c| c.is_finite())
+        .ok_or_else(|| format!("Invalid complex number:{str} use RE,IM such as -0.4,0.6"))
+}
 
-    // See blur() for an example of how to save the image
+fn positive_valid(str: &str) -> Result<f64, String> {
+    // ---
+    match str.parse::<f64>() {
+        Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+        _ => Err(format!("Invalid value:{str} must be a positive number")),
+    }
 }
 
-// This code was adapted from https://github.com/PistonDevelopers/image
-fn fractal(outfile: &String, width: u32, height: u32) -> Result<()> {
+fn fractal(
+    outfile: &String,
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    parameters: Option<&Parameters>,
+) -> Result<()> {
+    // ---
     println!("fractal: f:{outfile}, w:{width}, h:{height}");
-    let mut imgbuf = image::ImageBuffer::new(width, height);
-
-    let scale_x = 3.0 / width as f32;
-    let scale_y = 3.0 / height as f32;
 
-    // Iterate over the coordinates and pixels of the image
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        // Use red and blue to be a pretty gradient background
-        let red = (0.3 * x as f32) as u8;
-        let blue = (0.3 * y as f32) as u8;
-
-        // Use green as the fractal foreground (here is the fractal math part)
-        let cx = y as f32 * scale_x - 1.5;
-        let cy = x as f32 * scale_y - 1.5;
+    // Report whole percentages on stderr, each one only once
+    let reported = AtomicU32::new(0);
+    let img = mirage::fractal_with_progress(width, height, params, |done, total| {
+        let percent = done * 100 / total.max(1);
+        if reported.fetch_max(percent, Ordering::Relaxed) < percent {
+            eprint!("\rfractal: {percent:3}%");
+        }
+    })?;
+    eprintln!();
 
-        let c = num_complex::Complex::new(-0.4, 0.6);
-        let mut z = num_complex::Complex::new(cx, cy);
+    save_generated(&DynamicImage::ImageRgb8(img), outfile, parameters)
+}
 
-        let mut green = 0;
-        while green < 255 && z.norm() <= 2.0 {
-            z = z * z + c;
-            green += 1;
-        }
+fn animate(
+    outfile: &str,
+    format: AnimationFormat,
+    width: u32,
+    height: u32,
+    params: &FractalParams,
+    animation: &Animation,
+) -> Result<()> {
+    // ---
+    println!(
+        "animate: f:{outfile}, w:{width}, h:{height}, frames:{}",
+        animation.frames
+    );
 
-        // Actually set the pixel. red, green, and blue are u8 values!
-        *pixel = image::Rgb([red, green, blue]);
-    }
-    imgbuf.save(outfile)?;
+    animation.save(outfile, format, width, height, params, |done, total| {
+        eprint!("\ranimate: frame {done}/{total}");
+    })?;
+    eprintln!();
     Ok(())
 }
 
@@ -254,17 +1771,25 @@ mod tests {
         // ---
 
         ensure!(
-            rotate_valid("90").map_err(anyhow::Error::msg)? == 90,
+            rotate_valid("90").map_err(anyhow::Error::msg)? == 90.0,
             "90 degrees should be valid"
         );
         ensure!(
-            rotate_valid("180").map_err(anyhow::Error::msg)? == 180,
+            rotate_valid("180").map_err(anyhow::Error::msg)? == 180.0,
             "180 degrees should be valid"
         );
         ensure!(
-            rotate_valid("270").map_err(anyhow::Error::msg)? == 270,
+            rotate_valid("270").map_err(anyhow::Error::msg)? == 270.0,
             "270 degrees should be valid"
         );
+        ensure!(
+            rotate_valid("12.5").map_err(anyhow::Error::msg)? == 12.5,
+            "Arbitrary angles should be valid"
+        );
+        ensure!(
+            rotate_valid("-90").map_err(anyhow::Error::msg)? == -90.0,
+            "Negative angles rotate counterclockwise"
+        );
         Ok(())
     }
 
@@ -272,16 +1797,12 @@ mod tests {
     fn test_rotate_valid_rejects_invalid_degrees() -> Result<()> {
         // ---
 
-        ensure!(rotate_valid("45").is_err(), "45 degrees should be invalid");
-        ensure!(
-            rotate_valid("360").is_err(),
-            "360 degrees should be invalid"
-        );
-        ensure!(rotate_valid("0").is_err(), "0 degrees should be invalid");
+        ensure!(rotate_valid("inf").is_err(), "Infinity should be invalid");
         ensure!(
-            rotate_valid("-90").is_err(),
-            "negative degrees should be invalid"
+            rotate_valid("-inf").is_err(),
+            "Negative infinity should be invalid"
         );
+        ensure!(rotate_valid("NaN").is_err(), "NaN should be invalid");
         Ok(())
     }
 
@@ -294,8 +1815,8 @@ mod tests {
             "non-numeric input should be invalid"
         );
         ensure!(
-            rotate_valid("90.5").is_err(),
-            "decimal input should be invalid"
+            rotate_valid("90,5").is_err(),
+            "decimal comma should be invalid"
         );
         ensure!(rotate_valid("").is_err(), "empty input should be invalid");
         ensure!(
@@ -309,17 +1830,17 @@ mod tests {
     fn test_rotate_valid_error_messages() -> Result<()> {
         // ---
 
-        let result = rotate_valid("45");
-        ensure!(result.is_err(), "45 should produce an error");
+        let result = rotate_valid("inf");
+        ensure!(result.is_err(), "inf should produce an error");
 
         let error = result.unwrap_err();
         ensure!(
-            error.contains("Invalid rotation value:45"),
+            error.contains("Invalid rotation value:inf"),
             "Error should contain invalid rotation message"
         );
         ensure!(
-            error.contains("90, 180 or 270"),
-            "Error should list valid values"
+            error.contains("finite number of degrees"),
+            "Error should say what is valid"
         );
         Ok(())
     }
@@ -332,7 +1853,7 @@ mod tests {
         let output_path = temp_dir.path().join("test_fractal.png");
         let output_str = output_path.to_string_lossy().to_string();
 
-        fractal(&output_str, 100, 100)?;
+        fractal(&output_str, 100, 100, &FractalParams::default(), None)?;
 
         ensure!(output_path.exists(), "Fractal file should be created");
 
@@ -350,13 +1871,13 @@ mod tests {
         // Test small image
         let small_path = temp_dir.path().join("small.png");
         let small_str = small_path.to_string_lossy().to_string();
-        fractal(&small_str, 10, 10)?;
+        fractal(&small_str, 10, 10, &FractalParams::default(), None)?;
         ensure!(small_path.exists(), "Small fractal should be created");
 
         // Test larger image
         let large_path = temp_dir.path().join("large.png");
         let large_str = large_path.to_string_lossy().to_string();
-        fractal(&large_str, 200, 200)?;
+        fractal(&large_str, 200, 200, &FractalParams::default(), None)?;
         ensure!(large_path.exists(), "Large fractal should be created");
 
         // Larger image should have more bytes
@@ -370,22 +1891,152 @@ mod tests {
     }
 
     #[test]
-    fn test_generate_placeholder_behavior() -> Result<()> {
+    fn test_generate_writes_solid_color() -> Result<()> {
         // ---
 
         let temp_dir = TempDir::new()?;
         let output_path = temp_dir.path().join("test_generate.png");
         let output_str = output_path.to_string_lossy().to_string();
 
-        // Since generate is not implemented, it should return Ok but not create a file
-        generate(&output_str, 255)?;
+        let color = mirage::parse_color("#336699")?;
+        generate(&output_str, 12, 7, &Fill::Solid(color), None)?;
+
+        let img = image::open(&output_path)?.to_rgba8();
+        ensure!(
+            img.dimensions() == (12, 7),
+            "Generated image should be 12x7, got {:?}",
+            img.dimensions()
+        );
+        ensure!(
+            img.pixels().all(|p| *p == Rgba([0x33, 0x66, 0x99, 0xff])),
+            "Every pixel should have the requested color"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_generate_args_require_fill() -> Result<()> {
+        // ---
+
+        use clap::Parser;
+
+        let args = Args::try_parse_from(["mirage", "generate", "out.png"]);
+        ensure!(args.is_err(), "Generate without a fill should be rejected");
+
+        let args = Args::try_parse_from([
+            "mirage",
+            "generate",
+            "out.png",
+            "--color",
+            "red",
+            "--gradient",
+            "linear",
+        ]);
+        ensure!(args.is_err(), "--color and --gradient should conflict");
+
+        let args = Args::try_parse_from([
+            "mirage",
+            "generate",
+            "out.png",
+            "--gradient",
+            "conic",
+            "--stop",
+            "red",
+            "--stop",
+            "hsl(240, 100%, 50%)@75%",
+            "--center",
+            "0.25,0.75",
+        ]);
+        ensure!(args.is_ok(), "Gradient with stops should parse");
+
+        for side in ["--width", "--height"] {
+            let args = Args::try_parse_from([
+                "mirage", "generate", "out.png", "--color", "red", side, "0",
+            ]);
+            ensure!(args.is_err(), "{} 0 should be rejected", side);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_center_valid() -> Result<()> {
+        // ---
+
+        ensure!(center_valid("0.25, 0.75") == Ok((0.25, 0.75)));
+        ensure!(center_valid("0.5").is_err(), "Missing Y should fail");
+        ensure!(center_valid("a,b").is_err(), "Non-numeric should fail");
+        Ok(())
+    }
+
+    #[test]
+    fn test_complex_valid() -> Result<()> {
+        // ---
+
+        ensure!(complex_valid("-0.4,0.6") == Ok(Complex64::new(-0.4, 0.6)));
+        ensure!(complex_valid("-0.8+0.156i") == Ok(Complex64::new(-0.8, 0.156)));
+        ensure!(complex_valid("1.5") == Ok(Complex64::new(1.5, 0.0)));
+        ensure!(
+            complex_valid("1,x").is_err(),
+            "Non-numeric part should fail"
+        );
         ensure!(
-            !output_path.exists(),
-            "Generate should not create file until implemented"
+            complex_valid("inf,0").is_err(),
+            "Infinite value should fail"
         );
         Ok(())
     }
 
+    #[test]
+    fn test_fractal_args_parse_options() -> Result<()> {
+        // ---
+
+        use clap::Parser;
+
+        let args = Args::try_parse_from([
+            "mirage",
+            "fractal",
+            "out.png",
+            "300",
+            "200",
+            "--type",
+            "mandelbrot",
+            "--center",
+            "-0.75,0.1",
+            "--zoom",
+            "8",
+            "--max-iterations",
+            "1000",
+        ])?;
+        match args.command {
+            Command::Fractal {
+                fractal:
+                    FractalArgs {
+                        kind,
+                        center,
+                        zoom,
+                        max_iterations,
+                        ..
+                    },
+                ..
+            } => {
+                ensure!(kind == FractalKind::Mandelbrot, "Type should be parsed");
+                ensure!(center.map(|c| c.to_complex64()) == Some(Complex64::new(-0.75, 0.1)));
+                ensure!(zoom == 8.0 && max_iterations == 1000);
+            }
+            _ => anyhow::bail!("Expected Fractal command but got different command type"),
+        }
+
+        let args =
+            Args::try_parse_from(["mirage", "fractal", "out.png", "10", "10", "--zoom", "0"]);
+        ensure!(args.is_err(), "Zero zoom should be rejected");
+
+        for size in [["0", "10"], ["10", "0"]] {
+            let args = Args::try_parse_from(["mirage", "fractal", "out.png", size[0], size[1]]);
+            ensure!(args.is_err(), "{}x{} should be rejected", size[0], size[1]);
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_command_debug_formatting() -> Result<()> {
         // ---
@@ -393,7 +2044,11 @@ mod tests {
         let blur_cmd = Command::Blur {
             infile: "input.jpg".to_string(),
             outfile: "output.jpg".to_string(),
-            percent: 50,
+            amount: 50.0,
+            unit: Unit::Sigma,
+            kernel: Kernel::Gaussian,
+            angle: 0.0,
+            passes: 3,
         };
         let debug_str = format!("{:?}", blur_cmd);
 
@@ -407,7 +2062,7 @@ mod tests {
         );
         ensure!(
             debug_str.contains("50"),
-            "Debug output should contain percent value"
+            "Debug output should contain the blur amount"
         );
         Ok(())
     }
@@ -459,11 +2114,12 @@ mod tests {
             Command::Blur {
                 infile,
                 outfile,
-                percent,
+                amount,
+                ..
             } => {
                 ensure!(infile == "input.jpg", "Input filename should match");
                 ensure!(outfile == "output.jpg", "Output filename should match");
-                ensure!(percent == 50, "Percent value should match");
+                ensure!(amount == 50.0, "Blur amount should match");
             }
             _ => anyhow::bail!("Expected Blur command but got different command type"),
         }
@@ -481,6 +2137,7 @@ mod tests {
                 outfile,
                 width,
                 height,
+                ..
             } => {
                 ensure!(
                     outfile == "output.png",
@@ -494,6 +2151,110 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_parse_pipeline_steps_in_order() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> = [
+            "crop",
+            "0",
+            "0",
+            "80",
+            "60",
+            ":",
+            "blur",
+            "3",
+            ":",
+            "grayscale",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let steps = parse_pipeline(&tokens)?;
+
+        let names: Vec<&str> = steps.iter().map(Command::name).collect();
+        ensure!(
+            names == ["crop", "blur", "grayscale"],
+            "Steps should keep their order, got {:?}",
+            names
+        );
+        ensure!(
+            matches!(steps[1], Command::Blur { amount, .. } if amount == 3.0),
+            "Blur step should carry its argument"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pipeline_rejects_bad_steps() -> Result<()> {
+        // ---
+
+        let to_tokens = |s: &str| -> Vec<String> { s.split(' ').map(String::from).collect() };
+
+        ensure!(
+            parse_pipeline(&to_tokens("blur 3 : : grayscale")).is_err(),
+            "Empty step should be rejected"
+        );
+        ensure!(
+            parse_pipeline(&to_tokens("blur 3 :")).is_err(),
+            "Trailing separator should be rejected"
+        );
+        ensure!(
+            parse_pipeline(&to_tokens("emboss 3")).is_err(),
+            "Unknown operation should be rejected"
+        );
+        ensure!(
+            parse_pipeline(&to_tokens("rotate sideways")).is_err(),
+            "Step arguments should be validated like the subcommand"
+        );
+        ensure!(
+            parse_pipeline(&to_tokens("brighten -20")).is_ok(),
+            "Negative brighten amount should parse inside a pipeline"
+        );
+        for step in [
+            "blur 3 --recursive",
+            "grayscale --force",
+            "invert --output-name {stem}.png",
+            "blur -r 3",
+            "grayscale --no-auto-orient",
+        ] {
+            ensure!(
+                parse_pipeline(&to_tokens(step)).is_err(),
+                "Input options in `{}` should be rejected",
+                step
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipeline_matches_separate_commands() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_fn(40, 30, |x, y| {
+            image::Rgb([(x * 6) as u8, (y * 8) as u8, 128])
+        }));
+
+        let tokens: Vec<String> = ["crop", "5", "5", "20", "10", ":", "invert"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let piped = parse_pipeline(&tokens)?
+            .iter()
+            .map(Command::operation)
+            .collect::<Result<Pipeline>>()?
+            .apply(img.clone())?;
+
+        let mut expected = img.crop_imm(5, 5, 20, 10);
+        expected.invert();
+
+        ensure!(
+            piped.to_rgb8() == expected.to_rgb8(),
+            "Pipeline result should equal the operations applied one by one"
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_error_handling_context() -> Result<()> {
         // ---
@@ -512,4 +2273,440 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_stored_parameters_reparse_to_same_render() -> Result<()> {
+        // ---
+
+        let args = Args::try_parse_from([
+            "mirage",
+            "fractal",
+            "out.png",
+            "30",
+            "20",
+            "--type",
+            "mandelbrot",
+            "--center=-0.74,0.13",
+            "--zoom",
+            "3",
+            "--coloring",
+            "smooth",
+            "--interior",
+            "navy",
+        ])?;
+        let parameters = args
+            .command
+            .parameters()
+            .context("Fractal has parameters")?;
+        ensure!(parameters.software == metadata::SOFTWARE);
+
+        let (name, arguments) = parameters.arguments.split_first().context("Empty")?;
+        let argv = ["mirage", name, "again.png"]
+            .into_iter()
+            .chain(arguments.iter().map(String::as_str));
+        match (args.command, Args::try_parse_from(argv)?.command) {
+            (
+                Command::Fractal { width, fractal, .. },
+                Command::Fractal {
+                    outfile,
+                    width: again_width,
+                    fractal: again,
+                    ..
+                },
+            ) => {
+                ensure!(outfile == "again.png" && width == again_width);
+                ensure!(
+                    fractal.params()? == again.params()?,
+                    "Stored arguments should give the same render"
+                );
+            }
+            _ => anyhow::bail!("Expected Fractal commands"),
+        }
+
+        let generate = Args::try_parse_from([
+            "mirage",
+            "generate",
+            "out.png",
+            "--gradient",
+            "radial",
+            "--stop",
+            "red",
+            "--stop",
+            "blue@25%",
+        ])?
+        .command
+        .parameters()
+        .context("Generate has parameters")?;
+        ensure!(
+            generate
+                .arguments
+                .contains(&"--stop=#0000ffff@0.25".to_string()),
+            "Stops should be spelled out: {:?}",
+            generate.arguments
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rerender_resolution_keeps_aspect() -> Result<()> {
+        // ---
+
+        ensure!(resolution((300, 200), None, None) == (300, 200));
+        ensure!(resolution((300, 200), Some(600), None) == (600, 400));
+        ensure!(resolution((300, 200), None, Some(50)) == (75, 50));
+        ensure!(resolution((300, 200), Some(10), Some(10)) == (10, 10));
+        ensure!(resolution((300, 1), Some(3), None) == (3, 1), "Never zero");
+        Ok(())
+    }
+
+    #[test]
+    fn test_percent_valid() -> Result<()> {
+        // ---
+
+        ensure!(percent_valid("150").map_err(anyhow::Error::msg)? == 150.0);
+        ensure!(percent_valid("50%").map_err(anyhow::Error::msg)? == 50.0);
+        ensure!(
+            percent_valid("-10").is_err(),
+            "Negative percent should fail"
+        );
+        ensure!(
+            percent_valid("inf").is_err(),
+            "Infinite percent should fail"
+        );
+        ensure!(percent_valid("lots").is_err(), "Text should fail");
+        Ok(())
+    }
+
+    #[test]
+    fn test_hue_and_modulate_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> = "hue -30 : modulate --saturation 50% --model hsv"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let steps = parse_pipeline(&tokens)?;
+        ensure!(steps.len() == 2);
+        match &steps[..] {
+            [Command::Hue { degrees, .. }, modulate @ Command::Modulate { model, .. }] => {
+                ensure!(*degrees == -30.0 && *model == ColorModel::Hsv);
+                ensure!(
+                    format!("{:?}", modulate.operation()?).contains("saturation: 0.5"),
+                    "Percent should become a factor"
+                );
+            }
+            _ => anyhow::bail!("Expected hue and modulate steps, got {:?}", steps),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_resize_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> =
+            "resize 200x --filter nearest : resize 64x64 --fit cover --gravity north"
+                .split(' ')
+                .map(String::from)
+                .collect();
+        let steps = parse_pipeline(&tokens)?;
+        match &steps[..] {
+            [Command::Resize {
+                size: Size::Width(200),
+                filter: Filter::Nearest,
+                fit: Fit::Stretch,
+                ..
+            }, Command::Resize {
+                fit: Fit::Cover,
+                gravity: Gravity::North,
+                shrink_only: false,
+                ..
+            }] => {}
+            _ => anyhow::bail!("Expected two resize steps, got {:?}", steps),
+        }
+        ensure!(
+            parse_pipeline(&["resize".to_string(), "200".to_string()]).is_err(),
+            "A size needs an x"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_auto_orient_flags() -> Result<()> {
+        // ---
+
+        use clap::Parser;
+
+        let parse = |extra: &[&str]| -> Result<bool> {
+            let argv = ["mirage", "flip", "in.jpg", "out.jpg"].iter().chain(extra);
+            Ok(Args::try_parse_from(argv)?.input.auto_orient())
+        };
+        ensure!(parse(&[])?, "On by default");
+        ensure!(!parse(&["--no-auto-orient"])?);
+        ensure!(
+            parse(&["--no-auto-orient", "--auto-orient"])?,
+            "The last flag wins"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_crop_geometry_forms() -> Result<()> {
+        // ---
+
+        let strings = |region: &[&str]| region.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let classic = crop_geometry(&strings(&["10", "20", "80", "60"]), false)?;
+        ensure!(
+            classic == "80x60+10+20".parse()?,
+            "Same rectangle either way"
+        );
+        ensure!(crop_geometry(&[], true)? == Geometry::default());
+        ensure!(
+            crop_geometry(&[], false).is_err(),
+            "Needs a region or aspect"
+        );
+        ensure!(crop_geometry(&strings(&["10", "20"]), false).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trim_and_smartcrop_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> =
+            "trim --fuzz 10 : smartcrop 50%x200 --metric edges : smartcrop --aspect 1:1"
+                .split(' ')
+                .map(String::from)
+                .collect();
+        let steps = parse_pipeline(&tokens)?;
+        match &steps[..] {
+            [Command::Trim { fuzz, .. }, Command::Smartcrop {
+                size: Some((Length::Percent(width), Length::Pixels(200))),
+                aspect: None,
+                metric: Metric::Edges,
+                ..
+            }, Command::Smartcrop {
+                size: None,
+                aspect: Some(_),
+                ..
+            }] => ensure!(*fuzz == 10.0 && *width == 50.0),
+            _ => anyhow::bail!("Unexpected steps {:?}", steps),
+        }
+
+        for invalid in ["smartcrop", "smartcrop 10x10+1+1", "smartcrop 10"] {
+            let tokens: Vec<String> = invalid.split(' ').map(String::from).collect();
+            ensure!(parse_pipeline(&tokens).is_err(), "{} should fail", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharpen_and_unsharp_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> = "sharpen : sharpen 2 --amount 150% : unsharp 0.5 80 5"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(
+            operations
+                == [
+                    "Sharpen { sigma: 1.0, amount: 1.0, threshold: 0.0 }",
+                    "Sharpen { sigma: 2.0, amount: 1.5, threshold: 0.0 }",
+                    "Sharpen { sigma: 0.5, amount: 0.8, threshold: 0.05 }",
+                ],
+            "Unexpected operations {:?}",
+            operations
+        );
+        let tokens: Vec<String> = ["unsharp", "0", "80", "5"].map(String::from).to_vec();
+        ensure!(parse_pipeline(&tokens).is_err(), "Radius must be positive");
+        Ok(())
+    }
+
+    #[test]
+    fn test_tone_adjustments_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> =
+            "contrast -20 : gamma 2.2 : levels 5 95% 1.2 --channels b : curves 0,0 25,20 100,100"
+                .split(' ')
+                .map(String::from)
+                .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(operations[0] == "Contrast { amount: -0.2 }");
+        ensure!(operations[1] == "Gamma { gamma: 2.2 }");
+        ensure!(
+            operations[2].starts_with("Levels { black: 0.05, white: 0.95, gamma: 1.2")
+                && operations[2].contains("red: false, green: false, blue: true"),
+            "Unexpected levels {}",
+            operations[2]
+        );
+        ensure!(operations[3].contains("points: [(0.0, 0.0), (0.25, 0.2), (1.0, 1.0)]"));
+
+        for invalid in [
+            "contrast -101",
+            "curves 0,0",
+            "curves 0,0 50,120",
+            "levels 5",
+        ] {
+            let tokens: Vec<String> = invalid.split(' ').map(String::from).collect();
+            ensure!(parse_pipeline(&tokens).is_err(), "{} should fail", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_brighten_modes_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> =
+            "brighten -20 : brighten --percent 10 --blue -5 : brighten --exposure -1.5"
+                .split(' ')
+                .map(String::from)
+                .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(operations[0] == "Brighten { amount: -20 }");
+        ensure!(operations[1] == "Brightness { red: 0.1, green: 0.1, blue: 0.05 }");
+        ensure!(operations[2] == "Exposure { stops: -1.5 }");
+
+        for invalid in [
+            "brighten",
+            "brighten 20 --percent 5",
+            "brighten --exposure 1 --red 5",
+            "brighten --green 101",
+        ] {
+            let tokens: Vec<String> = invalid.split(' ').map(String::from).collect();
+            ensure!(parse_pipeline(&tokens).is_err(), "{} should fail", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_grayscale_options_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> = "grayscale : grayscale --weights 0.2,0.7,0.1 --amount 40"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(operations[0] == "Desaturate { weights: Rec709, amount: 1.0, keep_rgb: false }");
+        ensure!(
+            operations[1]
+                == "Desaturate { weights: Mix([0.2, 0.7, 0.1]), amount: 0.4, keep_rgb: false }"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invert_options_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> = "invert : invert --channels g --alpha : invert --lightness"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(operations[0].contains("red: true, green: true, blue: true, alpha: false"));
+        ensure!(operations[1].contains("red: false, green: true, blue: false, alpha: true"));
+        ensure!(operations[2] == "InvertLightness");
+        Ok(())
+    }
+
+    #[test]
+    fn test_toning_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> =
+            "sepia --amount 40 : duotone --shadows navy : tint #ff8000 : colorize --stop red --stop blue@80%"
+                .split(' ')
+                .map(String::from)
+                .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(operations[0] == "Sepia { amount: 0.4 }");
+        ensure!(operations[1].contains("shadows: Rgba([0, 0, 128, 255])"));
+        ensure!(operations[1].contains("highlights: Rgba([255, 255, 255, 255])"));
+        ensure!(operations[2] == "Tint { color: Rgba([255, 128, 0, 255]), amount: 1.0 }");
+        ensure!(operations[3].contains("position: Some(0.8)"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_threshold_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> = "threshold 40 : threshold --otsu : adaptive-threshold --method niblack : adaptive-threshold --k 0.5 : posterize 8"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(operations[0] == "Threshold { level: Some(0.4) }");
+        ensure!(operations[1] == "Threshold { level: None }");
+        ensure!(operations[2] == "AdaptiveThreshold { method: Niblack, window: 25, k: -0.2 }");
+        ensure!(operations[3] == "AdaptiveThreshold { method: Sauvola, window: 25, k: 0.5 }");
+        ensure!(operations[4] == "Posterize { levels: 8 }");
+
+        let tokens: Vec<String> = ["threshold"].map(String::from).to_vec();
+        ensure!(
+            parse_pipeline(&tokens).is_err(),
+            "A level or --otsu is needed"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_dither_parse() -> Result<()> {
+        // ---
+
+        let tokens: Vec<String> =
+            "dither : dither --bits 4 --method bayer2 : dither --palette black #fff --serpentine"
+                .split(' ')
+                .map(String::from)
+                .collect();
+        let operations: Vec<String> = parse_pipeline(&tokens)?
+            .iter()
+            .map(|step| Ok(format!("{:?}", step.operation()?)))
+            .collect::<Result<_>>()?;
+        ensure!(
+            operations[0]
+                == "Dither { colors: Gray(1), method: FloydSteinberg, serpentine: false }"
+        );
+        ensure!(operations[1] == "Dither { colors: Gray(4), method: Bayer2, serpentine: false }");
+        ensure!(
+            operations[2].contains("Palette([Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 255])])")
+        );
+        ensure!(operations[2].contains("serpentine: true"));
+
+        let tokens: Vec<String> = "dither --bits 2 --palette red blue"
+            .split(' ')
+            .map(String::from)
+            .collect();
+        ensure!(
+            parse_pipeline(&tokens).is_err(),
+            "Bits and a palette conflict"
+        );
+        Ok(())
+    }
 }
@@ -0,0 +1,277 @@
+//! Image metadata: render parameters stored in PNG text chunks, and the
+//! EXIF orientation of photos.
+//!
+//! Generated images record how they were made so they can be reproduced
+//! later: a `Software` tEXt chunk names the mirage version, and a
+//! `mirage:parameters` chunk holds the command that rendered the image,
+//! one argument per line, without the output file. Text that isn't
+//! Latin-1 is stored in an iTXt chunk instead.
+//!
+//! Cameras often store photos sideways and record the turn needed to show
+//! them upright in an EXIF Orientation tag. [`open`] applies that turn to
+//! the pixels. Images are written without EXIF, so the result is upright
+//! for every viewer.
+
+use crate::{Operation, Orientation};
+use anyhow::{Context, Result};
+use image::{DynamicImage, ImageFormat};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+/// Value of the `Software` chunk written by this version.
+pub const SOFTWARE: &str = concat!("mirage ", env!("CARGO_PKG_VERSION"));
+
+/// Keyword of the chunk holding the render command.
+pub const PARAMETERS_KEYWORD: &str = "mirage:parameters";
+
+/// The command that rendered an image and the version that ran it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameters {
+    /// Program and version, e.g. `mirage 0.1.2`.
+    pub software: String,
+    /// Subcommand and arguments, e.g. `["fractal", "800", "600", "--zoom=4"]`.
+    pub arguments: Vec<String>,
+}
+
+impl Parameters {
+    // ---
+
+    /// Parameters for `arguments` rendered by this version of mirage.
+    pub fn new(arguments: Vec<String>) -> Self {
+        // ---
+        Self {
+            software: SOFTWARE.to_string(),
+            arguments,
+        }
+    }
+
+    /// Write `img` to `path`, embedding the parameters when the path names
+    /// a PNG. Other formats are written without them.
+    pub fn save(&self, img: &DynamicImage, path: &Path) -> Result<()> {
+        // ---
+        if ImageFormat::from_path(path).ok() != Some(ImageFormat::Png) {
+            return img
+                .save(path)
+                .context(format!("Failed writing {}.", path.display()));
+        }
+
+        let (color, depth) = match img {
+            DynamicImage::ImageLuma8(_) => (png::ColorType::Grayscale, png::BitDepth::Eight),
+            DynamicImage::ImageLumaA8(_) => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
+            DynamicImage::ImageRgb8(_) => (png::ColorType::Rgb, png::BitDepth::Eight),
+            DynamicImage::ImageLuma16(_) => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
+            DynamicImage::ImageLumaA16(_) => {
+                (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen)
+            }
+            DynamicImage::ImageRgb16(_) => (png::ColorType::Rgb, png::BitDepth::Sixteen),
+            DynamicImage::ImageRgba16(_) => (png::ColorType::Rgba, png::BitDepth::Sixteen),
+            _ => (png::ColorType::Rgba, png::BitDepth::Eight),
+        };
+        let data = match (img, depth) {
+            // PNG stores 16-bit samples big-endian
+            (_, png::BitDepth::Sixteen) => img
+                .as_bytes()
+                .chunks_exact(2)
+                .flat_map(|pair| u16::from_ne_bytes([pair[0], pair[1]]).to_be_bytes())
+                .collect(),
+            (DynamicImage::ImageLuma8(_), _)
+            | (DynamicImage::ImageLumaA8(_), _)
+            | (DynamicImage::ImageRgb8(_), _)
+            | (DynamicImage::ImageRgba8(_), _) => img.as_bytes().to_vec(),
+            _ => img.to_rgba8().into_raw(),
+        };
+
+        let file = File::create(path).context(format!("Failed to create {}", path.display()))?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), img.width(), img.height());
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder.add_text_chunk("Software".to_string(), self.software.clone())?;
+        let text = self.arguments.join("\n");
+        if text.chars().all(|c| c <= '\u{ff}') {
+            encoder.add_text_chunk(PARAMETERS_KEYWORD.to_string(), text)?;
+        } else {
+            encoder.add_itxt_chunk(PARAMETERS_KEYWORD.to_string(), text)?;
+        }
+        let mut writer = encoder.write_header()?;
+        writer
+            .write_image_data(&data)
+            .context(format!("Failed writing {}.", path.display()))?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    /// Read the parameters embedded in the PNG at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        // ---
+        let file = File::open(path).context(format!("Failed to open {}", path.display()))?;
+        let reader = png::Decoder::new(BufReader::new(file))
+            .read_info()
+            .context(format!("{} isn't a PNG file", path.display()))?;
+        let info = reader.info();
+
+        let mut software = None;
+        let mut arguments = None;
+        let chunks = info
+            .uncompressed_latin1_text
+            .iter()
+            .map(|chunk| (chunk.keyword.as_str(), Ok(chunk.text.clone())))
+            .chain(
+                info.compressed_latin1_text
+                    .iter()
+                    .map(|chunk| (chunk.keyword.as_str(), chunk.get_text())),
+            )
+            .chain(
+                info.utf8_text
+                    .iter()
+                    .map(|chunk| (chunk.keyword.as_str(), chunk.get_text())),
+            );
+        for (keyword, text) in chunks {
+            match keyword {
+                "Software" => software = Some(text?),
+                PARAMETERS_KEYWORD => arguments = Some(text?),
+                _ => {}
+            }
+        }
+
+        let arguments = arguments.context(format!(
+            "{} has no mirage render parameters",
+            path.display()
+        ))?;
+        Ok(Self {
+            software: software.unwrap_or_default(),
+            arguments: arguments.lines().map(String::from).collect(),
+        })
+    }
+}
+
+/// The EXIF orientation of the image at `path`, `None` when the file has
+/// no readable Orientation tag.
+pub fn orientation(path: &Path) -> Option<Orientation> {
+    // ---
+    let file = File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?;
+    Orientation::from_exif(field.value.get_uint(0)?)
+}
+
+/// Open the image at `path`, turning it upright according to its EXIF
+/// orientation when `auto_orient` is set.
+pub fn open(path: &Path, auto_orient: bool) -> Result<DynamicImage> {
+    // ---
+    let img = image::open(path).context(format!("Failed to open {}", path.display()))?;
+    match orientation(path).filter(|_| auto_orient) {
+        Some(orientation) => orientation.apply(img),
+        None => Ok(img),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{GenericImageView, ImageBuffer, Rgb, RgbImage, Rgba, RgbaImage};
+    use tempfile::TempDir;
+
+    /// Write `img` as a JPEG whose EXIF Orientation tag is `value`.
+    fn write_oriented_jpeg(img: &RgbImage, path: &Path, value: u16) -> Result<()> {
+        // ---
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(img.clone())
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)?;
+
+        // APP1 segment: a little-endian TIFF header and one IFD entry
+        let mut exif = b"Exif\0\0II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        exif.extend(value.to_le_bytes());
+        exif.extend([0; 6]);
+        let mut segment = vec![0xff, 0xe1];
+        segment.extend((exif.len() as u16 + 2).to_be_bytes());
+        segment.extend(exif);
+        jpeg.splice(2..2, segment);
+        std::fs::write(path, jpeg)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parameters_roundtrip() -> Result<()> {
+        // ---
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("out.png");
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(3, 2, Rgba([1, 2, 3, 4])));
+        let parameters = Parameters::new(vec![
+            "generate".to_string(),
+            "--width=3".to_string(),
+            "--palette=palettes/秋.ggr".to_string(),
+        ]);
+        parameters.save(&img, &path)?;
+
+        ensure!(
+            Parameters::read(&path)? == parameters,
+            "Parameters should roundtrip through iTXt"
+        );
+        ensure!(image::open(&path)? == img, "Pixels should be unchanged");
+        Ok(())
+    }
+
+    #[test]
+    fn test_sixteen_bit_pixels_are_kept() -> Result<()> {
+        // ---
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("deep.png");
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(2, 2, Rgb([1, 256, 65535])));
+        Parameters::new(vec!["generate".to_string()]).save(&img, &path)?;
+        ensure!(image::open(&path)? == img, "16-bit samples should survive");
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_parameters_is_an_error() -> Result<()> {
+        // ---
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("plain.png");
+        RgbaImage::new(1, 1).save(&path)?;
+        ensure!(
+            Parameters::read(&path).is_err(),
+            "Plain PNG has no parameters"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_turns_photos_upright() -> Result<()> {
+        // ---
+
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("sideways.jpg");
+        // Stored lying on its side: white on the left, to be turned clockwise
+        let stored = RgbImage::from_fn(32, 16, |x, _| match x < 16 {
+            true => Rgb([255, 255, 255]),
+            false => Rgb([0, 0, 0]),
+        });
+        write_oriented_jpeg(&stored, &path, 6)?;
+
+        ensure!(orientation(&path) == Some(Orientation::Rotate90));
+        let upright = open(&path, true)?.to_luma8();
+        ensure!(upright.dimensions() == (16, 32), "Turned a quarter");
+        ensure!(
+            upright.get_pixel(8, 4)[0] > 200 && upright.get_pixel(8, 28)[0] < 50,
+            "White should end up on top"
+        );
+        ensure!(
+            open(&path, false)?.dimensions() == (32, 16),
+            "Kept as stored"
+        );
+
+        let plain = temp_dir.path().join("plain.png");
+        stored.save(&plain)?;
+        ensure!(orientation(&plain).is_none(), "No EXIF");
+        Ok(())
+    }
+}
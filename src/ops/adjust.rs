@@ -0,0 +1,577 @@
+use super::pixels::{linear_to_srgb, map_channels, srgb_to_linear, ChannelCurve};
+use super::Operation;
+use anyhow::Result;
+use image::DynamicImage;
+use std::str::FromStr;
+
+/// Add `amount` to every color channel, darkening for negative values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Brighten {
+    pub amount: i32,
+}
+
+impl Operation for Brighten {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(imageop!(img, brighten, self.amount))
+    }
+}
+
+/// Add a fraction of the channel range to each color channel, darkening
+/// for negative values, so the same amount has the same effect at every
+/// bit depth.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Brightness {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl Brightness {
+    // ---
+
+    /// The same `amount` for all three color channels.
+    pub fn new(amount: f32) -> Self {
+        // ---
+        Self {
+            red: amount,
+            green: amount,
+            blue: amount,
+        }
+    }
+}
+
+impl Operation for Brightness {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            [self.red, self.green, self.blue]
+                .iter()
+                .all(|a| a.is_finite()),
+            "Invalid brightness:{:?} must be finite numbers",
+            self
+        );
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for Brightness {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match channel {
+            0 => value + self.red,
+            1 => value + self.green,
+            2 => value + self.blue,
+            _ => value,
+        }
+    }
+}
+
+/// Change the exposure by `stops`: the color channels are decoded from
+/// sRGB to linear light, multiplied by 2^`stops` and encoded again, as a
+/// camera's exposure would. Black stays black and values past white clip.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exposure {
+    pub stops: f32,
+}
+
+impl Operation for Exposure {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.stops.is_finite(),
+            "Invalid exposure:{} must be a number of stops",
+            self.stops
+        );
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for Exposure {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match Channels::RGB.contains(channel) {
+            true => linear_to_srgb((srgb_to_linear(value) * self.stops.exp2()).min(1.0)),
+            false => value,
+        }
+    }
+}
+
+/// The channels an adjustment applies to, written as letters such as
+/// `rgb`, `r` or `gba`, optionally separated by commas as in `r,b`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Channels {
+    pub red: bool,
+    pub green: bool,
+    pub blue: bool,
+    pub alpha: bool,
+}
+
+impl Channels {
+    // ---
+
+    /// The three color channels together.
+    pub const RGB: Self = Self {
+        red: true,
+        green: true,
+        blue: true,
+        alpha: false,
+    };
+
+    /// Whether channel number `channel` (0 red to 3 alpha) is selected.
+    pub fn contains(self, channel: usize) -> bool {
+        // ---
+        [self.red, self.green, self.blue, self.alpha]
+            .get(channel)
+            .copied()
+            .unwrap_or(false)
+    }
+}
+
+impl Default for Channels {
+    // ---
+    fn default() -> Self {
+        Self::RGB
+    }
+}
+
+impl FromStr for Channels {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let mut channels = Self {
+            red: false,
+            green: false,
+            blue: false,
+            alpha: false,
+        };
+        for letter in text.trim().chars().filter(|&c| c != ',') {
+            match letter.to_ascii_lowercase() {
+                'r' => channels.red = true,
+                'g' => channels.green = true,
+                'b' => channels.blue = true,
+                'a' => channels.alpha = true,
+                _ => anyhow::bail!("Invalid channels {}, use letters of rgba", text),
+            }
+        }
+        anyhow::ensure!(
+            (0..4).any(|channel| channels.contains(channel)),
+            "Invalid channels {}, use letters of rgba",
+            text
+        );
+        Ok(channels)
+    }
+}
+
+/// Scale the distance of every color value from middle gray by
+/// 1 + `amount`: 0.5 raises contrast by half, -1 leaves flat gray.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Contrast {
+    pub amount: f32,
+}
+
+impl Operation for Contrast {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.amount.is_finite() && self.amount >= -1.0,
+            "Invalid contrast:{} must be -100% or more",
+            self.amount * 100.0
+        );
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for Contrast {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match Channels::RGB.contains(channel) {
+            true => (value - 0.5) * (1.0 + self.amount) + 0.5,
+            false => value,
+        }
+    }
+}
+
+/// Gamma correction: every color value `v` becomes `v^(1 / gamma)`, so
+/// values above 1 brighten the midtones and values below 1 darken them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Gamma {
+    pub gamma: f32,
+}
+
+impl Operation for Gamma {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.gamma.is_finite() && self.gamma > 0.0,
+            "Invalid gamma:{} must be a positive number",
+            self.gamma
+        );
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for Gamma {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match Channels::RGB.contains(channel) {
+            true => value.powf(1.0 / self.gamma),
+            false => value,
+        }
+    }
+}
+
+/// Stretch the values between `black` and `white` (fractions of the
+/// channel range) to the full range, clipping the rest, and bend the
+/// midtones by `gamma` as [`Gamma`] does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Levels {
+    pub black: f32,
+    pub white: f32,
+    pub gamma: f32,
+    pub channels: Channels,
+}
+
+impl Levels {
+    // ---
+
+    /// Levels of the color channels together, without a gamma change.
+    pub fn new(black: f32, white: f32) -> Self {
+        // ---
+        Self {
+            black,
+            white,
+            gamma: 1.0,
+            channels: Channels::RGB,
+        }
+    }
+}
+
+impl Operation for Levels {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.black)
+                && (0.0..=1.0).contains(&self.white)
+                && self.black < self.white,
+            "Invalid levels: the black point {}% must be below the white point {}%, both 0-100",
+            self.black * 100.0,
+            self.white * 100.0
+        );
+        anyhow::ensure!(
+            self.gamma.is_finite() && self.gamma > 0.0,
+            "Invalid gamma:{} must be a positive number",
+            self.gamma
+        );
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for Levels {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match self.channels.contains(channel) {
+            true => ((value - self.black) / (self.white - self.black))
+                .clamp(0.0, 1.0)
+                .powf(1.0 / self.gamma),
+            false => value,
+        }
+    }
+}
+
+/// Map values through a curve passing through `points`, given as
+/// (input, output) fractions of the channel range.
+///
+/// The curve is a monotone cubic spline, smooth through the points
+/// without overshooting between them, and flat beyond the first and last
+/// point. At least two points with different inputs are needed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curves {
+    pub points: Vec<(f32, f32)>,
+    pub channels: Channels,
+}
+
+impl Operation for Curves {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        let spline = Spline::new(&self.points)?;
+        Ok(map_channels(
+            img,
+            &CurvesMap {
+                spline,
+                channels: self.channels,
+            },
+            false,
+        ))
+    }
+}
+
+struct CurvesMap {
+    spline: Spline,
+    channels: Channels,
+}
+
+impl ChannelCurve for CurvesMap {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match self.channels.contains(channel) {
+            true => self.spline.at(value),
+            false => value,
+        }
+    }
+}
+
+/// A monotone cubic Hermite spline (Fritsch-Carlson).
+struct Spline {
+    points: Vec<(f32, f32)>,
+    tangents: Vec<f32>,
+}
+
+impl Spline {
+    // ---
+    fn new(points: &[(f32, f32)]) -> Result<Self> {
+        // ---
+        let mut points = points.to_vec();
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        anyhow::ensure!(
+            points
+                .iter()
+                .all(|&(x, y)| (0.0..=1.0).contains(&x) && (0.0..=1.0).contains(&y)),
+            "Curve points must lie within 0-100%"
+        );
+        anyhow::ensure!(
+            points.len() >= 2 && points.windows(2).all(|pair| pair[0].0 < pair[1].0),
+            "A curve needs at least two points with different inputs"
+        );
+
+        let slopes: Vec<f32> = points
+            .windows(2)
+            .map(|pair| (pair[1].1 - pair[0].1) / (pair[1].0 - pair[0].0))
+            .collect();
+        let last = slopes.len() - 1;
+        let mut tangents: Vec<f32> = (0..points.len())
+            .map(|i| match i {
+                0 => slopes[0],
+                i if i > last => slopes[last],
+                i if slopes[i - 1] * slopes[i] <= 0.0 => 0.0,
+                i => (slopes[i - 1] + slopes[i]) / 2.0,
+            })
+            .collect();
+
+        // Limit the tangents so no segment overshoots its end points
+        for (i, &slope) in slopes.iter().enumerate() {
+            if slope == 0.0 {
+                tangents[i] = 0.0;
+                tangents[i + 1] = 0.0;
+                continue;
+            }
+            let (a, b) = (tangents[i] / slope, tangents[i + 1] / slope);
+            let length = (a * a + b * b).sqrt();
+            if length > 3.0 {
+                tangents[i] = 3.0 * a / length * slope;
+                tangents[i + 1] = 3.0 * b / length * slope;
+            }
+        }
+        Ok(Self { points, tangents })
+    }
+
+    fn at(&self, x: f32) -> f32 {
+        // ---
+        let points = &self.points;
+        let segment = points.partition_point(|point| point.0 <= x);
+        if segment == 0 {
+            return points[0].1;
+        }
+        if segment == points.len() {
+            return points[segment - 1].1;
+        }
+
+        let (i, j) = (segment - 1, segment);
+        let width = points[j].0 - points[i].0;
+        let t = (x - points[i].0) / width;
+        let (t2, t3) = (t * t, t * t * t);
+        (2.0 * t3 - 3.0 * t2 + 1.0) * points[i].1
+            + (t3 - 2.0 * t2 + t) * width * self.tangents[i]
+            + (-2.0 * t3 + 3.0 * t2) * points[j].1
+            + (t3 - t2) * width * self.tangents[j]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{ImageBuffer, Luma, Rgb, RgbImage};
+
+    fn gray(value: u8) -> DynamicImage {
+        // ---
+        DynamicImage::ImageLuma8(ImageBuffer::from_pixel(2, 2, Luma([value])))
+    }
+
+    fn value(img: DynamicImage) -> u8 {
+        // ---
+        img.to_luma8().get_pixel(0, 0)[0]
+    }
+
+    #[test]
+    fn test_channels_parsing() -> Result<()> {
+        // ---
+
+        ensure!("rgb".parse::<Channels>()? == Channels::RGB);
+        let blue: Channels = "B".parse()?;
+        ensure!(blue.contains(2) && !blue.contains(0) && !blue.contains(3));
+        ensure!("rgba".parse::<Channels>()?.contains(3));
+        ensure!("r,b".parse::<Channels>()? == "rb".parse()?);
+        ensure!("rgx".parse::<Channels>().is_err() && "".parse::<Channels>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_brightness_is_depth_independent() -> Result<()> {
+        // ---
+
+        ensure!(Brightness::new(0.1).apply(gray(100)).map(value)? == 126);
+        ensure!(Brightness::new(-0.5).apply(gray(100)).map(value)? == 0);
+        let deep = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(2, 2, Luma([100 * 257])));
+        let deep = Brightness::new(0.1).apply(deep)?.into_luma16();
+        ensure!(deep.get_pixel(0, 0)[0] == 32254, "The same step on 16-bit");
+
+        let warmer = Brightness {
+            red: 0.1,
+            green: 0.0,
+            blue: -0.1,
+        }
+        .apply(DynamicImage::ImageRgb8(RgbImage::from_pixel(
+            1,
+            1,
+            Rgb([100; 3]),
+        )))?;
+        ensure!(warmer.to_rgb8().get_pixel(0, 0).0 == [126, 100, 75]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_exposure_in_linear_light() -> Result<()> {
+        // ---
+
+        let exposure = |stops, v| Exposure { stops }.apply(gray(v)).map(value);
+        ensure!(exposure(1.0, 0)? == 0, "Black stays black");
+        ensure!(
+            exposure(1.0, 128)? == 176,
+            "Twice the light, not twice the value"
+        );
+        ensure!(exposure(-1.0, 175)? == 128);
+        ensure!(exposure(3.0, 200)? == 255, "Highlights clip");
+        ensure!(Exposure { stops: f32::NAN }.apply(gray(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_contrast_and_gamma() -> Result<()> {
+        // ---
+
+        let contrast = |amount, v| Contrast { amount }.apply(gray(v)).map(value);
+        ensure!(contrast(1.0, 63)? == 0 && contrast(1.0, 192)? == 255);
+        ensure!(contrast(-0.5, 0)? == 64, "Lower contrast moves toward gray");
+        ensure!(contrast(-1.0, 200)? == 128, "-100% is flat gray");
+        ensure!(contrast(-1.5, 200).is_err());
+
+        ensure!(Gamma { gamma: 2.0 }.apply(gray(64)).map(value)? == 128);
+        ensure!(Gamma { gamma: 1.0 }.apply(gray(77)).map(value)? == 77);
+        ensure!(Gamma { gamma: 0.0 }.apply(gray(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_levels_per_channel() -> Result<()> {
+        // ---
+
+        let levels = Levels::new(0.25, 0.75);
+        ensure!(levels.apply(gray(63)).map(value)? == 0);
+        ensure!(
+            (128..=129).contains(&levels.apply(gray(128)).map(value)?),
+            "The middle stays in the middle"
+        );
+        ensure!(levels.apply(gray(200)).map(value)? == 255);
+
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([100, 100, 100])));
+        let red = Levels {
+            channels: "r".parse()?,
+            ..Levels::new(0.0, 0.5)
+        }
+        .apply(img)?;
+        ensure!(red.to_rgb8().get_pixel(0, 0).0 == [200, 100, 100]);
+        ensure!(Levels::new(0.6, 0.4).apply(gray(1)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_curves_pass_through_points_without_overshoot() -> Result<()> {
+        // ---
+
+        let spline = Spline::new(&[(1.0, 1.0), (0.0, 0.0), (0.25, 0.15), (0.75, 0.85)])?;
+        for (x, y) in [(0.0, 0.0), (0.25, 0.15), (0.75, 0.85), (1.0, 1.0)] {
+            ensure!(
+                (spline.at(x) - y).abs() < 1e-6,
+                "Curve misses ({}, {})",
+                x,
+                y
+            );
+        }
+        let samples: Vec<f32> = (0..=100).map(|i| spline.at(i as f32 / 100.0)).collect();
+        ensure!(
+            samples.windows(2).all(|pair| pair[0] <= pair[1] + 1e-6),
+            "An increasing S-curve stays increasing"
+        );
+
+        let flat = Spline::new(&[(0.2, 0.5), (0.8, 0.5)])?;
+        ensure!(flat.at(0.0) == 0.5 && flat.at(0.5) == 0.5 && flat.at(1.0) == 0.5);
+        ensure!(Spline::new(&[(0.5, 0.5)]).is_err(), "One point");
+        ensure!(
+            Spline::new(&[(0.5, 0.1), (0.5, 0.9)]).is_err(),
+            "Same input"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_lookup_is_exact_on_16_bit() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageLuma16(ImageBuffer::from_fn(256, 1, |x, _| {
+            Luma([(x * 257 + 1) as u16])
+        }));
+        let curves = Curves {
+            points: vec![(0.0, 0.0), (1.0, 1.0)],
+            channels: Channels::RGB,
+        };
+        ensure!(
+            curves.apply(img.clone())? == img,
+            "The identity changes nothing"
+        );
+        let inverted = Curves {
+            points: vec![(0.0, 1.0), (1.0, 0.0)],
+            ..curves
+        }
+        .apply(img)?
+        .into_luma16();
+        ensure!(inverted.get_pixel(0, 0)[0] == 65534);
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+//! Crops that find their own rectangle: [`Trim`] removes uniform borders
+//! and [`SmartCrop`] keeps the most detailed part of an image.
+//!
+//! Both only choose the rectangle and leave the cutting to [`Crop`].
+
+use super::geometry::{Aspect, Crop, Length};
+use super::Operation;
+use anyhow::Result;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, GrayImage};
+use rayon::prelude::*;
+
+/// Remove the borders that have the color of the top left corner.
+///
+/// A pixel belongs to the border when none of its channels, alpha
+/// included, differs from the corner by more than `fuzz`, a fraction of
+/// the channel range. An image that is uniform all over is left as it is.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Trim {
+    pub fuzz: f32,
+}
+
+impl Trim {
+    // ---
+
+    /// The rectangle of `img` inside its borders, `None` when the whole
+    /// image is border.
+    pub fn rect(&self, img: &DynamicImage) -> Option<Crop> {
+        // ---
+        let rgba = img.to_rgba32f();
+        let width = rgba.width() as usize;
+        let corner = *rgba.get_pixel(0, 0);
+        let differs = |pixel: &[f32]| {
+            pixel
+                .iter()
+                .zip(corner.0)
+                .any(|(channel, border)| (channel - border).abs() > self.fuzz)
+        };
+
+        // First and last column of content in each row that has any
+        let rows: Vec<(usize, usize, usize)> = rgba
+            .as_raw()
+            .par_chunks(width * 4)
+            .enumerate()
+            .filter_map(|(y, row)| {
+                let first = row.chunks_exact(4).position(&differs)?;
+                let last = row.chunks_exact(4).rposition(&differs)?;
+                Some((y, first, last))
+            })
+            .collect();
+
+        let (top, bottom) = (rows.first()?.0, rows.last()?.0);
+        let left = rows.iter().map(|row| row.1).min()?;
+        let right = rows.iter().map(|row| row.2).max()?;
+        Some(Crop {
+            x: left as u32,
+            y: top as u32,
+            width: (right - left + 1) as u32,
+            height: (bottom - top + 1) as u32,
+        })
+    }
+}
+
+impl Operation for Trim {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        match self.rect(&img) {
+            Some(crop) => crop.apply(img),
+            None => Ok(img),
+        }
+    }
+}
+
+/// How [`SmartCrop`] measures the detail in a candidate crop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Metric {
+    /// variety of brightness levels, favors textured and busy regions
+    #[default]
+    Entropy,
+    /// sum of brightness gradients, favors sharp outlines
+    Edges,
+}
+
+/// Crop to `width` x `height`, narrowed to `aspect` when given, at the
+/// position where the crop holds the most detail by `metric`.
+///
+/// The search runs on a copy scaled down to at most [`SmartCrop::SEARCH_SIZE`]
+/// pixels on its longer side. Among equally detailed crops the one closest
+/// to the center wins, so a featureless image is cropped in the middle.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SmartCrop {
+    pub width: Length,
+    pub height: Length,
+    pub aspect: Option<Aspect>,
+    pub metric: Metric,
+}
+
+impl SmartCrop {
+    // ---
+
+    /// Longer side of the scaled copy the search runs on.
+    pub const SEARCH_SIZE: u32 = 256;
+
+    /// The most detailed crop of the largest size of `aspect`.
+    pub fn new(aspect: Aspect) -> Self {
+        // ---
+        Self {
+            width: Length::Percent(100.0),
+            height: Length::Percent(100.0),
+            aspect: Some(aspect),
+            metric: Metric::default(),
+        }
+    }
+
+    /// The chosen rectangle of `img`. It is returned as is when it doesn't
+    /// fit, and rejected when applied.
+    pub fn rect(&self, img: &DynamicImage) -> Crop {
+        // ---
+        let (width, height) = img.dimensions();
+        let mut size = (self.width.resolve(width), self.height.resolve(height));
+        if let Some(aspect) = self.aspect {
+            size = aspect.fit(size);
+        }
+        let centered = Crop {
+            x: width.saturating_sub(size.0) / 2,
+            y: height.saturating_sub(size.1) / 2,
+            width: size.0,
+            height: size.1,
+        };
+        if size.0 == 0 || size.1 == 0 || size.0 > width || size.1 > height {
+            return centered;
+        }
+
+        let scale = (Self::SEARCH_SIZE as f64 / width.max(height) as f64).min(1.0);
+        let shrink = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+        let small = img
+            .resize_exact(shrink(width), shrink(height), FilterType::Triangle)
+            .to_luma8();
+        let window = (
+            shrink(size.0).min(small.width()),
+            shrink(size.1).min(small.height()),
+        );
+        let (x, y) = best_window(&small, window, self.metric);
+
+        Crop {
+            x: ((x as f64 / scale).round() as u32).min(width - size.0),
+            y: ((y as f64 / scale).round() as u32).min(height - size.1),
+            ..centered
+        }
+    }
+}
+
+impl Operation for SmartCrop {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        self.rect(&img).apply(img)
+    }
+}
+
+/// Number of brightness levels the entropy is measured over.
+const ENTROPY_BINS: usize = 16;
+
+/// Top left corner of the `window` sized area of `img` with the highest
+/// score, preferring the center among equal scores.
+fn best_window(img: &GrayImage, window: (u32, u32), metric: Metric) -> (u32, u32) {
+    // ---
+    let (width, height) = img.dimensions();
+    let score: Box<dyn Fn(u32, u32) -> f64> = match metric {
+        Metric::Edges => {
+            let luma = |x: u32, y: u32| img.get_pixel(x, y)[0] as f64;
+            let table = SummedArea::new(width, height, |x, y| {
+                let right = luma((x + 1).min(width - 1), y);
+                let below = luma(x, (y + 1).min(height - 1));
+                (right - luma(x, y)).abs() + (below - luma(x, y)).abs()
+            });
+            Box::new(move |x, y| table.sum(x, y, window))
+        }
+        Metric::Entropy => {
+            let tables: Vec<SummedArea> = (0..ENTROPY_BINS)
+                .map(|bin| {
+                    SummedArea::new(width, height, |x, y| {
+                        let level = img.get_pixel(x, y)[0] as usize * ENTROPY_BINS / 256;
+                        (level == bin) as u8 as f64
+                    })
+                })
+                .collect();
+            let total = (window.0 * window.1) as f64;
+            Box::new(move |x, y| {
+                tables
+                    .iter()
+                    .map(|table| table.sum(x, y, window) / total)
+                    .filter(|&p| p > 0.0)
+                    .map(|p| -p * p.log2())
+                    .sum()
+            })
+        }
+    };
+
+    let center = |x: u32, y: u32| {
+        let dx = x as f64 - (width - window.0) as f64 / 2.0;
+        let dy = y as f64 - (height - window.1) as f64 / 2.0;
+        dx * dx + dy * dy
+    };
+    let mut best = (f64::NEG_INFINITY, f64::INFINITY, (0, 0));
+    for y in 0..=height - window.1 {
+        for x in 0..=width - window.0 {
+            let candidate = (score(x, y), center(x, y), (x, y));
+            let better = candidate.0 > best.0 + 1e-9
+                || (candidate.0 > best.0 - 1e-9 && candidate.1 < best.1);
+            if better {
+                best = candidate;
+            }
+        }
+    }
+    best.2
+}
+
+/// Sums of a per-pixel value over any rectangle in constant time.
+pub(super) struct SummedArea {
+    width: usize,
+    sums: Vec<f64>,
+}
+
+impl SummedArea {
+    // ---
+    pub(super) fn new(width: u32, height: u32, value: impl Fn(u32, u32) -> f64) -> Self {
+        // ---
+        let stride = width as usize + 1;
+        let mut sums = vec![0.0; stride * (height as usize + 1)];
+        for y in 0..height {
+            let mut row = 0.0;
+            for x in 0..width {
+                row += value(x, y);
+                let index = (y as usize + 1) * stride + x as usize + 1;
+                sums[index] = sums[index - stride] + row;
+            }
+        }
+        Self {
+            width: stride,
+            sums,
+        }
+    }
+
+    /// Sum over the `size` rectangle with its top left corner at (`x`, `y`).
+    pub(super) fn sum(&self, x: u32, y: u32, size: (u32, u32)) -> f64 {
+        // ---
+        let at = |x: u32, y: u32| self.sums[y as usize * self.width + x as usize];
+        let (right, bottom) = (x + size.0, y + size.1);
+        at(right, bottom) - at(x, bottom) - at(right, y) + at(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{Luma, Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn test_trim_removes_borders() -> Result<()> {
+        // ---
+
+        // A 4x3 red block at (5, 2) on white, with one off-white speck
+        let mut img = RgbImage::from_pixel(12, 8, Rgb([255, 255, 255]));
+        for (x, y) in (5..9).flat_map(|x| (2..5).map(move |y| (x, y))) {
+            img.put_pixel(x, y, Rgb([200, 0, 0]));
+        }
+        img.put_pixel(1, 7, Rgb([250, 250, 250]));
+        let img = DynamicImage::ImageRgb8(img);
+
+        let strict = Trim::default().rect(&img);
+        ensure!(
+            strict
+                == Some(Crop {
+                    x: 1,
+                    y: 2,
+                    width: 8,
+                    height: 6
+                }),
+            "The speck counts without fuzz: {:?}",
+            strict
+        );
+        let trimmed = Trim { fuzz: 0.05 }.apply(img)?;
+        ensure!(trimmed.dimensions() == (4, 3), "Only the block is left");
+        ensure!(trimmed.color() == image::ColorType::Rgb8, "Type is kept");
+        Ok(())
+    }
+
+    #[test]
+    fn test_trim_keeps_uniform_images() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(5, 5, Rgba([1, 2, 3, 0])));
+        ensure!(Trim::default().rect(&img).is_none());
+        ensure!(Trim::default().apply(img.clone())? == img);
+        Ok(())
+    }
+
+    #[test]
+    fn test_smartcrop_finds_detail() -> Result<()> {
+        // ---
+
+        // Flat gray with a checkered patch near the right edge
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(300, 100, |x, y| {
+            match (220..280).contains(&x) && (20..80).contains(&y) {
+                true => Luma([((x / 4 + y / 4) % 2 * 255) as u8]),
+                false => Luma([128]),
+            }
+        }));
+        for metric in [Metric::Entropy, Metric::Edges] {
+            let crop = SmartCrop {
+                metric,
+                ..SmartCrop::new("1:1".parse()?)
+            }
+            .rect(&img);
+            ensure!(crop.width == 100 && crop.height == 100);
+            ensure!(
+                crop.x >= 180 && crop.x <= 200,
+                "{:?} should keep the patch, got {:?}",
+                metric,
+                crop
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_smartcrop_centers_featureless_images() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageLuma8(GrayImage::from_pixel(90, 40, Luma([7])));
+        let crop = SmartCrop {
+            width: Length::Pixels(30),
+            height: Length::Pixels(20),
+            aspect: None,
+            metric: Metric::Edges,
+        };
+        ensure!(
+            crop.rect(&img)
+                == Crop {
+                    x: 30,
+                    y: 10,
+                    width: 30,
+                    height: 20
+                }
+        );
+        let too_big = SmartCrop {
+            width: Length::Pixels(100),
+            ..crop
+        };
+        ensure!(too_big.apply(img).is_err(), "Larger than the image");
+        Ok(())
+    }
+}
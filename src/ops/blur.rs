@@ -0,0 +1,677 @@
+//! Blurs: the plain gaussian [`Blur`], [`KernelBlur`] with a choice of
+//! kernel and of the unit its size is given in, and [`SpinBlur`] and
+//! [`ZoomBlur`] around a center.
+//!
+//! Except for the plain gaussian, colors are blurred premultiplied by
+//! alpha, so transparent pixels don't bleed their color into their
+//! neighbors, and at the input's own bit depth.
+
+use super::geometry::sample;
+use super::pixels::{
+    linear_to_srgb, map_rgba, premultiply, srgb_to_linear, to_unit, unpremultiply, RgbaBuffer,
+    RgbaMap,
+};
+use super::Operation;
+use anyhow::Result;
+use image::{DynamicImage, GenericImageView, Pixel, Primitive, Rgba};
+use rayon::prelude::*;
+
+/// Gaussian blur with standard deviation `sigma` in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Blur {
+    pub sigma: f32,
+}
+
+impl Operation for Blur {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(imageop!(img, blur, self.sigma))
+    }
+}
+
+/// Shape of a [`KernelBlur`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Kernel {
+    /// smooth gaussian falloff
+    #[default]
+    Gaussian,
+    /// flat square average in repeated passes, fast at any size
+    Box,
+    /// streak along a line at an angle, like a camera moving
+    Motion,
+    /// flat disc averaged in linear light, like an out of focus lens
+    Bokeh,
+}
+
+/// Unit of the amount of a [`KernelBlur`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Unit {
+    /// standard deviation in pixels, so every kernel spreads about as far
+    /// as a gaussian of that sigma
+    #[default]
+    Sigma,
+    /// radius of the kernel in pixels: 3 sigma of a gaussian, the box or
+    /// disc radius, or half the length of a motion streak
+    Radius,
+    /// radius as a percentage of the image diagonal
+    Percent,
+}
+
+/// Blur with `kernel`, sized by `amount` in `unit`.
+///
+/// A motion blur streaks along `angle`, in degrees clockwise from
+/// horizontal, and a box blur is repeated `passes` times: three passes look
+/// close to a gaussian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KernelBlur {
+    pub kernel: Kernel,
+    pub amount: f32,
+    pub unit: Unit,
+    pub angle: f32,
+    pub passes: u32,
+}
+
+impl KernelBlur {
+    // ---
+
+    /// Blur with `kernel` by a sigma of `amount` pixels, horizontally for
+    /// a motion blur and in three passes for a box blur.
+    pub fn new(kernel: Kernel, amount: f32) -> Self {
+        // ---
+        Self {
+            kernel,
+            amount,
+            unit: Unit::default(),
+            angle: 0.0,
+            passes: 3,
+        }
+    }
+
+    /// Radius of the kernel in pixels on an image of `size`.
+    pub fn radius(&self, size: (u32, u32)) -> f32 {
+        // ---
+        let sigma = self.amount;
+        match (self.unit, self.kernel) {
+            (Unit::Radius, _) => self.amount,
+            (Unit::Percent, _) => {
+                let diagonal = (size.0 as f32).hypot(size.1 as f32);
+                self.amount / 100.0 * diagonal
+            }
+            (Unit::Sigma, Kernel::Gaussian) => 3.0 * sigma,
+            (Unit::Sigma, Kernel::Box) => {
+                // Each pass of width 2r + 1 adds ((2r + 1)^2 - 1) / 12 to the variance
+                let passes = self.passes.max(1) as f32;
+                ((12.0 * sigma * sigma / passes + 1.0).sqrt() - 1.0) / 2.0
+            }
+            (Unit::Sigma, Kernel::Motion) => 3f32.sqrt() * sigma,
+            (Unit::Sigma, Kernel::Bokeh) => 2.0 * sigma,
+        }
+    }
+}
+
+impl Operation for KernelBlur {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.amount.is_finite() && self.amount >= 0.0,
+            "Invalid blur:{} must be a number of 0 or more",
+            self.amount
+        );
+        anyhow::ensure!(
+            self.unit != Unit::Percent || self.amount <= 100.0,
+            "Invalid blur:{}% must be at most 100% of the diagonal",
+            self.amount
+        );
+        anyhow::ensure!(
+            self.angle.is_finite(),
+            "Invalid blur angle:{} must be a finite number of degrees",
+            self.angle
+        );
+        anyhow::ensure!(self.passes > 0, "A box blur needs at least one pass");
+
+        let radius = self.radius(img.dimensions());
+        if radius <= 0.0 {
+            return Ok(img);
+        }
+        match self.kernel {
+            Kernel::Gaussian => {
+                let sigma = match self.unit {
+                    Unit::Sigma => self.amount,
+                    _ => radius / 3.0,
+                };
+                Blur { sigma }.apply(img)
+            }
+            Kernel::Box => Ok(map_rgba(
+                img,
+                &BoxKernel {
+                    radius: radius.round() as usize,
+                    passes: self.passes,
+                },
+                false,
+            )),
+            Kernel::Motion => {
+                let (sin, cos) = (self.angle as f64).to_radians().sin_cos();
+                let radius = radius as f64;
+                let path = |x: f64, y: f64, t: f64| {
+                    let offset = (2.0 * t - 1.0) * radius;
+                    (x + offset * cos, y + offset * sin)
+                };
+                Ok(map_rgba(
+                    img,
+                    &PathKernel {
+                        length: |_, _| 2.0 * radius,
+                        path,
+                    },
+                    false,
+                ))
+            }
+            Kernel::Bokeh => Ok(map_rgba(img, &DiscKernel { radius }, false)),
+        }
+    }
+}
+
+/// Blur in circles around `center`, given as fractions of the image size,
+/// over an arc of `angle` degrees, like an image spinning as it's taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpinBlur {
+    pub angle: f32,
+    pub center: (f32, f32),
+}
+
+impl Operation for SpinBlur {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.angle.is_finite() && self.angle.abs() <= 360.0,
+            "Invalid spin:{} must be between -360 and 360 degrees",
+            self.angle
+        );
+        let (cx, cy) = center(&img, self.center);
+        let angle = (self.angle as f64).to_radians();
+        let length = |x: f64, y: f64| (x - cx).hypot(y - cy) * angle.abs();
+        let path = |x: f64, y: f64, t: f64| {
+            let (sin, cos) = ((t - 0.5) * angle).sin_cos();
+            let (dx, dy) = (x - cx, y - cy);
+            (cx + dx * cos - dy * sin, cy + dx * sin + dy * cos)
+        };
+        Ok(map_rgba(img, &PathKernel { length, path }, false))
+    }
+}
+
+/// Blur along the lines through `center`, given as fractions of the image
+/// size, as if zooming in while the image is taken: every pixel is averaged
+/// with the pixels up to `amount` of its distance closer to the center.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ZoomBlur {
+    pub amount: f32,
+    pub center: (f32, f32),
+}
+
+impl Operation for ZoomBlur {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.amount),
+            "Invalid zoom:{}% must be 0-100",
+            self.amount * 100.0
+        );
+        let (cx, cy) = center(&img, self.center);
+        let amount = self.amount as f64;
+        let length = |x: f64, y: f64| (x - cx).hypot(y - cy) * amount;
+        let path = |x: f64, y: f64, t: f64| {
+            let scale = 1.0 - t * amount;
+            (cx + (x - cx) * scale, cy + (y - cy) * scale)
+        };
+        Ok(map_rgba(img, &PathKernel { length, path }, false))
+    }
+}
+
+/// Pixel position of `center`, given as fractions of the size of `img`.
+fn center(img: &DynamicImage, center: (f32, f32)) -> (f64, f64) {
+    // ---
+    let (width, height) = img.dimensions();
+    (
+        center.0 as f64 * width as f64,
+        center.1 as f64 * height as f64,
+    )
+}
+
+/// Separable box blur of `radius` pixels, repeated `passes` times.
+struct BoxKernel {
+    radius: usize,
+    passes: u32,
+}
+
+impl RgbaMap for BoxKernel {
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if self.radius == 0 || width == 0 || height == 0 {
+            return img;
+        }
+        let mut colors: Vec<[f32; 4]> = img.pixels().map(|p| premultiply(*p)).collect();
+        for _ in 0..self.passes {
+            colors = colors
+                .par_chunks(width)
+                .flat_map_iter(|row| box_line(row, self.radius))
+                .collect();
+            let columns: Vec<Vec<[f32; 4]>> = (0..width)
+                .into_par_iter()
+                .map(|x| {
+                    let column: Vec<[f32; 4]> =
+                        (0..height).map(|y| colors[y * width + x]).collect();
+                    box_line(&column, self.radius)
+                })
+                .collect();
+            colors
+                .par_chunks_mut(width)
+                .enumerate()
+                .for_each(|(y, row)| {
+                    for (x, color) in row.iter_mut().enumerate() {
+                        *color = columns[x][y];
+                    }
+                });
+        }
+        restore(img, &colors)
+    }
+}
+
+/// Every color of `line` averaged with the `radius` colors on either side,
+/// repeating the end colors past the ends.
+fn box_line(line: &[[f32; 4]], radius: usize) -> Vec<[f32; 4]> {
+    // ---
+    let last = line.len() as isize - 1;
+    let at = |index: isize| line[index.clamp(0, last) as usize];
+    let radius = radius as isize;
+    let count = (2 * radius + 1) as f64;
+
+    // A running sum in f64 so long lines don't drift
+    let mut sum = [0.0f64; 4];
+    for index in -radius..=radius {
+        for (total, channel) in sum.iter_mut().zip(at(index)) {
+            *total += channel as f64;
+        }
+    }
+    (0..line.len() as isize)
+        .map(|index| {
+            let average = sum.map(|total| (total / count) as f32);
+            let (entering, leaving) = (at(index + radius + 1), at(index - radius));
+            for channel in 0..4 {
+                sum[channel] += entering[channel] as f64 - leaving[channel] as f64;
+            }
+            average
+        })
+        .collect()
+}
+
+/// Disc blur of `radius` pixels, averaging in linear light so that bright
+/// highlights spread into bright discs.
+///
+/// The radius is cut to the image diagonal, at which the disc of every
+/// pixel already covers the whole image, so that the time taken is bounded.
+struct DiscKernel {
+    radius: f32,
+}
+
+impl RgbaMap for DiscKernel {
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if width == 0 || height == 0 {
+            return img;
+        }
+
+        // Prefix sums of every row of premultiplied linear colors
+        let stride = width + 1;
+        let mut sums = vec![[0.0f64; 4]; stride * height];
+        sums.par_chunks_mut(stride)
+            .zip(img.par_chunks(width * 4))
+            .for_each(|(sums, row)| {
+                for (x, pixel) in row.chunks_exact(4).enumerate() {
+                    let alpha = to_unit(pixel[3]) as f64;
+                    let mut next = sums[x];
+                    for channel in 0..3 {
+                        next[channel] += srgb_to_linear(to_unit(pixel[channel])) as f64 * alpha;
+                    }
+                    next[3] += alpha;
+                    sums[x + 1] = next;
+                }
+            });
+
+        let radius = self.radius.min((width as f32).hypot(height as f32));
+        let reach = radius.floor() as isize;
+        let spans: Vec<(isize, isize)> = (-reach..=reach)
+            .map(|dy| {
+                let half = (radius * radius - (dy * dy) as f32).sqrt().floor();
+                (dy, half as isize)
+            })
+            .collect();
+        let count: f64 = spans.iter().map(|(_, half)| (2 * half + 1) as f64).sum();
+        let (last_x, last_y) = (width as isize - 1, height as isize - 1);
+
+        let mut out = RgbaBuffer::<T>::new(width as u32, height as u32);
+        out.par_chunks_mut(width * 4)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    let mut total = [0.0f64; 4];
+                    for &(dy, half) in &spans {
+                        let sy = (y as isize + dy).clamp(0, last_y) as usize;
+                        let sums = &sums[sy * stride..(sy + 1) * stride];
+                        let (left, right) = (x as isize - half, x as isize + half);
+
+                        // Past the ends the edge pixels repeat
+                        let before = (-left).max(0) as f64;
+                        let after = (right - last_x).max(0) as f64;
+                        let (left, right) = (left.max(0) as usize, right.min(last_x) as usize);
+                        for channel in 0..4 {
+                            let first = sums[1][channel] - sums[0][channel];
+                            let last = sums[width][channel] - sums[width - 1][channel];
+                            total[channel] += sums[right + 1][channel] - sums[left][channel]
+                                + before * first
+                                + after * last;
+                        }
+                    }
+                    let alpha = total[3] / count;
+                    let mut color = [0.0f32; 4];
+                    if alpha > 0.0 {
+                        for channel in 0..3 {
+                            let linear = (total[channel] / count / alpha) as f32;
+                            color[channel] = linear_to_srgb(linear.clamp(0.0, 1.0)) * alpha as f32;
+                        }
+                        color[3] = alpha as f32;
+                    }
+                    pixel.copy_from_slice(&unpremultiply::<T>(color).0);
+                }
+            });
+        out
+    }
+}
+
+/// Most samples taken along the path of a [`PathKernel`].
+const MAX_SAMPLES: usize = 256;
+
+/// Average of every pixel over a path: `path(x, y, t)` is the point for `t`
+/// from 0 to 1 of the path of the pixel centered at (`x`, `y`), and
+/// `length(x, y)` its length in pixels, which sets how many samples are
+/// taken.
+struct PathKernel<L, P> {
+    length: L,
+    path: P,
+}
+
+impl<L, P> RgbaMap for PathKernel<L, P>
+where
+    L: Fn(f64, f64) -> f64 + Sync,
+    P: Fn(f64, f64, f64) -> (f64, f64) + Sync,
+{
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        if width == 0 || height == 0 {
+            return img;
+        }
+        let colors: Vec<[f32; 4]> = img.pixels().map(|p| premultiply(*p)).collect();
+        let fetch = |x: i64, y: i64| -> [f32; 4] {
+            let x = x.clamp(0, width as i64 - 1) as usize;
+            let y = y.clamp(0, height as i64 - 1) as usize;
+            colors[y * width + x]
+        };
+
+        let mut out = img;
+        out.par_chunks_mut(width * 4)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    let (cx, cy) = (x as f64 + 0.5, y as f64 + 0.5);
+                    let length = (self.length)(cx, cy);
+                    let samples = ((length.ceil() as usize).saturating_add(1)).min(MAX_SAMPLES);
+                    if samples < 2 {
+                        continue;
+                    }
+                    let mut total = [0.0f32; 4];
+                    for index in 0..samples {
+                        let t = index as f64 / (samples - 1) as f64;
+                        let (sx, sy) = (self.path)(cx, cy, t);
+                        let color = sample(sx, sy, 1, &fetch, |t| [1.0 - t, t]);
+                        for (total, channel) in total.iter_mut().zip(color) {
+                            *total += channel;
+                        }
+                    }
+                    let color = total.map(|total| total / samples as f32);
+                    pixel.copy_from_slice(&unpremultiply::<T>(color).0);
+                }
+            });
+        out
+    }
+}
+
+/// `img` with its pixels replaced by the premultiplied `colors`.
+fn restore<T>(mut img: RgbaBuffer<T>, colors: &[[f32; 4]]) -> RgbaBuffer<T>
+where
+    T: Primitive + Send + Sync,
+    Rgba<T>: Pixel<Subpixel = T>,
+{
+    // ---
+    img.par_chunks_mut(4)
+        .zip(colors.par_iter())
+        .for_each(|(pixel, color)| pixel.copy_from_slice(&unpremultiply::<T>(*color).0));
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{GrayImage, ImageBuffer, Luma, RgbaImage};
+
+    /// Vertical black and white stripes, `width` pixels each.
+    fn stripes(width: u32) -> GrayImage {
+        // ---
+        GrayImage::from_fn(40, 40, |x, _| Luma([(x / width % 2 * 255) as u8]))
+    }
+
+    #[test]
+    fn test_radius_units() -> Result<()> {
+        // ---
+
+        let blur = |kernel, unit| KernelBlur {
+            unit,
+            ..KernelBlur::new(kernel, 2.0)
+        };
+        ensure!(blur(Kernel::Gaussian, Unit::Sigma).radius((9, 9)) == 6.0);
+        ensure!(blur(Kernel::Bokeh, Unit::Sigma).radius((9, 9)) == 4.0);
+        ensure!(blur(Kernel::Box, Unit::Radius).radius((9, 9)) == 2.0);
+        ensure!(
+            blur(Kernel::Motion, Unit::Percent).radius((300, 400)) == 10.0,
+            "2% of a 500 pixel diagonal"
+        );
+
+        // Three box passes of radius 2 have a variance of 3 * (25 - 1) / 12
+        let sigma = KernelBlur::new(Kernel::Box, 6f32.sqrt()).radius((9, 9));
+        ensure!((sigma - 2.0).abs() < 1e-5, "Box radius {}", sigma);
+        Ok(())
+    }
+
+    #[test]
+    fn test_kernels_blur_along_their_shape() -> Result<()> {
+        // ---
+
+        let flat = DynamicImage::ImageLuma8(GrayImage::from_pixel(20, 20, Luma([90])));
+        for kernel in [Kernel::Gaussian, Kernel::Box, Kernel::Motion, Kernel::Bokeh] {
+            let blurred = KernelBlur::new(kernel, 3.0).apply(flat.clone())?;
+            ensure!(blurred == flat, "{:?} keeps a flat image", kernel);
+        }
+
+        // A horizontal streak blurs vertical stripes but not horizontal ones
+        let streak = KernelBlur {
+            unit: Unit::Radius,
+            ..KernelBlur::new(Kernel::Motion, 4.0)
+        };
+        let across = streak.apply(DynamicImage::ImageLuma8(stripes(2)))?;
+        let across = across.into_luma8();
+        ensure!((8..32).all(|x| (100..156).contains(&across.get_pixel(x, 20)[0])));
+        let rotated = DynamicImage::ImageLuma8(image::imageops::rotate90(&stripes(2)));
+        ensure!(
+            streak.apply(rotated.clone())? == rotated,
+            "Along the stripes"
+        );
+        let vertical = KernelBlur {
+            angle: 90.0,
+            ..streak
+        };
+        ensure!(
+            vertical
+                .apply(DynamicImage::ImageLuma8(stripes(2)))?
+                .to_luma8()
+                == stripes(2)
+        );
+
+        let boxed = KernelBlur {
+            unit: Unit::Radius,
+            passes: 1,
+            ..KernelBlur::new(Kernel::Box, 1.0)
+        }
+        .apply(DynamicImage::ImageLuma8(stripes(3)))?
+        .into_luma8();
+        ensure!(boxed.get_pixel(4, 5)[0] == 255 && boxed.get_pixel(3, 5)[0] == 170);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bokeh_spreads_highlights_into_discs() -> Result<()> {
+        // ---
+
+        let mut dark = GrayImage::new(21, 21);
+        dark.put_pixel(10, 10, Luma([255]));
+        let disc = KernelBlur {
+            unit: Unit::Radius,
+            ..KernelBlur::new(Kernel::Bokeh, 5.0)
+        }
+        .apply(DynamicImage::ImageLuma8(dark))?
+        .into_luma8();
+
+        let center = disc.get_pixel(10, 10)[0];
+        ensure!(center > 0, "The highlight spreads");
+        ensure!(
+            [(15, 10), (10, 5), (13, 14)]
+                .iter()
+                .all(|&(x, y)| disc.get_pixel(x, y)[0] == center),
+            "Evenly over the disc"
+        );
+        ensure!(disc.get_pixel(14, 14)[0] == 0 && disc.get_pixel(16, 10)[0] == 0);
+        ensure!(center > 255 / 81, "Brighter than an average in gamma space");
+
+        // Beyond the diagonal a disc is cut to it, however large the amount
+        let img = DynamicImage::ImageLuma8(stripes(3));
+        let bokeh = |radius| {
+            KernelBlur {
+                unit: Unit::Radius,
+                ..KernelBlur::new(Kernel::Bokeh, radius)
+            }
+            .apply(img.clone())
+        };
+        let diagonal = 40f32.hypot(40.0);
+        ensure!(bokeh(1e9)? == bokeh(diagonal)?);
+        ensure!(bokeh(diagonal)? != bokeh(diagonal / 2.0)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blurs_keep_depth_and_alpha() -> Result<()> {
+        // ---
+
+        // Opaque red next to transparent green: the green must not bleed in
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(12, 4, |x, _| match x < 6 {
+            true => Rgba([200, 0, 0, 255]),
+            false => Rgba([0, 255, 0, 0]),
+        }));
+        let blurs: Vec<Box<dyn Operation>> = vec![
+            Box::new(KernelBlur::new(Kernel::Box, 2.0)),
+            Box::new(KernelBlur::new(Kernel::Motion, 2.0)),
+            Box::new(KernelBlur::new(Kernel::Bokeh, 2.0)),
+            Box::new(SpinBlur {
+                angle: 90.0,
+                center: (0.5, 0.5),
+            }),
+            Box::new(ZoomBlur {
+                amount: 0.5,
+                center: (1.0, 0.5),
+            }),
+        ];
+        for blur in &blurs {
+            let blurred = blur.apply(img.clone())?.into_rgba8();
+            let edge = blurred.get_pixel(5, 1);
+            ensure!(edge[1] == 0 && edge[3] < 255, "{:?} gave {:?}", blur, edge);
+        }
+
+        let deep = DynamicImage::ImageLuma16(ImageBuffer::from_fn(20, 4, |x, _| {
+            Luma([if x < 10 { 15000u16 } else { 45000 }])
+        }));
+        for blur in &blurs {
+            let blurred = blur.apply(deep.clone())?;
+            ensure!(blurred.color() == image::ColorType::L16, "{:?}", blur);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_spin_and_zoom_around_the_center() -> Result<()> {
+        // ---
+
+        // A disc around the center keeps its shape, only its jagged edge
+        // is smoothed
+        let distance = |x: u32, y: u32| (x as f32 - 20.0).hypot(y as f32 - 20.0);
+        let disc = GrayImage::from_fn(41, 41, |x, y| {
+            Luma([if distance(x, y) < 10.0 { 200 } else { 30 }])
+        });
+        let spun = SpinBlur {
+            angle: 45.0,
+            center: (0.5, 0.5),
+        }
+        .apply(DynamicImage::ImageLuma8(disc.clone()))?
+        .into_luma8();
+        ensure!(spun
+            .enumerate_pixels()
+            .filter(|(x, y, _)| (distance(*x, *y) - 10.0).abs() > 1.5)
+            .all(|(x, y, p)| p[0].abs_diff(disc.get_pixel(x, y)[0]) <= 1));
+
+        // Zooming blurs stripes crossing the lines to the center, but not the
+        // white stripe the center lies in
+        let zoomed = ZoomBlur {
+            amount: 0.3,
+            center: (0.475, 0.5),
+        }
+        .apply(DynamicImage::ImageLuma8(stripes(2)))?
+        .into_luma8();
+        ensure!(zoomed.get_pixel(18, 0)[0] == 255 && zoomed.get_pixel(19, 39)[0] == 255);
+        ensure!((60..200).contains(&zoomed.get_pixel(1, 20)[0]));
+
+        let invalid = ZoomBlur {
+            amount: 1.5,
+            center: (0.5, 0.5),
+        };
+        ensure!(invalid.apply(DynamicImage::ImageLuma8(disc)).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,559 @@
+use super::adjust::Channels;
+use super::pixels::{
+    from_unit, linear_to_srgb, map_channels, map_rgba, srgb_to_linear, to_unit, ChannelCurve,
+    RgbaBuffer, RgbaMap,
+};
+use super::Operation;
+use crate::color::{
+    hsl_to_rgb, hsv_to_rgb, linear_to_oklab, oklab_to_linear, rgb_to_hsl, rgb_to_hsv,
+};
+use anyhow::Result;
+use image::{ColorType, DynamicImage, Pixel, Primitive, Rgba};
+use rayon::prelude::*;
+use std::str::FromStr;
+
+/// Invert the color channels, leaving alpha untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Invert;
+
+impl Operation for Invert {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(imageop_mut!(img, invert))
+    }
+}
+
+/// Invert the selected `channels` only, which may include alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InvertChannels {
+    pub channels: Channels,
+}
+
+impl Operation for InvertChannels {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for InvertChannels {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        match self.channels.contains(channel) {
+            true => 1.0 - value,
+            false => value,
+        }
+    }
+}
+
+/// Swap light and dark while keeping hues, as for a dark mode version of
+/// a diagram: the lightness of every color is inverted in the perceptual
+/// OKLab color space, so white turns black, black turns white and a
+/// saturated red stays red.
+///
+/// Where the inverted color falls outside the RGB gamut its chroma is
+/// reduced until it fits, keeping hue and lightness. Alpha is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvertLightness;
+
+impl Operation for InvertLightness {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(map_rgba(img, self, false))
+    }
+}
+
+impl RgbaMap for InvertLightness {
+    // ---
+    fn map<T>(&self, mut img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        img.par_chunks_mut(4).for_each(|pixel| {
+            let linear = [pixel[0], pixel[1], pixel[2]].map(|c| srgb_to_linear(to_unit(c)));
+            let [lightness, a, b] = linear_to_oklab(linear);
+            let lightness = (1.0 - lightness).clamp(0.0, 1.0);
+            let rgb = fit_gamut(lightness, a, b).map(|c| linear_to_srgb(c.clamp(0.0, 1.0)));
+            for (channel, value) in pixel.iter_mut().zip(rgb) {
+                *channel = from_unit(value);
+            }
+        });
+        img
+    }
+}
+
+/// The linear RGB color of the OKLab color (`lightness`, `a`, `b`), with
+/// its chroma scaled down by bisection until it lies in the gamut.
+fn fit_gamut(lightness: f32, a: f32, b: f32) -> [f32; 3] {
+    // ---
+    let inside = |rgb: [f32; 3]| rgb.iter().all(|c| (-1e-4..=1.0 + 1e-4).contains(c));
+    let rgb = oklab_to_linear([lightness, a, b]);
+    if inside(rgb) {
+        return rgb;
+    }
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..16 {
+        let scale = (low + high) / 2.0;
+        match inside(oklab_to_linear([lightness, a * scale, b * scale])) {
+            true => low = scale,
+            false => high = scale,
+        }
+    }
+    oklab_to_linear([lightness, a * low, b * low])
+}
+
+/// Convert to a grayscale (luma) image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Grayscale;
+
+impl Operation for Grayscale {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(imageop!(img, grayscale))
+    }
+}
+
+/// How [`Desaturate`] turns a color into a gray value.
+///
+/// Parsed from `rec601`, `rec709`, `average` or `lightness`, or from
+/// `R,G,B` channel mixer weights such as `0.5,0.5,0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Weights {
+    /// luma of SD video and JPEG: 0.299 R + 0.587 G + 0.114 B
+    Rec601,
+    /// luma of HD video and sRGB, as [`Grayscale`] uses
+    Rec709,
+    /// the mean of red, green and blue
+    Average,
+    /// the mean of the largest and smallest channel, the L of HSL
+    Lightness,
+    /// custom weights of red, green and blue, which may be negative or add
+    /// up to more than 1
+    Mix([f32; 3]),
+}
+
+impl Weights {
+    // ---
+
+    /// The gray value of a 0..=1 `rgb` color.
+    pub fn gray(self, rgb: [f32; 3]) -> f32 {
+        // ---
+        let [r, g, b] = rgb;
+        let mix = |[wr, wg, wb]: [f32; 3]| wr * r + wg * g + wb * b;
+        match self {
+            Self::Rec601 => mix([0.299, 0.587, 0.114]),
+            Self::Rec709 => mix([0.2126, 0.7152, 0.0722]),
+            Self::Average => (r + g + b) / 3.0,
+            Self::Lightness => (r.max(g).max(b) + r.min(g).min(b)) / 2.0,
+            Self::Mix(weights) => mix(weights),
+        }
+    }
+}
+
+impl FromStr for Weights {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        match text.trim().to_ascii_lowercase().as_str() {
+            "rec601" | "601" => return Ok(Self::Rec601),
+            "rec709" | "709" => return Ok(Self::Rec709),
+            "average" => return Ok(Self::Average),
+            "lightness" => return Ok(Self::Lightness),
+            _ => {}
+        }
+        let weights: Vec<f32> = text
+            .split(',')
+            .map(|weight| weight.trim().parse::<f32>())
+            .collect::<Result<_, _>>()
+            .unwrap_or_default();
+        match weights[..] {
+            [r, g, b] if weights.iter().all(|w| w.is_finite()) => Ok(Self::Mix([r, g, b])),
+            _ => anyhow::bail!(
+                "Invalid weights {}, use rec601, rec709, average, lightness or R,G,B",
+                text
+            ),
+        }
+    }
+}
+
+/// Take the color out of an image by `amount`, from 0 for none to 1 for
+/// gray, turning each color into the gray value of its `weights`.
+///
+/// A fully gray result becomes a grayscale (luma) image unless `keep_rgb`
+/// is set, which keeps the color type for tinting later; partially
+/// desaturated images always keep it. Alpha and bit depth are kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Desaturate {
+    pub weights: Weights,
+    pub amount: f32,
+    pub keep_rgb: bool,
+}
+
+impl Desaturate {
+    // ---
+
+    /// Full desaturation by `weights` into a grayscale image.
+    pub fn new(weights: Weights) -> Self {
+        // ---
+        Self {
+            weights,
+            amount: 1.0,
+            keep_rgb: false,
+        }
+    }
+}
+
+impl Operation for Desaturate {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&self.amount),
+            "Invalid desaturation:{}% must be 0-100",
+            self.amount * 100.0
+        );
+        let gray = self.amount >= 1.0 && !self.keep_rgb;
+        if gray && self.weights == Weights::Rec709 {
+            return Grayscale.apply(img);
+        }
+
+        let img = map_rgba(img, self, false);
+        Ok(match (gray, img.color()) {
+            (false, _) => img,
+            (true, ColorType::L8 | ColorType::Rgb8) => DynamicImage::ImageLuma8(img.to_luma8()),
+            (true, ColorType::La8 | ColorType::Rgba8) => {
+                DynamicImage::ImageLumaA8(img.to_luma_alpha8())
+            }
+            (true, ColorType::L16 | ColorType::Rgb16) => DynamicImage::ImageLuma16(img.to_luma16()),
+            (true, ColorType::La16 | ColorType::Rgba16) => {
+                DynamicImage::ImageLumaA16(img.to_luma_alpha16())
+            }
+            // There are no float grayscale images
+            (true, _) => img,
+        })
+    }
+}
+
+impl RgbaMap for Desaturate {
+    // ---
+    fn map<T>(&self, mut img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        img.par_chunks_mut(4).for_each(|pixel| {
+            let rgb = [pixel[0], pixel[1], pixel[2]].map(to_unit);
+            let gray = self.weights.gray(rgb);
+            for (channel, value) in pixel.iter_mut().zip(rgb) {
+                *channel = from_unit(value + self.amount * (gray - value));
+            }
+        });
+        img
+    }
+}
+
+/// Color model in which [`Modulate`] adjusts saturation and lightness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum ColorModel {
+    /// hue, saturation, lightness: 100% lightness is white
+    #[default]
+    Hsl,
+    /// hue, saturation, value: 100% value is the pure color
+    Hsv,
+}
+
+/// Rotate hue by `degrees` around the color wheel, keeping saturation,
+/// lightness and alpha.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hue {
+    pub degrees: f32,
+}
+
+impl Operation for Hue {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        Modulate {
+            hue: self.degrees,
+            ..Modulate::default()
+        }
+        .apply(img)
+    }
+}
+
+/// Rotate hue by `hue` degrees and scale saturation and lightness (or
+/// value) by the given factors, 1 leaving them unchanged.
+///
+/// Works at the bit depth of the input and leaves alpha untouched.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Modulate {
+    pub hue: f32,
+    pub saturation: f32,
+    pub lightness: f32,
+    pub model: ColorModel,
+}
+
+impl Default for Modulate {
+    // ---
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            saturation: 1.0,
+            lightness: 1.0,
+            model: ColorModel::Hsl,
+        }
+    }
+}
+
+impl Operation for Modulate {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.hue.is_finite() && self.saturation >= 0.0 && self.lightness >= 0.0,
+            "Invalid modulation {:?}",
+            self
+        );
+        Ok(map_rgba(img, self, false))
+    }
+}
+
+impl RgbaMap for Modulate {
+    // ---
+    fn map<T>(&self, mut img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        img.par_chunks_mut(4).for_each(|pixel| {
+            let rgb = [pixel[0], pixel[1], pixel[2]].map(to_unit);
+            let rgb = match self.model {
+                ColorModel::Hsl => {
+                    let [h, s, l] = rgb_to_hsl(rgb);
+                    hsl_to_rgb(
+                        h + self.hue,
+                        (s * self.saturation).clamp(0.0, 1.0),
+                        (l * self.lightness).clamp(0.0, 1.0),
+                    )
+                }
+                ColorModel::Hsv => {
+                    let [h, s, v] = rgb_to_hsv(rgb.map(f64::from));
+                    let h = (h + self.hue as f64 / 360.0).rem_euclid(1.0);
+                    let s = (s * self.saturation as f64).clamp(0.0, 1.0);
+                    let v = (v * self.lightness as f64).clamp(0.0, 1.0);
+                    hsv_to_rgb([h, s, v]).map(|c| c as f32)
+                }
+            };
+            for (channel, value) in pixel.iter_mut().zip(rgb) {
+                *channel = from_unit(value);
+            }
+        });
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{ImageBuffer, RgbaImage};
+
+    #[test]
+    fn test_hue_rotation_moves_primaries() -> Result<()> {
+        // ---
+
+        let red = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([255, 0, 0, 128])));
+        let rotated = Hue { degrees: 120.0 }.apply(red.clone())?.to_rgba8();
+        ensure!(
+            *rotated.get_pixel(0, 0) == Rgba([0, 255, 0, 128]),
+            "Red turns green"
+        );
+        let back = Hue { degrees: -120.0 }.apply(DynamicImage::ImageRgba8(rotated))?;
+        ensure!(back == red, "Negative degrees rotate back, alpha is kept");
+        Ok(())
+    }
+
+    #[test]
+    fn test_modulate_keeps_sixteen_bits() -> Result<()> {
+        // ---
+
+        let pixel = image::Rgb([40000u16, 20000, 10001]);
+        let img = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(1, 1, pixel));
+        for model in [ColorModel::Hsl, ColorModel::Hsv] {
+            let same = Modulate {
+                model,
+                ..Modulate::default()
+            }
+            .apply(img.clone())?;
+            ensure!(
+                same == img,
+                "Neutral modulation should be lossless in {:?}",
+                model
+            );
+        }
+
+        let gray = Modulate {
+            saturation: 0.0,
+            ..Modulate::default()
+        }
+        .apply(img)?
+        .into_rgb16();
+        let [r, g, b] = gray.get_pixel(0, 0).0;
+        ensure!(r == g && g == b, "Zero saturation should be gray");
+        ensure!(
+            r == 25000 || r == 25001,
+            "HSL gray keeps lightness, got {}",
+            r
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_value_scales_in_hsv() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgb8(image::RgbImage::from_pixel(
+            1,
+            1,
+            image::Rgb([200, 100, 50]),
+        ));
+        let darker = Modulate {
+            lightness: 0.5,
+            model: ColorModel::Hsv,
+            ..Modulate::default()
+        }
+        .apply(img)?
+        .into_rgb8();
+        ensure!(*darker.get_pixel(0, 0) == image::Rgb([100, 50, 25]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_desaturate_weights() -> Result<()> {
+        // ---
+
+        ensure!("Rec601".parse::<Weights>()? == Weights::Rec601);
+        ensure!("0.5, 0.5,0".parse::<Weights>()? == Weights::Mix([0.5, 0.5, 0.0]));
+        for invalid in ["rec2020", "1,2", "1,2,x", ""] {
+            ensure!(
+                invalid.parse::<Weights>().is_err(),
+                "{} should fail",
+                invalid
+            );
+        }
+
+        let green =
+            DynamicImage::ImageRgb8(image::RgbImage::from_pixel(1, 1, image::Rgb([0, 255, 0])));
+        let gray = |weights| -> Result<u8> {
+            let img = Desaturate::new(weights).apply(green.clone())?;
+            ensure!(
+                img.color() == ColorType::L8,
+                "{:?} gives a luma image",
+                weights
+            );
+            Ok(img.into_luma8().get_pixel(0, 0)[0])
+        };
+        ensure!(gray(Weights::Rec601)? == 150);
+        ensure!(gray(Weights::Rec709)? == 182);
+        ensure!(gray(Weights::Average)? == 85);
+        ensure!(gray(Weights::Lightness)? == 128);
+        ensure!(gray(Weights::Mix([0.0, 1.2, 0.0]))? == 255, "Clipped");
+        ensure!(Desaturate::new(Weights::Rec709).apply(green.clone())? == Grayscale.apply(green)?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_desaturate_keeps_rgb() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            1,
+            1,
+            Rgba([60000u16, 20000, 10000, 30000]),
+        ));
+        let kept = Desaturate {
+            keep_rgb: true,
+            ..Desaturate::new(Weights::Average)
+        }
+        .apply(img.clone())?;
+        ensure!(kept.color() == ColorType::Rgba16);
+        ensure!(kept.into_rgba16().get_pixel(0, 0).0 == [30000, 30000, 30000, 30000]);
+
+        let half = Desaturate {
+            amount: 0.5,
+            ..Desaturate::new(Weights::Average)
+        }
+        .apply(img.clone())?;
+        ensure!(
+            half.color() == ColorType::Rgba16,
+            "Partial keeps the color type"
+        );
+        ensure!(half.into_rgba16().get_pixel(0, 0).0 == [45000, 25000, 20000, 30000]);
+
+        let gray = Desaturate::new(Weights::Average).apply(img)?;
+        ensure!(gray.color() == ColorType::La16);
+        ensure!(gray.into_luma_alpha16().get_pixel(0, 0).0 == [30000, 30000]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invert_channels() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_pixel(1, 1, Rgba([10, 20, 30, 200])));
+        let invert = |channels: &str| -> Result<[u8; 4]> {
+            let op = InvertChannels {
+                channels: channels.parse()?,
+            };
+            Ok(op.apply(img.clone())?.into_rgba8().get_pixel(0, 0).0)
+        };
+        ensure!(invert("rgb")? == Invert.apply(img.clone())?.into_rgba8().get_pixel(0, 0).0);
+        ensure!(invert("r,b")? == [245, 20, 225, 200]);
+        ensure!(invert("rgba")? == [245, 235, 225, 55]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_invert_lightness_keeps_hues() -> Result<()> {
+        // ---
+
+        let colors = [
+            Rgba([255, 255, 255, 255]),
+            Rgba([0, 0, 0, 128]),
+            Rgba([200, 30, 30, 255]),
+            Rgba([40, 60, 150, 255]),
+        ];
+        let img = DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 1, |x, _| colors[x as usize]));
+        let inverted = InvertLightness.apply(img.clone())?.into_rgba8();
+        ensure!(
+            *inverted.get_pixel(0, 0) == Rgba([0, 0, 0, 255]),
+            "White turns black"
+        );
+        ensure!(
+            *inverted.get_pixel(1, 0) == Rgba([255, 255, 255, 128]),
+            "Black turns white, alpha is kept"
+        );
+
+        for x in 2..4 {
+            let (before, after) = (colors[x as usize].0, inverted.get_pixel(x, 0).0);
+            let hue = |c: [u8; 4]| rgb_to_hsl([c[0], c[1], c[2]].map(|v| v as f32 / 255.0))[0];
+            ensure!(
+                ((hue(before) - hue(after) + 180.0).rem_euclid(360.0) - 180.0).abs() < 8.0,
+                "{:?} turned into {:?}",
+                before,
+                after
+            );
+        }
+        ensure!(inverted.get_pixel(3, 0)[2] > 150, "A dark blue turns light");
+        Ok(())
+    }
+}
@@ -0,0 +1,469 @@
+//! Reducing an image to the few gray levels or colors of a device, such as
+//! an e-ink panel or a thermal printer, while keeping the look of the
+//! tones it can't show by dithering.
+
+use super::Operation;
+use anyhow::Result;
+use image::{DynamicImage, GenericImageView, GrayAlphaImage, GrayImage, Luma, LumaA, Pixel};
+use image::{Rgb, RgbImage, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// How [`Dither`] spreads the difference between the image and the colors
+/// it can use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Dithering {
+    /// error diffusion to 4 neighbors, the classic choice
+    #[default]
+    FloydSteinberg,
+    /// error diffusion of 3/4 of the error to 6 neighbors, crisper with more
+    /// contrast, as on early Macs
+    Atkinson,
+    /// error diffusion to 12 neighbors, smoother but slower
+    #[value(alias = "jjn")]
+    JarvisJudiceNinke,
+    /// ordered dithering with a 2x2 Bayer matrix
+    Bayer2,
+    /// ordered dithering with a 4x4 Bayer matrix
+    Bayer4,
+    /// ordered dithering with an 8x8 Bayer matrix
+    Bayer8,
+}
+
+/// Neighbors (`dx`, `dy`) that receive a share of the error of a pixel.
+type Diffusion = [(i64, usize, f32)];
+
+const FLOYD_STEINBERG: &Diffusion = &[
+    (1, 0, 7.0 / 16.0),
+    (-1, 1, 3.0 / 16.0),
+    (0, 1, 5.0 / 16.0),
+    (1, 1, 1.0 / 16.0),
+];
+
+const ATKINSON: &Diffusion = &[
+    (1, 0, 1.0 / 8.0),
+    (2, 0, 1.0 / 8.0),
+    (-1, 1, 1.0 / 8.0),
+    (0, 1, 1.0 / 8.0),
+    (1, 1, 1.0 / 8.0),
+    (0, 2, 1.0 / 8.0),
+];
+
+const JARVIS_JUDICE_NINKE: &Diffusion = &[
+    (1, 0, 7.0 / 48.0),
+    (2, 0, 5.0 / 48.0),
+    (-2, 1, 3.0 / 48.0),
+    (-1, 1, 5.0 / 48.0),
+    (0, 1, 7.0 / 48.0),
+    (1, 1, 5.0 / 48.0),
+    (2, 1, 3.0 / 48.0),
+    (-2, 2, 1.0 / 48.0),
+    (-1, 2, 3.0 / 48.0),
+    (0, 2, 5.0 / 48.0),
+    (1, 2, 3.0 / 48.0),
+    (2, 2, 1.0 / 48.0),
+];
+
+impl Dithering {
+    // ---
+
+    /// The error diffusion of the method, `None` for ordered dithering.
+    fn diffusion(self) -> Option<&'static Diffusion> {
+        // ---
+        match self {
+            Self::FloydSteinberg => Some(FLOYD_STEINBERG),
+            Self::Atkinson => Some(ATKINSON),
+            Self::JarvisJudiceNinke => Some(JARVIS_JUDICE_NINKE),
+            Self::Bayer2 | Self::Bayer4 | Self::Bayer8 => None,
+        }
+    }
+
+    /// Side of the Bayer matrix of an ordered method.
+    fn matrix_size(self) -> usize {
+        // ---
+        match self {
+            Self::Bayer2 => 2,
+            Self::Bayer4 => 4,
+            _ => 8,
+        }
+    }
+}
+
+/// The colors [`Dither`] reduces an image to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Colors {
+    /// evenly spaced gray levels of this many bits per pixel, 1 to 8
+    Gray(u8),
+    /// these colors; their alpha is ignored
+    Palette(Vec<Rgba<u8>>),
+}
+
+/// Reduce an image to `colors`, dithering the difference by `method`.
+///
+/// Gray levels are matched against the luma of the image, as
+/// [`Grayscale`](super::Grayscale) computes it, and give an 8-bit grayscale
+/// image; palette colors are matched in RGB and give an 8-bit RGB image.
+/// Either keeps the alpha of the input. With `serpentine`, error diffusion
+/// runs every other row from right to left, which breaks up the diagonal
+/// patterns of scanning in one direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dither {
+    pub colors: Colors,
+    pub method: Dithering,
+    pub serpentine: bool,
+}
+
+impl Dither {
+    // ---
+
+    /// Floyd-Steinberg dithering to `colors`.
+    pub fn new(colors: Colors) -> Self {
+        // ---
+        Self {
+            colors,
+            method: Dithering::default(),
+            serpentine: false,
+        }
+    }
+}
+
+impl Operation for Dither {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        let targets = Targets::new(&self.colors)?;
+        let (width, height) = img.dimensions();
+        let mut values = match targets.channels {
+            1 => img.to_luma32f().into_raw(),
+            _ => img.to_rgb32f().into_raw(),
+        };
+        let indices = match self.method.diffusion() {
+            Some(diffusion) => diffuse(
+                &mut values,
+                width as usize,
+                &targets,
+                diffusion,
+                self.serpentine,
+            ),
+            None => ordered(&values, width as usize, &targets, self.method.matrix_size()),
+        };
+
+        let alpha = img.color().has_alpha().then(|| img.to_luma_alpha8());
+        let alpha_at = |x: u32, y: u32| alpha.as_ref().map_or(255, |a| a.get_pixel(x, y)[1]);
+        let index_at = |x: u32, y: u32| indices[y as usize * width as usize + x as usize];
+        Ok(match (&self.colors, &alpha) {
+            (Colors::Gray(_), None) => {
+                DynamicImage::ImageLuma8(GrayImage::from_fn(width, height, |x, y| {
+                    Luma([targets.gray(index_at(x, y))])
+                }))
+            }
+            (Colors::Gray(_), Some(_)) => {
+                DynamicImage::ImageLumaA8(GrayAlphaImage::from_fn(width, height, |x, y| {
+                    LumaA([targets.gray(index_at(x, y)), alpha_at(x, y)])
+                }))
+            }
+            (Colors::Palette(palette), None) => {
+                DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+                    palette[index_at(x, y)].to_rgb()
+                }))
+            }
+            (Colors::Palette(palette), Some(_)) => {
+                DynamicImage::ImageRgba8(RgbaImage::from_fn(width, height, |x, y| {
+                    let Rgb([r, g, b]) = palette[index_at(x, y)].to_rgb();
+                    Rgba([r, g, b, alpha_at(x, y)])
+                }))
+            }
+        })
+    }
+}
+
+/// The colors to dither to, as 0..=1 values of one (gray) or three (RGB)
+/// channels.
+struct Targets {
+    channels: usize,
+    colors: Vec<f32>,
+    /// Spacing of the colors, which ordered dithering spreads values by.
+    spread: f32,
+}
+
+impl Targets {
+    // ---
+    fn new(colors: &Colors) -> Result<Self> {
+        // ---
+        let (channels, colors): (usize, Vec<f32>) = match colors {
+            Colors::Gray(bits) => {
+                anyhow::ensure!((1..=8).contains(bits), "Invalid bits:{} must be 1-8", bits);
+                let last = ((1u32 << bits) - 1) as f32;
+                (
+                    1,
+                    (0..=last as u32).map(|level| level as f32 / last).collect(),
+                )
+            }
+            Colors::Palette(palette) => {
+                anyhow::ensure!(
+                    palette.len() >= 2,
+                    "Invalid palette:{} colors, must be 2 or more",
+                    palette.len()
+                );
+                let colors = palette
+                    .iter()
+                    .flat_map(|color| [color[0], color[1], color[2]].map(|c| c as f32 / 255.0))
+                    .collect();
+                (3, colors)
+            }
+        };
+
+        // The largest distance from a color to its closest other color,
+        // per channel
+        let count = colors.len() / channels;
+        let color = |i: usize| &colors[i * channels..(i + 1) * channels];
+        let spread = (0..count)
+            .map(|i| {
+                (0..count)
+                    .filter(|&j| j != i)
+                    .map(|j| distance(color(i), color(j)))
+                    .fold(f32::INFINITY, f32::min)
+            })
+            .fold(0.0, f32::max)
+            .sqrt()
+            / (channels as f32).sqrt();
+        Ok(Self {
+            channels,
+            colors,
+            spread,
+        })
+    }
+
+    /// Index of the color closest to `value`.
+    fn nearest(&self, value: &[f32]) -> usize {
+        // ---
+        if self.channels == 1 {
+            let last = self.colors.len() - 1;
+            return (value[0].clamp(0.0, 1.0) * last as f32).round() as usize;
+        }
+        self.colors
+            .chunks_exact(self.channels)
+            .map(|color| distance(color, value))
+            .enumerate()
+            .fold(
+                (0, f32::INFINITY),
+                |best, (i, d)| if d < best.1 { (i, d) } else { best },
+            )
+            .0
+    }
+
+    /// The 8-bit gray value of gray level `index`.
+    fn gray(&self, index: usize) -> u8 {
+        // ---
+        (self.colors[index] * 255.0).round() as u8
+    }
+}
+
+/// Squared distance between two colors.
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    // ---
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// Color indices of `values` by error diffusion: each pixel takes its
+/// nearest color and passes the difference on to the neighbors in
+/// `diffusion`, mirrored on right to left rows.
+fn diffuse(
+    values: &mut [f32],
+    width: usize,
+    targets: &Targets,
+    diffusion: &Diffusion,
+    serpentine: bool,
+) -> Vec<usize> {
+    // ---
+    let channels = targets.channels;
+    let height = values.len() / channels / width.max(1);
+    let mut indices = vec![0; width * height];
+
+    for y in 0..height {
+        let reverse = serpentine && y % 2 == 1;
+        for i in 0..width {
+            let x = if reverse { width - 1 - i } else { i };
+            let at = (y * width + x) * channels;
+            let value: Vec<f32> = values[at..at + channels]
+                .iter()
+                .map(|v| v.clamp(0.0, 1.0))
+                .collect();
+            let index = targets.nearest(&value);
+            indices[y * width + x] = index;
+
+            let color = &targets.colors[index * channels..(index + 1) * channels];
+            for &(dx, dy, weight) in diffusion {
+                let nx = x as i64 + if reverse { -dx } else { dx };
+                let ny = y + dy;
+                if nx < 0 || nx >= width as i64 || ny >= height {
+                    continue;
+                }
+                let neighbor = (ny * width + nx as usize) * channels;
+                for k in 0..channels {
+                    values[neighbor + k] += (value[k] - color[k]) * weight;
+                }
+            }
+        }
+    }
+    indices
+}
+
+/// Color indices of `values` by ordered dithering: each value is offset by
+/// its entry in a `size` x `size` Bayer matrix, tiled over the image,
+/// before taking the nearest color.
+fn ordered(values: &[f32], width: usize, targets: &Targets, size: usize) -> Vec<usize> {
+    // ---
+    let matrix = bayer(size);
+    let cells = (size * size) as f32;
+    values
+        .par_chunks_exact(targets.channels)
+        .enumerate()
+        .map(|(i, value)| {
+            let (x, y) = (i % width, i / width);
+            let offset = (matrix[(y % size) * size + x % size] as f32 + 0.5) / cells - 0.5;
+            let value: Vec<f32> = value.iter().map(|v| v + targets.spread * offset).collect();
+            targets.nearest(&value)
+        })
+        .collect()
+}
+
+/// The `size` x `size` Bayer threshold matrix, row by row, with entries
+/// 0 to `size`² - 1; `size` is a power of two.
+fn bayer(size: usize) -> Vec<u32> {
+    // ---
+    let mut matrix = vec![0];
+    let mut side = 1;
+    while side < size {
+        let next = side * 2;
+        matrix = (0..next * next)
+            .map(|i| {
+                let (x, y) = (i % next, i / next);
+                let quadrant = [[0, 2], [3, 1]][y / side][x / side];
+                4 * matrix[(y % side) * side + x % side] + quadrant
+            })
+            .collect();
+        side = next;
+    }
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::ColorType;
+
+    /// Mean 8-bit gray value of `img`.
+    fn mean(img: &GrayImage) -> f32 {
+        // ---
+        img.pixels().map(|p| p[0] as f32).sum::<f32>() / img.pixels().len() as f32
+    }
+
+    #[test]
+    fn test_bayer_matrix() -> Result<()> {
+        // ---
+
+        ensure!(bayer(2) == [0, 2, 3, 1]);
+        ensure!(bayer(4) == [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]);
+        let mut entries = bayer(8);
+        entries.sort();
+        ensure!(entries == (0..64).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dither_keeps_tones() -> Result<()> {
+        // ---
+
+        let gray = GrayImage::from_fn(64, 64, |x, _| Luma([(x * 4) as u8]));
+        let methods = [
+            Dithering::FloydSteinberg,
+            Dithering::Atkinson,
+            Dithering::JarvisJudiceNinke,
+            Dithering::Bayer2,
+            Dithering::Bayer4,
+            Dithering::Bayer8,
+        ];
+        for method in methods {
+            for serpentine in [false, true] {
+                let dither = Dither {
+                    method,
+                    serpentine,
+                    ..Dither::new(Colors::Gray(1))
+                };
+                let binary = dither.apply(DynamicImage::ImageLuma8(gray.clone()))?;
+                ensure!(binary.color() == ColorType::L8);
+                let binary = binary.into_luma8();
+                ensure!(binary.pixels().all(|p| p[0] == 0 || p[0] == 255));
+                // Atkinson drops a quarter of the error, so it drifts more
+                let difference = (mean(&binary) - mean(&gray)).abs();
+                ensure!(difference < 8.0, "{:?} is off by {}", method, difference);
+                ensure!(binary.get_pixel(0, 10)[0] == 0, "Black stays black");
+            }
+        }
+
+        let levels = Dither::new(Colors::Gray(2))
+            .apply(DynamicImage::ImageLuma8(gray.clone()))?
+            .into_luma8();
+        ensure!(levels.pixels().all(|p| [0, 85, 170, 255].contains(&p[0])));
+        ensure!((mean(&levels) - mean(&gray)).abs() < 2.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_serpentine_mirrors_odd_rows() -> Result<()> {
+        // ---
+
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(16, 4, Luma([100])));
+        let raster = Dither::new(Colors::Gray(1)).apply(gray.clone())?;
+        let serpentine = Dither {
+            serpentine: true,
+            ..Dither::new(Colors::Gray(1))
+        }
+        .apply(gray)?;
+        let (raster, serpentine) = (raster.into_luma8(), serpentine.into_luma8());
+        ensure!((0..16).all(|x| raster.get_pixel(x, 0) == serpentine.get_pixel(x, 0)));
+        ensure!(raster != serpentine, "The second row runs the other way");
+        Ok(())
+    }
+
+    #[test]
+    fn test_dither_to_palette() -> Result<()> {
+        // ---
+
+        let palette = vec![
+            Rgba([0, 0, 0, 255]),
+            Rgba([255, 255, 255, 255]),
+            Rgba([255, 0, 0, 255]),
+        ];
+        let img = RgbaImage::from_fn(32, 8, |x, y| Rgba([(x * 8) as u8, 0, 0, y as u8]));
+        for method in [Dithering::FloydSteinberg, Dithering::Bayer4] {
+            let dither = Dither {
+                method,
+                ..Dither::new(Colors::Palette(palette.clone()))
+            };
+            let result = dither.apply(DynamicImage::ImageRgba8(img.clone()))?;
+            ensure!(result.color() == ColorType::Rgba8);
+            let result = result.into_rgba8();
+            for (x, y, pixel) in result.enumerate_pixels() {
+                ensure!(pixel[3] == y as u8, "Alpha is kept");
+                ensure!(
+                    pixel[1] == 0 && [0, 255].contains(&pixel[0]),
+                    "{:?} uses only black and red, got {:?} at {}",
+                    method,
+                    pixel,
+                    x
+                );
+            }
+            ensure!(result.get_pixel(31, 0)[0] == 255 && result.get_pixel(0, 0)[0] == 0);
+        }
+
+        let one = Dither::new(Colors::Palette(vec![Rgba([0, 0, 0, 255])]));
+        ensure!(one.apply(DynamicImage::new_rgb8(1, 1)).is_err());
+        let deep = Dither::new(Colors::Gray(9));
+        ensure!(deep.apply(DynamicImage::new_rgb8(1, 1)).is_err());
+        Ok(())
+    }
+}
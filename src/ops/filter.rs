@@ -0,0 +1,173 @@
+use super::pixels::{map_rgba, premultiply, unpremultiply, RgbaBuffer, RgbaMap};
+use super::Operation;
+use anyhow::Result;
+use image::{DynamicImage, ImageBuffer, Pixel, Primitive, Rgba};
+use rayon::prelude::*;
+
+/// Sharpen with an unsharp mask: the difference between the image and a
+/// gaussian blur of it with standard deviation `sigma` is added back,
+/// scaled by `amount`.
+///
+/// Differences no larger than `threshold`, a fraction of the channel
+/// range, are left alone so flat areas and noise aren't sharpened. Colors
+/// are sharpened premultiplied by alpha, so transparent pixels don't bleed
+/// into their neighbors, and alpha itself is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sharpen {
+    pub sigma: f32,
+    pub amount: f32,
+    pub threshold: f32,
+}
+
+impl Sharpen {
+    // ---
+
+    /// Sharpen at full strength with no threshold.
+    pub fn new(sigma: f32) -> Self {
+        // ---
+        Self {
+            sigma,
+            amount: 1.0,
+            threshold: 0.0,
+        }
+    }
+}
+
+impl Operation for Sharpen {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.sigma.is_finite() && self.sigma > 0.0,
+            "Invalid sharpen radius:{} must be a positive number",
+            self.sigma
+        );
+        Ok(map_rgba(img, self, false))
+    }
+}
+
+impl RgbaMap for Sharpen {
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let (width, height) = img.dimensions();
+        let sharp: ImageBuffer<Rgba<f32>, Vec<f32>> =
+            ImageBuffer::from_fn(width, height, |x, y| {
+                Rgba(premultiply(*img.get_pixel(x, y)))
+            });
+        let soft = image::imageops::blur(&sharp, self.sigma);
+
+        let mut img = img;
+        img.par_chunks_mut(4)
+            .zip(sharp.par_chunks(4))
+            .zip(soft.par_chunks(4))
+            .for_each(|((pixel, sharp), soft)| {
+                let alpha = sharp[3];
+                if alpha <= 0.0 {
+                    return;
+                }
+                let mut color = [0.0, 0.0, 0.0, alpha];
+                for channel in 0..3 {
+                    let detail = sharp[channel] - soft[channel];
+                    color[channel] = match detail.abs() > self.threshold {
+                        true => (sharp[channel] + self.amount * detail).clamp(0.0, alpha),
+                        false => sharp[channel],
+                    };
+                }
+                let opacity = pixel[3];
+                pixel.copy_from_slice(&unpremultiply::<T>(color).0);
+                pixel[3] = opacity;
+            });
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{GrayImage, Luma, Rgba, RgbaImage};
+
+    /// A vertical edge from dark gray to light gray in the middle.
+    fn edge() -> GrayImage {
+        // ---
+        GrayImage::from_fn(20, 4, |x, _| Luma([if x < 10 { 60 } else { 180 }]))
+    }
+
+    #[test]
+    fn test_sharpen_increases_edge_contrast() -> Result<()> {
+        // ---
+
+        let sharpened = Sharpen::new(1.5)
+            .apply(DynamicImage::ImageLuma8(edge()))?
+            .into_luma8();
+        ensure!(sharpened.get_pixel(9, 0)[0] < 60, "Dark side gets darker");
+        ensure!(
+            sharpened.get_pixel(10, 0)[0] > 180,
+            "Light side gets lighter"
+        );
+        ensure!(
+            sharpened.get_pixel(0, 0)[0] == 60 && sharpened.get_pixel(19, 0)[0] == 180,
+            "Flat areas are unchanged"
+        );
+
+        let stronger = Sharpen {
+            amount: 3.0,
+            ..Sharpen::new(1.5)
+        }
+        .apply(DynamicImage::ImageLuma8(edge()))?
+        .into_luma8();
+        ensure!(stronger.get_pixel(9, 0)[0] < sharpened.get_pixel(9, 0)[0]);
+
+        let gentle = Sharpen {
+            threshold: 0.9,
+            ..Sharpen::new(1.5)
+        }
+        .apply(DynamicImage::ImageLuma8(edge()))?;
+        ensure!(
+            gentle.into_luma8() == edge(),
+            "Differences below the threshold are kept"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_sharpen_keeps_depth_and_alpha() -> Result<()> {
+        // ---
+
+        // Opaque red next to transparent green: the green must not bleed in
+        let img = RgbaImage::from_fn(8, 2, |x, _| match x < 4 {
+            true => Rgba([200, 0, 0, 255]),
+            false => Rgba([0, 255, 0, 0]),
+        });
+        let sharpened = Sharpen::new(1.0).apply(DynamicImage::ImageRgba8(img.clone()))?;
+        let sharpened = sharpened.into_rgba8();
+        ensure!(
+            sharpened
+                .pixels()
+                .zip(img.pixels())
+                .all(|(s, i)| s[3] == i[3]),
+            "Alpha is kept"
+        );
+        ensure!(
+            sharpened.get_pixel(3, 0)[1] == 0,
+            "No green fringe: {:?}",
+            sharpened.get_pixel(3, 0)
+        );
+
+        let deep = DynamicImage::ImageLuma16(ImageBuffer::from_fn(20, 4, |x, _| {
+            Luma([if x < 10 { 15000 } else { 45000 }])
+        }));
+        let sharpened = Sharpen::new(1.5).apply(deep)?;
+        ensure!(sharpened.color() == image::ColorType::L16);
+        let value = sharpened.into_luma16().get_pixel(9, 0)[0];
+        ensure!(value < 15000 && value % 257 != 0, "Full 16-bit precision");
+        Ok(())
+    }
+}
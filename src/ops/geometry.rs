@@ -0,0 +1,926 @@
+use super::pixels::{convert_color, map_rgba, premultiply, unpremultiply, RgbaBuffer, RgbaMap};
+use super::Operation;
+use anyhow::{Context, Result};
+use image::{DynamicImage, GenericImageView, Pixel, Primitive, Rgba};
+use rayon::prelude::*;
+use std::str::FromStr;
+
+/// Cut out the `width` x `height` rectangle whose top left corner is at
+/// (`x`, `y`). The rectangle must lie within the image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Operation for Crop {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        let (width, height) = img.dimensions();
+        anyhow::ensure!(
+            self.width > 0
+                && self.height > 0
+                && self.x as u64 + self.width as u64 <= width as u64
+                && self.y as u64 + self.height as u64 <= height as u64,
+            "Crop rectangle {}x{}+{}+{} doesn't fit in the {}x{} image",
+            self.width,
+            self.height,
+            self.x,
+            self.y,
+            width,
+            height
+        );
+        Ok(img.crop_imm(self.x, self.y, self.width, self.height))
+    }
+}
+
+/// A size or offset in pixels, or as a percentage of the image side it
+/// applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Length {
+    // ---
+
+    /// The length in pixels along an image side of `side` pixels.
+    pub fn resolve(self, side: u32) -> u32 {
+        // ---
+        match self {
+            Self::Pixels(pixels) => pixels,
+            Self::Percent(percent) => (side as f64 * percent as f64 / 100.0).round() as u32,
+        }
+    }
+}
+
+impl FromStr for Length {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let invalid = || format!("Invalid length {}, use pixels or a percentage", text);
+        match text.trim().strip_suffix('%') {
+            Some(percent) => {
+                let percent: f32 = percent.parse().ok().context(invalid())?;
+                anyhow::ensure!(percent.is_finite() && percent >= 0.0, invalid());
+                Ok(Self::Percent(percent))
+            }
+            None => Ok(Self::Pixels(text.trim().parse().ok().context(invalid())?)),
+        }
+    }
+}
+
+/// A rectangle as `WxH+X+Y`, where every part may be a percentage of the
+/// image size and the offsets may be left out.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Geometry {
+    pub width: Length,
+    pub height: Length,
+    pub x: Length,
+    pub y: Length,
+}
+
+impl Default for Geometry {
+    // ---
+
+    /// The whole image.
+    fn default() -> Self {
+        // ---
+        Self {
+            width: Length::Percent(100.0),
+            height: Length::Percent(100.0),
+            x: Length::Pixels(0),
+            y: Length::Pixels(0),
+        }
+    }
+}
+
+impl FromStr for Geometry {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let invalid = || format!("Invalid geometry {}, use WxH or WxH+X+Y", text);
+        let mut parts = text.split('+');
+        let size = parts.next().unwrap_or_default();
+        let (width, height) = size.split_once(['x', 'X']).context(invalid())?;
+        let offsets: Vec<&str> = parts.collect();
+        let (x, y) = match offsets[..] {
+            [] => ("0", "0"),
+            [x, y] => (x, y),
+            _ => anyhow::bail!(invalid()),
+        };
+        let length = |part: &str| part.parse::<Length>().context(invalid());
+        Ok(Self {
+            width: length(width)?,
+            height: length(height)?,
+            x: length(x)?,
+            y: length(y)?,
+        })
+    }
+}
+
+/// An aspect ratio such as `16:9`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aspect {
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Aspect {
+    // ---
+
+    /// The largest size of this aspect ratio that fits in `size`.
+    pub fn fit(self, size: (u32, u32)) -> (u32, u32) {
+        // ---
+        let ratio = self.width / self.height;
+        let (width, height) = (size.0 as f64, size.1 as f64);
+        if width > height * ratio {
+            (((height * ratio).round() as u32).max(1), size.1)
+        } else {
+            (size.0, ((width / ratio).round() as u32).max(1))
+        }
+    }
+}
+
+impl FromStr for Aspect {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let invalid = || format!("Invalid aspect ratio {}, use W:H such as 16:9", text);
+        let (width, height) = text.split_once(':').context(invalid())?;
+        let side = |side: &str| -> Result<f64> {
+            match side.trim().parse::<f64>() {
+                Ok(value) if value.is_finite() && value > 0.0 => Ok(value),
+                _ => Err(anyhow::anyhow!(invalid())),
+            }
+        };
+        Ok(Self {
+            width: side(width)?,
+            height: side(height)?,
+        })
+    }
+}
+
+/// Crop to a rectangle given relative to the image.
+///
+/// The rectangle has the size of `geometry`, narrowed to the largest
+/// rectangle of `aspect` that fits when one is given. It is aligned by
+/// `gravity` and then moved inward by the geometry's offsets: right and
+/// down, but left from an east and up from a south gravity's edge, as
+/// ImageMagick's `-gravity` does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CropRegion {
+    pub geometry: Geometry,
+    pub aspect: Option<Aspect>,
+    pub gravity: Gravity,
+}
+
+impl CropRegion {
+    // ---
+
+    /// Crop to `geometry` measured from the top left corner.
+    pub fn new(geometry: Geometry) -> Self {
+        // ---
+        Self {
+            geometry,
+            aspect: None,
+            gravity: Gravity::NorthWest,
+        }
+    }
+
+    /// The pixel rectangle of this region in an image of `size`.
+    pub fn rect(&self, size: (u32, u32)) -> Crop {
+        // ---
+        let geometry = &self.geometry;
+        let (mut width, mut height) = (
+            geometry.width.resolve(size.0),
+            geometry.height.resolve(size.1),
+        );
+        if let Some(aspect) = self.aspect {
+            (width, height) = aspect.fit((width, height));
+        }
+        let (x, y) = self.gravity.offset(size, (width, height));
+        let (dx, dy) = (
+            geometry.x.resolve(size.0) as i64,
+            geometry.y.resolve(size.1) as i64,
+        );
+        let x = match self.gravity {
+            Gravity::NorthEast | Gravity::East | Gravity::SouthEast => x - dx,
+            _ => x + dx,
+        };
+        let y = match self.gravity {
+            Gravity::SouthWest | Gravity::South | Gravity::SouthEast => y - dy,
+            _ => y + dy,
+        };
+        Crop {
+            x: x.max(0) as u32,
+            y: y.max(0) as u32,
+            width,
+            height,
+        }
+    }
+}
+
+impl Operation for CropRegion {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        self.rect(img.dimensions()).apply(img)
+    }
+}
+
+/// Mirror top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flip;
+
+impl Operation for Flip {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(imageop!(img, flipv))
+    }
+}
+
+/// Mirror left to right.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flop;
+
+impl Operation for Flop {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(imageop!(img, fliph))
+    }
+}
+
+/// Mirror along the diagonal from the top left to the bottom right
+/// corner, swapping rows and columns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transpose;
+
+impl Operation for Transpose {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(img.rotate90().fliph())
+    }
+}
+
+/// Mirror along the diagonal from the top right to the bottom left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Transverse;
+
+impl Operation for Transverse {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        Ok(img.rotate90().flipv())
+    }
+}
+
+/// How the stored pixels of a photo relate to the upright image, as given
+/// by its EXIF Orientation tag.
+///
+/// Applying an orientation performs the transform that turns the stored
+/// pixels upright.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Orientation {
+    /// 1: stored upright
+    #[default]
+    Normal,
+    /// 2: mirrored left to right
+    Flop,
+    /// 3: upside down
+    Rotate180,
+    /// 4: mirrored top to bottom
+    Flip,
+    /// 5: mirrored along the main diagonal
+    Transpose,
+    /// 6: needs a quarter turn clockwise
+    Rotate90,
+    /// 7: mirrored along the other diagonal
+    Transverse,
+    /// 8: needs a quarter turn counterclockwise
+    Rotate270,
+}
+
+impl Orientation {
+    // ---
+
+    /// The orientation of an EXIF Orientation tag value, `None` when it
+    /// is out of range.
+    pub fn from_exif(value: u32) -> Option<Self> {
+        // ---
+        match value {
+            1 => Some(Self::Normal),
+            2 => Some(Self::Flop),
+            3 => Some(Self::Rotate180),
+            4 => Some(Self::Flip),
+            5 => Some(Self::Transpose),
+            6 => Some(Self::Rotate90),
+            7 => Some(Self::Transverse),
+            8 => Some(Self::Rotate270),
+            _ => None,
+        }
+    }
+}
+
+impl Operation for Orientation {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        match self {
+            Self::Normal => Ok(img),
+            Self::Flop => Flop.apply(img),
+            Self::Rotate180 => Ok(img.rotate180()),
+            Self::Flip => Flip.apply(img),
+            Self::Transpose => Transpose.apply(img),
+            Self::Rotate90 => Ok(img.rotate90()),
+            Self::Transverse => Transverse.apply(img),
+            Self::Rotate270 => Ok(img.rotate270()),
+        }
+    }
+}
+
+/// Where an image is placed when it is cropped or padded to a different
+/// size, as a compass direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Gravity {
+    #[value(alias = "nw", alias = "northwest")]
+    NorthWest,
+    #[value(alias = "n")]
+    North,
+    #[value(alias = "ne", alias = "northeast")]
+    NorthEast,
+    #[value(alias = "w")]
+    West,
+    #[default]
+    Center,
+    #[value(alias = "e")]
+    East,
+    #[value(alias = "sw", alias = "southwest")]
+    SouthWest,
+    #[value(alias = "s")]
+    South,
+    #[value(alias = "se", alias = "southeast")]
+    SouthEast,
+}
+
+impl Gravity {
+    // ---
+
+    /// Position of the top left corner of an `inner` sized area aligned
+    /// within an `outer` sized one; negative when `inner` is larger.
+    pub fn offset(self, outer: (u32, u32), inner: (u32, u32)) -> (i64, i64) {
+        // ---
+        let (column, row) = match self {
+            Self::NorthWest => (0, 0),
+            Self::North => (1, 0),
+            Self::NorthEast => (2, 0),
+            Self::West => (0, 1),
+            Self::Center => (1, 1),
+            Self::East => (2, 1),
+            Self::SouthWest => (0, 2),
+            Self::South => (1, 2),
+            Self::SouthEast => (2, 2),
+        };
+        let align = |space: i64, step: i64| (space * step).div_euclid(2);
+        (
+            align(outer.0 as i64 - inner.0 as i64, column),
+            align(outer.1 as i64 - inner.1 as i64, row),
+        )
+    }
+}
+
+/// Place `img` with its top left corner at `offset` on a canvas of `size`
+/// filled with `background`. Parts outside the canvas are cut off.
+pub(super) fn place(
+    img: DynamicImage,
+    size: (u32, u32),
+    offset: (i64, i64),
+    background: Rgba<u8>,
+) -> DynamicImage {
+    // ---
+    map_rgba(
+        img,
+        &Place {
+            size,
+            offset,
+            background,
+        },
+        background[3] < 255,
+    )
+}
+
+struct Place {
+    size: (u32, u32),
+    offset: (i64, i64),
+    background: Rgba<u8>,
+}
+
+impl RgbaMap for Place {
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let (width, height) = self.size;
+        let mut canvas = RgbaBuffer::from_pixel(width, height, convert_color(self.background));
+        image::imageops::replace(&mut canvas, &img, self.offset.0, self.offset.1);
+        canvas
+    }
+}
+
+/// How pixels are sampled when rotating by an angle that isn't a multiple
+/// of 90 degrees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Interpolation {
+    /// closest source pixel, keeps hard edges
+    Nearest,
+    /// weighted average of the 4 closest pixels
+    Bilinear,
+    /// Catmull-Rom cubic over the 16 closest pixels, sharpest
+    #[default]
+    Bicubic,
+}
+
+/// Size of the canvas of a rotated image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Canvas {
+    /// grow the canvas so the whole rotated image fits
+    #[default]
+    Expand,
+    /// keep the original size, cutting off the rotated corners
+    Crop,
+}
+
+/// Rotate clockwise by `degrees`; negative angles rotate counterclockwise.
+///
+/// Multiples of 90 degrees move pixels without resampling. Other angles
+/// sample the source with `interpolation`, and canvas areas outside the
+/// rotated image are filled with `background`, which may be transparent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rotate {
+    pub degrees: f32,
+    pub interpolation: Interpolation,
+    pub canvas: Canvas,
+    pub background: Rgba<u8>,
+}
+
+impl Rotate {
+    // ---
+
+    /// Rotation by `degrees` with bicubic sampling on an expanded canvas
+    /// filled with black.
+    pub fn new(degrees: f32) -> Self {
+        // ---
+        Self {
+            degrees,
+            interpolation: Interpolation::default(),
+            canvas: Canvas::default(),
+            background: Rgba([0, 0, 0, 255]),
+        }
+    }
+}
+
+impl Operation for Rotate {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.degrees.is_finite(),
+            "Invalid rotation value:{}",
+            self.degrees
+        );
+
+        // Quarter turns move pixels without resampling, so they are exact
+        // even when recentered on a cropped canvas.
+        let quarter_turns = match self.degrees.rem_euclid(360.0) {
+            0.0 => Some(0),
+            90.0 => Some(1),
+            180.0 => Some(2),
+            270.0 => Some(3),
+            _ => None,
+        };
+        let (sin, cos) = match quarter_turns {
+            Some(turns) => [(0.0, 1.0), (1.0, 0.0), (0.0, -1.0), (-1.0, 0.0)][turns],
+            None => (self.degrees as f64).to_radians().sin_cos(),
+        };
+
+        let (width, height) = img.dimensions();
+        let (w, h) = (width as f64, height as f64);
+        let size = match self.canvas {
+            Canvas::Crop => (width, height),
+            Canvas::Expand => {
+                let fit = |a: f64, b: f64| ((a + b) - 1e-9).ceil().max(1.0) as u32;
+                (
+                    fit(w * cos.abs(), h * sin.abs()),
+                    fit(w * sin.abs(), h * cos.abs()),
+                )
+            }
+        };
+
+        if let Some(turns) = quarter_turns {
+            let rotated = match turns {
+                1 => img.rotate90(),
+                2 => img.rotate180(),
+                3 => img.rotate270(),
+                _ => img,
+            };
+            if rotated.dimensions() == size {
+                return Ok(rotated);
+            }
+            let offset = Gravity::Center.offset(size, rotated.dimensions());
+            return Ok(place(rotated, size, offset, self.background));
+        }
+
+        let resample = Resample {
+            size,
+            sin,
+            cos,
+            interpolation: self.interpolation,
+            background: self.background,
+        };
+        Ok(map_rgba(img, &resample, self.background[3] < 255))
+    }
+}
+
+/// Sample the source, rotated by the angle of `sin` and `cos` about its
+/// center, onto a canvas of `size` centered on it.
+struct Resample {
+    size: (u32, u32),
+    sin: f64,
+    cos: f64,
+    interpolation: Interpolation,
+    background: Rgba<u8>,
+}
+
+impl RgbaMap for Resample {
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let (width, height) = self.size;
+        let background = premultiply(convert_color::<T>(self.background));
+
+        // Interpolate premultiplied colors so transparent pixels don't
+        // bleed their color into the edges.
+        let fetch = |x: i64, y: i64| -> [f32; 4] {
+            if x < 0 || y < 0 || x >= img.width() as i64 || y >= img.height() as i64 {
+                return background;
+            }
+            premultiply(*img.get_pixel(x as u32, y as u32))
+        };
+
+        let (source_cx, source_cy) = (img.width() as f64 / 2.0, img.height() as f64 / 2.0);
+        let (cx, cy) = (width as f64 / 2.0, height as f64 / 2.0);
+        let mut out = RgbaBuffer::<T>::new(width, height);
+        out.par_chunks_mut(width as usize * 4)
+            .enumerate()
+            .for_each(|(y, row)| {
+                for (x, pixel) in row.chunks_exact_mut(4).enumerate() {
+                    // Inverse rotation of the output pixel center
+                    let dx = x as f64 + 0.5 - cx;
+                    let dy = y as f64 + 0.5 - cy;
+                    let sx = dx * self.cos + dy * self.sin + source_cx;
+                    let sy = -dx * self.sin + dy * self.cos + source_cy;
+
+                    let color = match self.interpolation {
+                        Interpolation::Nearest => fetch(sx.floor() as i64, sy.floor() as i64),
+                        Interpolation::Bilinear => sample(sx, sy, 1, &fetch, |t| [1.0 - t, t]),
+                        Interpolation::Bicubic => sample(sx, sy, 2, &fetch, catmull_rom),
+                    };
+                    pixel.copy_from_slice(&unpremultiply::<T>(color).0);
+                }
+            });
+        out
+    }
+}
+
+/// Weighted sum of the `2 * radius` x `2 * radius` pixels around the point
+/// (`x`, `y`), with `weights(t)` giving the weights of one axis for a point
+/// a fraction `t` past the pixel center to its left or above it.
+pub(super) fn sample<const N: usize>(
+    x: f64,
+    y: f64,
+    radius: i64,
+    fetch: &impl Fn(i64, i64) -> [f32; 4],
+    weights: impl Fn(f32) -> [f32; N],
+) -> [f32; 4] {
+    // ---
+    let (px, py) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (px.floor(), py.floor());
+    let wx = weights((px - x0) as f32);
+    let wy = weights((py - y0) as f32);
+    let (x0, y0) = (x0 as i64 - radius + 1, y0 as i64 - radius + 1);
+
+    let mut sum = [0.0f32; 4];
+    for (j, wy) in wy.iter().enumerate() {
+        for (i, wx) in wx.iter().enumerate() {
+            let color = fetch(x0 + i as i64, y0 + j as i64);
+            for (total, channel) in sum.iter_mut().zip(color) {
+                *total += channel * wx * wy;
+            }
+        }
+    }
+    sum
+}
+
+/// Catmull-Rom weights of the 4 pixels around a point a fraction `t` past
+/// the second one.
+fn catmull_rom(t: f32) -> [f32; 4] {
+    // ---
+    let (t2, t3) = (t * t, t * t * t);
+    [
+        (-t3 + 2.0 * t2 - t) / 2.0,
+        (3.0 * t3 - 5.0 * t2 + 2.0) / 2.0,
+        (-3.0 * t3 + 4.0 * t2 + t) / 2.0,
+        (t3 - t2) / 2.0,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Context, Result};
+    use image::RgbImage;
+
+    fn numbered(width: u32, height: u32) -> DynamicImage {
+        // ---
+        DynamicImage::ImageRgb8(RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([x as u8, y as u8, (x * 7 + y * 13) as u8])
+        }))
+    }
+
+    #[test]
+    fn test_crop_rejects_rectangles_outside() -> Result<()> {
+        // ---
+
+        let img = numbered(10, 8);
+        let crop = |x, y, width, height| {
+            Crop {
+                x,
+                y,
+                width,
+                height,
+            }
+            .apply(img.clone())
+        };
+        ensure!(
+            crop(2, 2, 8, 6)?.dimensions() == (8, 6),
+            "Touching the edges"
+        );
+        ensure!(crop(3, 0, 8, 8).is_err(), "Too wide");
+        ensure!(crop(0, 8, 1, 1).is_err(), "Below the image");
+        ensure!(crop(0, 0, 0, 5).is_err(), "Empty");
+        let err = crop(u32::MAX, 0, 2, 2).err().context("Overflow")?;
+        ensure!(
+            err.to_string().contains("10x8 image"),
+            "Unexpected message {}",
+            err
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_geometry_parsing() -> Result<()> {
+        // ---
+
+        let geometry: Geometry = "800x600+10+20".parse()?;
+        ensure!(
+            geometry
+                == Geometry {
+                    width: Length::Pixels(800),
+                    height: Length::Pixels(600),
+                    x: Length::Pixels(10),
+                    y: Length::Pixels(20),
+                }
+        );
+        let geometry: Geometry = "50%x25%".parse()?;
+        ensure!(geometry.width == Length::Percent(50.0) && geometry.x == Length::Pixels(0));
+        for invalid in ["800", "800x600+10", "axb", "10x10+-1+0", "x+1+1"] {
+            ensure!(
+                invalid.parse::<Geometry>().is_err(),
+                "{} should fail",
+                invalid
+            );
+        }
+        ensure!("16:9".parse::<Aspect>()?.width == 16.0);
+        ensure!("16:0".parse::<Aspect>().is_err() && "16x9".parse::<Aspect>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_crop_region_rects() -> Result<()> {
+        // ---
+
+        let rect = |region: CropRegion| {
+            let Crop {
+                x,
+                y,
+                width,
+                height,
+            } = region.rect((400, 300));
+            (x, y, width, height)
+        };
+        ensure!(rect(CropRegion::new("50%x50%+25%+10".parse()?)) == (100, 10, 200, 150));
+        let wide = CropRegion {
+            aspect: Some("16:9".parse()?),
+            gravity: Gravity::Center,
+            ..CropRegion::new(Geometry::default())
+        };
+        ensure!(rect(wide) == (0, 37, 400, 225), "Largest 16:9, centered");
+        let tall = CropRegion {
+            aspect: Some("1:2".parse()?),
+            gravity: Gravity::East,
+            ..wide
+        };
+        ensure!(
+            rect(tall) == (250, 0, 150, 300),
+            "Largest 1:2, at the right"
+        );
+
+        // Offsets move inward from the gravity's edges
+        let expected = [
+            (Gravity::NorthWest, (10, 20)),
+            (Gravity::North, (160, 20)),
+            (Gravity::NorthEast, (290, 20)),
+            (Gravity::West, (10, 145)),
+            (Gravity::Center, (160, 145)),
+            (Gravity::East, (290, 145)),
+            (Gravity::SouthWest, (10, 230)),
+            (Gravity::South, (160, 230)),
+            (Gravity::SouthEast, (290, 230)),
+        ];
+        for (gravity, (x, y)) in expected {
+            let region = CropRegion {
+                gravity,
+                ..CropRegion::new("100x50+10+20".parse()?)
+            };
+            ensure!(
+                rect(region) == (x, y, 100, 50),
+                "{:?} gave {:?}",
+                gravity,
+                rect(region)
+            );
+            ensure!(region.apply(DynamicImage::new_rgb8(400, 300))?.dimensions() == (100, 50));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_mirrors_move_pixels() -> Result<()> {
+        // ---
+
+        let img = numbered(5, 3);
+        let pixel = |img: DynamicImage, x, y| img.to_rgb8().get_pixel(x, y).0;
+        let corner = pixel(img.clone(), 4, 0);
+        ensure!(pixel(Flip.apply(img.clone())?, 4, 2) == corner);
+        ensure!(pixel(Flop.apply(img.clone())?, 0, 0) == corner);
+
+        let transposed = Transpose.apply(img.clone())?;
+        ensure!(transposed.dimensions() == (3, 5));
+        ensure!(pixel(transposed, 0, 4) == corner, "Rows become columns");
+        ensure!(pixel(Transverse.apply(img.clone())?, 2, 0) == corner);
+        ensure!(
+            Transpose.apply(Transpose.apply(img.clone())?)? == img,
+            "Mirroring twice restores the image"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_orientations_turn_upright() -> Result<()> {
+        // ---
+
+        let upright = numbered(5, 3);
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).context("Valid tag")?;
+            // How a camera would have stored the upright image
+            let stored = match orientation {
+                Orientation::Rotate90 => upright.rotate270(),
+                Orientation::Rotate270 => upright.rotate90(),
+                other => other.apply(upright.clone())?,
+            };
+            ensure!(
+                orientation.apply(stored)? == upright,
+                "Orientation {} should turn upright",
+                value
+            );
+        }
+        ensure!(Orientation::from_exif(0).is_none() && Orientation::from_exif(9).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_quarter_turns_are_lossless() -> Result<()> {
+        // ---
+
+        let img = numbered(5, 3);
+        let rotate = |degrees| Rotate::new(degrees).apply(img.clone());
+        ensure!(
+            rotate(90.0)? == img.rotate90(),
+            "90 is a clockwise quarter turn"
+        );
+        ensure!(rotate(180.0)? == img.rotate180());
+        ensure!(rotate(270.0)? == img.rotate270(), "270 is not 180");
+        ensure!(
+            rotate(-90.0)? == img.rotate270(),
+            "Negative is counterclockwise"
+        );
+        ensure!(rotate(360.0)? == img, "A full turn changes nothing");
+        ensure!(
+            rotate(90.0)?.color() == img.color(),
+            "No hue or type change"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cropped_quarter_turn_keeps_size() -> Result<()> {
+        // ---
+
+        let img = numbered(6, 4);
+        let rotated = Rotate {
+            canvas: Canvas::Crop,
+            background: Rgba([0, 0, 0, 0]),
+            ..Rotate::new(90.0)
+        }
+        .apply(img.clone())?;
+        ensure!(rotated.dimensions() == (6, 4));
+
+        // The middle column of the 4-wide turned image comes from the top row
+        let turned = img.rotate90().to_rgba8();
+        let rotated = rotated.to_rgba8();
+        ensure!(
+            rotated.get_pixel(1, 0) == turned.get_pixel(0, 1),
+            "Pixels are copied"
+        );
+        ensure!(
+            rotated.get_pixel(0, 0)[3] == 0,
+            "Uncovered area is background"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_arbitrary_angle_expands_canvas() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(10, 10, image::Rgb([200, 10, 10])));
+        for interpolation in [
+            Interpolation::Nearest,
+            Interpolation::Bilinear,
+            Interpolation::Bicubic,
+        ] {
+            let rotated = Rotate {
+                interpolation,
+                background: Rgba([0, 0, 255, 255]),
+                ..Rotate::new(45.0)
+            }
+            .apply(img.clone())?
+            .to_rgb8();
+            ensure!(
+                rotated.dimensions() == (15, 15),
+                "sqrt(2) * 10 rounds up to 15, got {:?}",
+                rotated.dimensions()
+            );
+            ensure!(
+                *rotated.get_pixel(7, 7) == image::Rgb([200, 10, 10]),
+                "Center kept"
+            );
+            ensure!(
+                *rotated.get_pixel(0, 0) == image::Rgb([0, 0, 255]),
+                "Corner filled"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_transparent_background_adds_alpha() -> Result<()> {
+        // ---
+
+        let img = numbered(8, 8);
+        let rotated = Rotate {
+            background: Rgba([0, 0, 0, 0]),
+            ..Rotate::new(30.0)
+        }
+        .apply(img)?;
+        ensure!(rotated.color() == image::ColorType::Rgba8);
+        ensure!(rotated.to_rgba8().get_pixel(0, 0)[3] == 0);
+        Ok(())
+    }
+}
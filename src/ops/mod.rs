@@ -0,0 +1,122 @@
+//! Image operations, one type per `mirage` command.
+//!
+//! Each operation implements [`Operation`] and can be applied on its own or
+//! chained with others in a [`Pipeline`].
+
+use anyhow::Result;
+use image::DynamicImage;
+use std::fmt::Debug;
+
+macro_rules! imageop {
+    ($img: ident, $op: ident, $arg: expr) => {{
+        $img.$op($arg)
+    }};
+    ($img: ident, $op: ident) => {{
+        $img.$op()
+    }};
+}
+
+macro_rules! imageop_mut {
+    ($img:   ident,
+     $op:    ident $(, $args: ident)*) => {{
+         let mut img = $img;
+         img.$op($($args),*);
+         img
+    }};
+}
+
+mod adjust;
+mod autocrop;
+mod blur;
+mod color;
+mod dither;
+mod filter;
+mod geometry;
+mod pixels;
+mod resize;
+mod threshold;
+mod toning;
+
+pub use adjust::{Brighten, Brightness, Channels, Contrast, Curves, Exposure, Gamma, Levels};
+pub use autocrop::{Metric, SmartCrop, Trim};
+pub use blur::{Blur, Kernel, KernelBlur, SpinBlur, Unit, ZoomBlur};
+pub use color::{
+    ColorModel, Desaturate, Grayscale, Hue, Invert, InvertChannels, InvertLightness, Modulate,
+    Weights,
+};
+pub use dither::{Colors, Dither, Dithering};
+pub use filter::Sharpen;
+pub use geometry::{
+    Aspect, Canvas, Crop, CropRegion, Flip, Flop, Geometry, Gravity, Interpolation, Length,
+    Orientation, Rotate, Transpose, Transverse,
+};
+pub use resize::{Filter, Fit, Resize, Size};
+pub use threshold::{Adaptive, AdaptiveThreshold, Posterize, Threshold};
+pub use toning::{Colorize, Duotone, Sepia, Tint};
+
+/// An image transformation that consumes an image and returns the result.
+pub trait Operation: Debug + Send + Sync {
+    // ---
+
+    /// Apply the operation to `img`.
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage>;
+}
+
+impl<T: Operation + ?Sized> Operation for Box<T> {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        (**self).apply(img)
+    }
+}
+
+/// A sequence of operations applied in order to the same in-memory image.
+#[derive(Debug, Default)]
+pub struct Pipeline {
+    steps: Vec<Box<dyn Operation>>,
+}
+
+impl Pipeline {
+    // ---
+
+    /// Create an empty pipeline, which returns its input unchanged.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append `op` to the end of the pipeline.
+    pub fn then(mut self, op: impl Operation + 'static) -> Self {
+        self.push(Box::new(op));
+        self
+    }
+
+    /// Append an already boxed operation to the end of the pipeline.
+    pub fn push(&mut self, op: Box<dyn Operation>) {
+        self.steps.push(op);
+    }
+
+    /// Number of operations in the pipeline.
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    /// Whether the pipeline has no operations.
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl FromIterator<Box<dyn Operation>> for Pipeline {
+    // ---
+    fn from_iter<I: IntoIterator<Item = Box<dyn Operation>>>(iter: I) -> Self {
+        Self {
+            steps: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Operation for Pipeline {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        self.steps.iter().try_fold(img, |img, step| step.apply(img))
+    }
+}
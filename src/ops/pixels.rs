@@ -0,0 +1,226 @@
+//! Helpers for operations that work on any color type and bit depth.
+//!
+//! Such an operation is written once, generic over the channel type, for an
+//! RGBA buffer of the input's own depth (8-bit, 16-bit or float), and
+//! [`map_rgba`] converts the result back to the input's color type.
+//! Adjustments of each channel value on its own implement [`ChannelCurve`]
+//! and run through lookup tables with [`map_channels`].
+
+use image::{ColorType, DynamicImage, ImageBuffer, Pixel, Primitive, Rgba};
+use num_traits::NumCast;
+use rayon::prelude::*;
+
+/// An RGBA image with channels of type `T`.
+pub(crate) type RgbaBuffer<T> = ImageBuffer<Rgba<T>, Vec<T>>;
+
+/// An operation on RGBA buffers of any depth.
+pub(crate) trait RgbaMap {
+    // ---
+    fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>;
+}
+
+/// Apply `op` to `img` at its own bit depth and convert the result back to
+/// the color type of `img`. An alpha channel is added when `alpha` is set
+/// and the input has none.
+pub(crate) fn map_rgba(img: DynamicImage, op: &impl RgbaMap, alpha: bool) -> DynamicImage {
+    // ---
+    let color = img.color();
+    let mapped = match color {
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            DynamicImage::ImageRgba16(op.map(img.into_rgba16()))
+        }
+        ColorType::Rgb32F | ColorType::Rgba32F => {
+            DynamicImage::ImageRgba32F(op.map(img.into_rgba32f()))
+        }
+        _ => DynamicImage::ImageRgba8(op.map(img.into_rgba8())),
+    };
+
+    match (color, alpha) {
+        (ColorType::L8, false) => DynamicImage::ImageLuma8(mapped.to_luma8()),
+        (ColorType::L8 | ColorType::La8, _) => DynamicImage::ImageLumaA8(mapped.to_luma_alpha8()),
+        (ColorType::Rgb8, false) => DynamicImage::ImageRgb8(mapped.to_rgb8()),
+        (ColorType::L16, false) => DynamicImage::ImageLuma16(mapped.to_luma16()),
+        (ColorType::L16 | ColorType::La16, _) => {
+            DynamicImage::ImageLumaA16(mapped.to_luma_alpha16())
+        }
+        (ColorType::Rgb16, false) => DynamicImage::ImageRgb16(mapped.to_rgb16()),
+        (ColorType::Rgb32F, false) => DynamicImage::ImageRgb32F(mapped.to_rgb32f()),
+        _ => mapped,
+    }
+}
+
+/// An adjustment of every channel value on its own, such as a tone curve.
+pub(crate) trait ChannelCurve: Sync {
+    // ---
+
+    /// The new 0..=1 value of the 0..=1 `value` of `channel`: 0, 1 and 2
+    /// for red, green and blue, 3 for alpha.
+    fn curve(&self, channel: usize, value: f32) -> f32;
+}
+
+/// Apply `curve` to `img` at its own bit depth. 8 and 16-bit channels go
+/// through a lookup table with an entry for every value, so the result is
+/// exact and costs one table build; float channels are computed directly.
+/// An alpha channel is added when `alpha` is set, as for [`map_rgba`].
+pub(crate) fn map_channels(
+    img: DynamicImage,
+    curve: &impl ChannelCurve,
+    alpha: bool,
+) -> DynamicImage {
+    // ---
+    map_rgba(img, &Lookup(curve), alpha)
+}
+
+struct Lookup<'a, C>(&'a C);
+
+impl<C: ChannelCurve> RgbaMap for Lookup<'_, C> {
+    // ---
+    fn map<T>(&self, mut img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let max = channel_max::<T>();
+        if max <= 1.0 {
+            img.par_chunks_mut(4).for_each(|pixel| {
+                for (channel, value) in pixel.iter_mut().enumerate() {
+                    *value = from_unit(self.0.curve(channel, to_unit(*value)));
+                }
+            });
+            return img;
+        }
+
+        let tables: Vec<Vec<T>> = (0..4)
+            .map(|channel| {
+                (0..=max as usize)
+                    .into_par_iter()
+                    .map(|index| from_unit(self.0.curve(channel, index as f32 / max)))
+                    .collect()
+            })
+            .collect();
+        img.par_chunks_mut(4).for_each(|pixel| {
+            for (value, table) in pixel.iter_mut().zip(&tables) {
+                let index: usize = NumCast::from(*value).unwrap_or(0);
+                *value = table[index];
+            }
+        });
+        img
+    }
+}
+
+/// Largest value of a channel type: 255, 65535, or 1.0 for floats.
+pub(crate) fn channel_max<T: Primitive>() -> f32 {
+    // ---
+    NumCast::from(T::DEFAULT_MAX_VALUE).unwrap_or(1.0)
+}
+
+/// A channel value scaled to 0..=1.
+pub(crate) fn to_unit<T: Primitive>(value: T) -> f32 {
+    // ---
+    NumCast::from(value).unwrap_or(0.0) / channel_max::<T>()
+}
+
+/// A 0..=1 value as a channel value, clamped and rounded for integer
+/// channels.
+pub(crate) fn from_unit<T: Primitive>(value: f32) -> T {
+    // ---
+    let max = channel_max::<T>();
+    let scaled = value.clamp(0.0, 1.0) * max;
+    let scaled = if max > 1.0 { scaled.round() } else { scaled };
+    NumCast::from(scaled).unwrap_or(T::DEFAULT_MIN_VALUE)
+}
+
+/// A 0..=1 sRGB encoded value as linear light.
+pub(crate) fn srgb_to_linear(value: f32) -> f32 {
+    // ---
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+/// A 0..=1 linear light value encoded as sRGB.
+pub(crate) fn linear_to_srgb(value: f32) -> f32 {
+    // ---
+    match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+/// An 8-bit color as a color with channels of type `T`.
+pub(crate) fn convert_color<T: Primitive>(color: Rgba<u8>) -> Rgba<T> {
+    // ---
+    Rgba(color.0.map(|c| from_unit(c as f32 / 255.0)))
+}
+
+/// A color with channels scaled to 0..=1 and multiplied by its alpha.
+pub(crate) fn premultiply<T: Primitive>(color: Rgba<T>) -> [f32; 4] {
+    // ---
+    let [r, g, b, a] = color.0.map(to_unit);
+    [r * a, g * a, b * a, a]
+}
+
+/// A premultiplied 0..=1 color as a straight color with channels of type
+/// `T`; colors without alpha become transparent black.
+pub(crate) fn unpremultiply<T: Primitive>(color: [f32; 4]) -> Rgba<T> {
+    // ---
+    let alpha = color[3].clamp(0.0, 1.0);
+    let unmultiply = |c: f32| if alpha > 0.0 { c / alpha } else { 0.0 };
+    Rgba([
+        from_unit(unmultiply(color[0])),
+        from_unit(unmultiply(color[1])),
+        from_unit(unmultiply(color[2])),
+        from_unit(alpha),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{GrayImage, Luma};
+
+    struct Identity;
+
+    impl RgbaMap for Identity {
+        // ---
+        fn map<T>(&self, img: RgbaBuffer<T>) -> RgbaBuffer<T>
+        where
+            T: Primitive + Send + Sync,
+            Rgba<T>: Pixel<Subpixel = T>,
+        {
+            img
+        }
+    }
+
+    #[test]
+    fn test_map_rgba_keeps_color_type() -> Result<()> {
+        // ---
+
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(2, 2, Luma([77])));
+        ensure!(map_rgba(gray.clone(), &Identity, false) == gray);
+        ensure!(map_rgba(gray, &Identity, true).color() == ColorType::La8);
+
+        let deep = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(1, 1, image::Rgb([1, 2, 3])));
+        ensure!(map_rgba(deep.clone(), &Identity, false) == deep);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unit_conversions() -> Result<()> {
+        // ---
+
+        ensure!(from_unit::<u8>(to_unit(200u8)) == 200);
+        ensure!(from_unit::<u16>(1.5) == u16::MAX, "Values clamp");
+        ensure!(from_unit::<f32>(0.25) == 0.25, "Floats aren't rounded");
+        ensure!(convert_color::<u16>(Rgba([255, 0, 0, 255])) == Rgba([65535, 0, 0, 65535]));
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+use super::geometry::{place, Gravity};
+use super::Operation;
+use crate::check_size;
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, Rgba};
+use std::str::FromStr;
+
+/// Target size of a [`Resize`].
+///
+/// Parsed from `WxH`, `Wx` or `xH` (the missing side keeps the aspect
+/// ratio), or a percentage such as `50%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Size {
+    /// A `width` x `height` box, filled according to the [`Fit`].
+    Box {
+        width: u32,
+        height: u32,
+    },
+    Width(u32),
+    Height(u32),
+    Percent(f32),
+}
+
+impl FromStr for Size {
+    // ---
+    type Err = anyhow::Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        // ---
+        let invalid = || format!("Invalid size {}, use WxH, Wx, xH or a percentage", text);
+        if let Some(percent) = text.strip_suffix('%') {
+            let percent: f32 = percent.trim().parse().ok().context(invalid())?;
+            anyhow::ensure!(percent.is_finite() && percent > 0.0, invalid());
+            return Ok(Self::Percent(percent));
+        }
+
+        let (width, height) = text.split_once(['x', 'X']).context(invalid())?;
+        let parse = |side: &str| -> Result<Option<u32>> {
+            match side.trim() {
+                "" => Ok(None),
+                side => match side.parse::<u32>() {
+                    Ok(value) if value > 0 => Ok(Some(value)),
+                    _ => Err(anyhow::anyhow!(invalid())),
+                },
+            }
+        };
+        match (parse(width)?, parse(height)?) {
+            (Some(width), Some(height)) => Ok(Self::Box { width, height }),
+            (Some(width), None) => Ok(Self::Width(width)),
+            (None, Some(height)) => Ok(Self::Height(height)),
+            (None, None) => Err(anyhow::anyhow!(invalid())),
+        }
+    }
+}
+
+/// How an image is fitted into a `WxH` [`Size::Box`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Fit {
+    /// exactly WxH, ignoring the aspect ratio
+    #[default]
+    Stretch,
+    /// as large as fits inside WxH, keeping the aspect ratio
+    Inside,
+    /// fit inside and letterbox to exactly WxH with the background color
+    Contain,
+    /// fill WxH, keeping the aspect ratio and cropping the overflow
+    Cover,
+}
+
+/// Resampling filter of a [`Resize`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Filter {
+    /// closest pixel, blocky but exact for pixel art
+    Nearest,
+    /// linear, fast and soft
+    Triangle,
+    /// cubic, sharp
+    CatmullRom,
+    /// gaussian, smooth
+    Gaussian,
+    /// windowed sinc, sharpest
+    #[default]
+    Lanczos3,
+}
+
+impl From<Filter> for FilterType {
+    // ---
+    fn from(filter: Filter) -> Self {
+        match filter {
+            Filter::Nearest => FilterType::Nearest,
+            Filter::Triangle => FilterType::Triangle,
+            Filter::CatmullRom => FilterType::CatmullRom,
+            Filter::Gaussian => FilterType::Gaussian,
+            Filter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Scale to `size`.
+///
+/// A [`Size::Box`] is filled according to `fit`, with `gravity` choosing
+/// which part is kept by [`Fit::Cover`] and where [`Fit::Contain`] places
+/// the image on its `background`. With `shrink_only` images are never
+/// enlarged, as for thumbnails; a letterbox is still padded to the box.
+/// Results larger than [`check_size`] allows are an error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resize {
+    pub size: Size,
+    pub fit: Fit,
+    pub filter: Filter,
+    pub gravity: Gravity,
+    pub background: Rgba<u8>,
+    pub shrink_only: bool,
+}
+
+impl Resize {
+    // ---
+
+    /// Resize to `size` with Lanczos3, stretching a box, centered on black.
+    pub fn new(size: Size) -> Self {
+        // ---
+        Self {
+            size,
+            fit: Fit::default(),
+            filter: Filter::default(),
+            gravity: Gravity::default(),
+            background: Rgba([0, 0, 0, 255]),
+            shrink_only: false,
+        }
+    }
+}
+
+impl Operation for Resize {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        let (width, height) = img.dimensions();
+        let (w, h) = (width as f64, height as f64);
+        let limit = |scale: f64| match self.shrink_only {
+            true => scale.min(1.0),
+            false => scale,
+        };
+        let scaled = |scale: f64| {
+            let scale = limit(scale);
+            (
+                ((w * scale).round() as u32).max(1),
+                ((h * scale).round() as u32).max(1),
+            )
+        };
+
+        let target = match self.size {
+            Size::Percent(percent) => scaled(percent as f64 / 100.0),
+            Size::Width(new_width) => scaled(new_width as f64 / w),
+            Size::Height(new_height) => scaled(new_height as f64 / h),
+            Size::Box {
+                width: box_width,
+                height: box_height,
+            } => {
+                let (x_scale, y_scale) = (box_width as f64 / w, box_height as f64 / h);
+                match self.fit {
+                    Fit::Stretch => (
+                        ((w * limit(x_scale)).round() as u32).max(1),
+                        ((h * limit(y_scale)).round() as u32).max(1),
+                    ),
+                    Fit::Inside | Fit::Contain => {
+                        // Snap the constrained side to the box exactly
+                        let (mut fit_width, mut fit_height) = scaled(x_scale.min(y_scale));
+                        if !self.shrink_only || x_scale.min(y_scale) <= 1.0 {
+                            if x_scale <= y_scale {
+                                fit_width = box_width;
+                            } else {
+                                fit_height = box_height;
+                            }
+                        }
+                        (fit_width.min(box_width), fit_height.min(box_height))
+                    }
+                    Fit::Cover => {
+                        let (cover_width, cover_height) = scaled(x_scale.max(y_scale));
+                        if !self.shrink_only || x_scale.max(y_scale) <= 1.0 {
+                            (cover_width.max(box_width), cover_height.max(box_height))
+                        } else {
+                            (cover_width, cover_height)
+                        }
+                    }
+                }
+            }
+        };
+
+        check_size(target)?;
+        if let (Size::Box { width, height }, Fit::Contain) = (self.size, self.fit) {
+            check_size((width, height))?;
+        }
+
+        let img = match target == (width, height) {
+            true => img,
+            false => img.resize_exact(target.0, target.1, self.filter.into()),
+        };
+
+        match (self.size, self.fit) {
+            (Size::Box { width, height }, Fit::Contain) => {
+                let offset = self.gravity.offset((width, height), target);
+                Ok(place(img, (width, height), offset, self.background))
+            }
+            (Size::Box { width, height }, Fit::Cover) => {
+                let crop = (width.min(target.0), height.min(target.1));
+                let (x, y) = self.gravity.offset(target, crop);
+                Ok(img.crop_imm(x as u32, y as u32, crop.0, crop.1))
+            }
+            _ => Ok(img),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::RgbImage;
+
+    fn resize(size: &str, fit: Fit, width: u32, height: u32) -> Result<DynamicImage> {
+        // ---
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, image::Rgb([9; 3])));
+        Resize {
+            fit,
+            ..Resize::new(size.parse()?)
+        }
+        .apply(img)
+    }
+
+    #[test]
+    fn test_size_parsing() -> Result<()> {
+        // ---
+
+        ensure!(
+            "800x600".parse::<Size>()?
+                == Size::Box {
+                    width: 800,
+                    height: 600
+                }
+        );
+        ensure!("800x".parse::<Size>()? == Size::Width(800));
+        ensure!("x600".parse::<Size>()? == Size::Height(600));
+        ensure!("12.5%".parse::<Size>()? == Size::Percent(12.5));
+        for invalid in ["800", "x", "0x10", "-5x5", "abc%", "0%", "axb"] {
+            ensure!(invalid.parse::<Size>().is_err(), "{} should fail", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fit_modes() -> Result<()> {
+        // ---
+
+        let dimensions =
+            |size, fit| -> Result<(u32, u32)> { Ok(resize(size, fit, 400, 200)?.dimensions()) };
+        ensure!(dimensions("100x100", Fit::Stretch)? == (100, 100));
+        ensure!(dimensions("100x100", Fit::Inside)? == (100, 50));
+        ensure!(dimensions("100x100", Fit::Contain)? == (100, 100));
+        ensure!(dimensions("100x100", Fit::Cover)? == (100, 100));
+        ensure!(dimensions("100x", Fit::Stretch)? == (100, 50));
+        ensure!(
+            dimensions("x50", Fit::Cover)? == (100, 50),
+            "Fit only applies to boxes"
+        );
+        ensure!(dimensions("25%", Fit::Stretch)? == (100, 50));
+        ensure!(
+            dimensions("333x333", Fit::Inside)? == (333, 167),
+            "Aspect ratio rounds to the nearest pixel"
+        );
+
+        for huge in ["1000000%", "40000x40000", "70000x", "x4000000000"] {
+            ensure!(
+                resize(huge, Fit::Stretch, 400, 200).is_err(),
+                "{} should be too large",
+                huge
+            );
+        }
+        ensure!(resize("100000x100000", Fit::Contain, 400, 200).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_contain_letterboxes_with_gravity() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgb8(RgbImage::from_pixel(40, 20, image::Rgb([200, 0, 0])));
+        let boxed = Resize {
+            fit: Fit::Contain,
+            gravity: Gravity::North,
+            background: Rgba([0, 0, 255, 255]),
+            ..Resize::new("20x20".parse()?)
+        }
+        .apply(img)?
+        .to_rgb8();
+        ensure!(
+            *boxed.get_pixel(10, 0) == image::Rgb([200, 0, 0]),
+            "Image at the top"
+        );
+        ensure!(
+            *boxed.get_pixel(10, 19) == image::Rgb([0, 0, 255]),
+            "Letterbox below"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cover_crops_with_gravity() -> Result<()> {
+        // ---
+
+        // Left half red, right half green
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, _| match x < 20 {
+            true => image::Rgb([255, 0, 0]),
+            false => image::Rgb([0, 255, 0]),
+        }));
+        let cover = |gravity| -> Result<image::Rgb<u8>> {
+            let img = Resize {
+                fit: Fit::Cover,
+                gravity,
+                filter: Filter::Nearest,
+                ..Resize::new("10x20".parse()?)
+            }
+            .apply(img.clone())?;
+            ensure!(img.dimensions() == (10, 20));
+            Ok(*img.to_rgb8().get_pixel(5, 10))
+        };
+        ensure!(cover(Gravity::West)? == image::Rgb([255, 0, 0]));
+        ensure!(cover(Gravity::East)? == image::Rgb([0, 255, 0]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shrink_only_never_enlarges() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgb8(RgbImage::new(40, 20));
+        let shrink = |size: &str, fit| -> Result<(u32, u32)> {
+            Ok(Resize {
+                fit,
+                shrink_only: true,
+                ..Resize::new(size.parse()?)
+            }
+            .apply(img.clone())?
+            .dimensions())
+        };
+        ensure!(shrink("400x400", Fit::Inside)? == (40, 20));
+        ensure!(shrink("200%", Fit::Stretch)? == (40, 20));
+        ensure!(shrink("20x20", Fit::Inside)? == (20, 10));
+        ensure!(
+            shrink("100x100", Fit::Contain)? == (100, 100),
+            "Still padded"
+        );
+        Ok(())
+    }
+}
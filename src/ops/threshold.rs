@@ -0,0 +1,389 @@
+//! Reducing the number of tones: [`Threshold`] and [`AdaptiveThreshold`]
+//! turn an image black and white by its luma, the gray value
+//! [`Grayscale`](super::Grayscale) computes, and [`Posterize`] keeps a few
+//! levels per channel.
+
+use super::autocrop::SummedArea;
+use super::pixels::{map_channels, ChannelCurve};
+use super::Operation;
+use anyhow::Result;
+use image::{DynamicImage, GrayImage};
+use rayon::prelude::*;
+
+/// Luma values of `img` in 0..=1, row by row.
+fn luma(img: &DynamicImage) -> Vec<f32> {
+    // ---
+    img.to_luma32f().into_raw()
+}
+
+/// A black and white version of `img`, white where `white` holds for the
+/// index and value of a pixel in `luma`, the luma of `img`. The result is an
+/// 8-bit grayscale image, with the alpha of `img` if it has one.
+fn binarize(
+    img: &DynamicImage,
+    luma: Vec<f32>,
+    white: impl Fn(usize, f32) -> bool + Sync,
+) -> DynamicImage {
+    // ---
+    let values: Vec<u8> = luma
+        .into_par_iter()
+        .enumerate()
+        .map(|(index, luma)| if white(index, luma) { 255 } else { 0 })
+        .collect();
+
+    if img.color().has_alpha() {
+        let mut img = img.to_luma_alpha8();
+        for (pixel, value) in img.pixels_mut().zip(values) {
+            pixel[0] = value;
+        }
+        return DynamicImage::ImageLumaA8(img);
+    }
+    let (width, height) = (img.width(), img.height());
+    DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, values).unwrap_or_default())
+}
+
+/// Turn pixels with a luma above `level`, a fraction of the range, white
+/// and the rest black. Without a level, Otsu's method picks the one that
+/// best separates the dark and light pixels of the image.
+///
+/// The result is an 8-bit grayscale image that keeps any alpha.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Threshold {
+    pub level: Option<f32>,
+}
+
+impl Threshold {
+    // ---
+
+    /// Threshold at the level picked by Otsu's method.
+    pub fn otsu() -> Self {
+        Self::default()
+    }
+
+    /// The level `img` is thresholded at.
+    pub fn level(&self, img: &DynamicImage) -> f32 {
+        // ---
+        self.level.unwrap_or_else(|| otsu_level(&luma(img)))
+    }
+}
+
+impl Operation for Threshold {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        if let Some(level) = self.level {
+            anyhow::ensure!(
+                (0.0..=1.0).contains(&level),
+                "Invalid threshold:{}% must be 0-100",
+                level * 100.0
+            );
+        }
+        let values = luma(&img);
+        let level = self.level.unwrap_or_else(|| otsu_level(&values));
+        Ok(binarize(&img, values, |_, luma| luma > level))
+    }
+}
+
+/// Otsu's threshold of `luma` values: the level between two classes of
+/// the 256 level histogram with the largest variance between them.
+fn otsu_level(luma: &[f32]) -> f32 {
+    // ---
+    let mut histogram = [0u64; 256];
+    for value in luma {
+        histogram[(value.clamp(0.0, 1.0) * 255.0).round() as usize] += 1;
+    }
+    let total = luma.len() as f64;
+    let sum: f64 = (0..256).map(|i| i as f64 * histogram[i] as f64).sum();
+
+    let (mut below, mut sum_below) = (0.0, 0.0);
+    let (mut best, mut level) = (-1.0, 0);
+    for (i, &count) in histogram.iter().enumerate() {
+        below += count as f64;
+        sum_below += i as f64 * count as f64;
+        let above = total - below;
+        if below == 0.0 || above == 0.0 {
+            continue;
+        }
+        let difference = sum_below / below - (sum - sum_below) / above;
+        let variance = below * above * difference * difference;
+        if variance > best {
+            best = variance;
+            level = i;
+        }
+    }
+    (level as f32 + 0.5) / 255.0
+}
+
+/// Reduce every color channel to `levels` evenly spaced values, leaving
+/// alpha untouched. Keeps the color type and bit depth.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Posterize {
+    pub levels: u32,
+}
+
+impl Operation for Posterize {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.levels >= 2,
+            "Invalid posterize levels:{} must be 2 or more",
+            self.levels
+        );
+        Ok(map_channels(img, self, false))
+    }
+}
+
+impl ChannelCurve for Posterize {
+    // ---
+    fn curve(&self, channel: usize, value: f32) -> f32 {
+        // ---
+        if channel == 3 {
+            return value;
+        }
+        let steps = (self.levels - 1) as f32;
+        (value * steps).round() / steps
+    }
+}
+
+/// Standard deviation of the luma below which a window has no contrast to
+/// set a Niblack level by, about 2 of 255 levels.
+const MIN_CONTRAST: f64 = 2.0 / 255.0;
+
+/// How [`AdaptiveThreshold`] computes the local level from the mean `m`
+/// and standard deviation `s` of the luma around a pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Adaptive {
+    /// m * (1 + k * (s / 0.5 - 1)), robust on stained and unevenly lit pages
+    #[default]
+    Sauvola,
+    /// m + k * s, keeps faint strokes but adds noise in empty areas; areas
+    /// without contrast are split at mid gray
+    Niblack,
+}
+
+impl Adaptive {
+    // ---
+
+    /// The usual `k` of the method.
+    pub fn k(self) -> f32 {
+        // ---
+        match self {
+            Self::Sauvola => 0.2,
+            Self::Niblack => -0.2,
+        }
+    }
+}
+
+/// Turn an image black and white at a level that follows the local
+/// brightness, for scans with shadows or uneven lighting.
+///
+/// The level of each pixel is computed by `method` from the mean and
+/// standard deviation of the luma in the `window` x `window` square around
+/// it, cut off at the image edges, and `k` sets how far below the mean it
+/// lies. The result is an 8-bit grayscale image that keeps any alpha.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdaptiveThreshold {
+    pub method: Adaptive,
+    pub window: u32,
+    pub k: f32,
+}
+
+impl AdaptiveThreshold {
+    // ---
+
+    /// Threshold by `method` in a 25 pixel window with its usual `k`.
+    pub fn new(method: Adaptive) -> Self {
+        // ---
+        Self {
+            method,
+            window: 25,
+            k: method.k(),
+        }
+    }
+}
+
+impl Operation for AdaptiveThreshold {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        anyhow::ensure!(
+            self.window >= 3,
+            "Invalid window:{} must be 3 or more pixels",
+            self.window
+        );
+        anyhow::ensure!(self.k.is_finite(), "Invalid k:{} must be a number", self.k);
+
+        let (width, height) = (img.width(), img.height());
+        let values = luma(&img);
+        let at = |x: u32, y: u32| values[y as usize * width as usize + x as usize] as f64;
+        let sums = SummedArea::new(width, height, at);
+        let squares = SummedArea::new(width, height, |x, y| at(x, y) * at(x, y));
+
+        let half = self.window / 2;
+        let k = self.k as f64;
+        Ok(binarize(&img, values, |index, luma| {
+            let (x, y) = (index % width as usize, index / width as usize);
+            let (x, y) = (x as u32, y as u32);
+            let (left, top) = (x.saturating_sub(half), y.saturating_sub(half));
+            let size = (
+                (x + half + 1).min(width) - left,
+                (y + half + 1).min(height) - top,
+            );
+            let count = (size.0 * size.1) as f64;
+            let mean = sums.sum(left, top, size) / count;
+            let variance = squares.sum(left, top, size) / count - mean * mean;
+            let deviation = variance.max(0.0).sqrt();
+            let level = match self.method {
+                Adaptive::Sauvola => mean * (1.0 + k * (deviation / 0.5 - 1.0)),
+                // A blank area would sit at its own mean and turn black
+                Adaptive::Niblack if deviation < MIN_CONTRAST => 0.5,
+                Adaptive::Niblack => mean + k * deviation,
+            };
+            luma as f64 > level
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{ColorType, ImageBuffer, Luma, LumaA, Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn test_threshold_fixed_and_otsu() -> Result<()> {
+        // ---
+
+        // Dark text values around 40 and paper around 200
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(20, 10, |x, y| {
+            let value = if (x + y) % 4 == 0 {
+                30 + x as u8
+            } else {
+                190 + y as u8
+            };
+            Rgb([value, value, value])
+        }));
+        let level = Threshold::otsu().level(&img);
+        ensure!(level > 49.0 / 255.0 && level < 190.0 / 255.0, "{}", level);
+
+        for threshold in [Threshold::otsu(), Threshold { level: Some(0.5) }] {
+            let binary = threshold.apply(img.clone())?;
+            ensure!(binary.color() == ColorType::L8);
+            let original = img.to_luma8();
+            ensure!(binary
+                .into_luma8()
+                .pixels()
+                .zip(original.pixels())
+                .all(|(b, o)| b[0] == if o[0] > 100 { 255 } else { 0 }));
+        }
+
+        let high = Threshold { level: Some(1.0) }.apply(img.clone())?;
+        ensure!(high.into_luma8().pixels().all(|p| p[0] == 0), "All black");
+        ensure!(Threshold { level: Some(1.5) }.apply(img).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_threshold_keeps_alpha() -> Result<()> {
+        // ---
+
+        let img = RgbaImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([250, 250, 250, 40]),
+            _ => Rgba([10, 10, 10, 255]),
+        });
+        let binary = Threshold { level: Some(0.5) }.apply(DynamicImage::ImageRgba8(img))?;
+        ensure!(binary.color() == ColorType::La8);
+        let binary = binary.into_luma_alpha8();
+        ensure!(binary.get_pixel(0, 0) == &LumaA([255, 40]));
+        ensure!(binary.get_pixel(1, 0) == &LumaA([0, 255]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_posterize_levels() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(256, 1, |x, _| Luma([x as u8])));
+        let poster = Posterize { levels: 4 }.apply(img)?.into_luma8();
+        let mut values: Vec<u8> = poster.pixels().map(|p| p[0]).collect();
+        values.dedup();
+        ensure!(values == [0, 85, 170, 255], "{:?}", values);
+
+        let deep = DynamicImage::ImageLuma16(ImageBuffer::from_pixel(1, 1, Luma([30000u16])));
+        let poster = Posterize { levels: 3 }.apply(deep)?;
+        ensure!(poster.color() == ColorType::L16);
+        ensure!(poster.into_luma16().get_pixel(0, 0)[0] == 32768);
+        ensure!(Posterize { levels: 1 }
+            .apply(DynamicImage::new_luma8(1, 1))
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_adaptive_threshold_follows_lighting() -> Result<()> {
+        // ---
+
+        // Paper fading from 230 to 90 with text strokes 60 darker; the
+        // darkest paper is darker than the lightest text
+        let text = |x: u32, y: u32| x % 10 == 3 && y % 8 < 5;
+        let img = DynamicImage::ImageLuma8(GrayImage::from_fn(120, 40, |x, y| {
+            let paper = 230.0 - 140.0 * x as f32 / 119.0;
+            Luma([(paper - if text(x, y) { 60.0 } else { 0.0 }) as u8])
+        }));
+
+        let global = Threshold::otsu().apply(img.clone())?.into_luma8();
+        ensure!(
+            global
+                .enumerate_pixels()
+                .any(|(x, y, p)| (p[0] == 0) != text(x, y)),
+            "A global level can't separate them"
+        );
+        for method in [Adaptive::Sauvola, Adaptive::Niblack] {
+            let binary = AdaptiveThreshold {
+                window: 15,
+                ..AdaptiveThreshold::new(method)
+            }
+            .apply(img.clone())?
+            .into_luma8();
+            let wrong = binary
+                .enumerate_pixels()
+                .filter(|(x, y, p)| (p[0] == 0) != text(*x, *y))
+                .count();
+            ensure!(wrong == 0, "{:?} got {} pixels wrong", method, wrong);
+        }
+
+        // A blank margin wider than the window next to lines of text
+        let line = |x: u32, y: u32| x >= 40 && y % 8 < 2;
+        let page = DynamicImage::ImageLuma8(GrayImage::from_fn(80, 40, |x, y| {
+            Luma([if line(x, y) { 20 } else { 200 }])
+        }));
+        for method in [Adaptive::Sauvola, Adaptive::Niblack] {
+            let binary = AdaptiveThreshold {
+                window: 15,
+                ..AdaptiveThreshold::new(method)
+            }
+            .apply(page.clone())?
+            .into_luma8();
+            let wrong = binary
+                .enumerate_pixels()
+                .filter(|(x, y, p)| (p[0] == 0) != line(*x, *y))
+                .count();
+            ensure!(
+                wrong == 0,
+                "{:?} got {} pixels of the page wrong",
+                method,
+                wrong
+            );
+        }
+
+        let small = AdaptiveThreshold {
+            window: 1,
+            ..AdaptiveThreshold::new(Adaptive::Sauvola)
+        };
+        ensure!(small.apply(img).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,375 @@
+//! Toning effects that recolor an image by its luma, the gray value
+//! [`Grayscale`](super::Grayscale) computes: [`Sepia`], [`Duotone`] and
+//! [`Colorize`] map it onto colors, and [`Tint`] shifts the midtones
+//! toward a color.
+//!
+//! Grayscale inputs are turned into RGB images so they can take on color.
+
+use super::color::Weights;
+use super::pixels::{from_unit, map_rgba, to_unit, RgbaBuffer, RgbaMap};
+use super::Operation;
+use crate::generate::{interpolate, resolve_stops, ColorStop};
+use anyhow::Result;
+use image::{ColorType, DynamicImage, Pixel, Primitive, Rgba};
+use rayon::prelude::*;
+
+/// The luma of a 0..=1 `rgb` color.
+fn luma(rgb: [f32; 3]) -> f32 {
+    // ---
+    Weights::Rec709.gray(rgb)
+}
+
+/// `img` with color channels, so that grayscale images can be toned.
+fn with_color(img: DynamicImage) -> DynamicImage {
+    // ---
+    match img.color() {
+        ColorType::L8 => DynamicImage::ImageRgb8(img.into_rgb8()),
+        ColorType::La8 => DynamicImage::ImageRgba8(img.into_rgba8()),
+        ColorType::L16 => DynamicImage::ImageRgb16(img.into_rgb16()),
+        ColorType::La16 => DynamicImage::ImageRgba16(img.into_rgba16()),
+        _ => img,
+    }
+}
+
+/// Check that `amount` of an effect is a fraction from 0 to 1.
+fn check_amount(effect: &str, amount: f32) -> Result<()> {
+    // ---
+    anyhow::ensure!(
+        (0.0..=1.0).contains(&amount),
+        "Invalid {}:{}% must be 0-100",
+        effect,
+        amount * 100.0
+    );
+    Ok(())
+}
+
+/// Replace every color by the color its luma falls on along a gradient,
+/// blended with the original by `amount`. The pixel's alpha is scaled by
+/// the alpha of the gradient color.
+struct LumaMap {
+    /// Positions in 0..=1 and colors in 0-255, as from [`resolve_stops`].
+    stops: Vec<(f32, [f32; 4])>,
+    amount: f32,
+}
+
+impl LumaMap {
+    // ---
+
+    /// Map `img` through the gradient, adding alpha when it has
+    /// translucent colors.
+    fn apply(&self, img: DynamicImage) -> DynamicImage {
+        // ---
+        let alpha = self.stops.iter().any(|(_, color)| color[3] < 255.0);
+        map_rgba(with_color(img), self, alpha)
+    }
+}
+
+impl RgbaMap for LumaMap {
+    // ---
+    fn map<T>(&self, mut img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        img.par_chunks_mut(4).for_each(|pixel| {
+            let rgba = [pixel[0], pixel[1], pixel[2], pixel[3]].map(to_unit);
+            let gray = luma([rgba[0], rgba[1], rgba[2]]);
+            let tone = interpolate(&self.stops, gray).map(|c| c / 255.0);
+            for channel in 0..3 {
+                let value = rgba[channel];
+                pixel[channel] = from_unit(value + self.amount * (tone[channel] - value));
+            }
+            pixel[3] = from_unit(rgba[3] * (1.0 + self.amount * (tone[3] - 1.0)));
+        });
+        img
+    }
+}
+
+/// Give an image the brown tones of an old photograph by `amount`, from 0
+/// for none to 1 for full sepia.
+///
+/// The luma runs from black through [`Sepia::TONE`] to white, so the
+/// tone is strongest in the midtones and lightness is kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sepia {
+    pub amount: f32,
+}
+
+impl Sepia {
+    // ---
+
+    /// The color of sepia ink.
+    pub const TONE: Rgba<u8> = Rgba([0x70, 0x42, 0x14, 0xff]);
+}
+
+impl Default for Sepia {
+    // ---
+    fn default() -> Self {
+        Self { amount: 1.0 }
+    }
+}
+
+impl Operation for Sepia {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        check_amount("sepia", self.amount)?;
+        let tone = Self::TONE.0.map(|c| c as f32 / 255.0);
+        let stops = [
+            ColorStop {
+                color: Rgba([0, 0, 0, 255]),
+                position: None,
+            },
+            ColorStop {
+                color: Self::TONE,
+                position: Some(luma([tone[0], tone[1], tone[2]])),
+            },
+            ColorStop {
+                color: Rgba([255, 255, 255, 255]),
+                position: None,
+            },
+        ];
+        Ok(LumaMap {
+            stops: resolve_stops(&stops)?,
+            amount: self.amount,
+        }
+        .apply(img))
+    }
+}
+
+/// Map luma onto a gradient from `shadows` for black to `highlights` for
+/// white.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Duotone {
+    pub shadows: Rgba<u8>,
+    pub highlights: Rgba<u8>,
+}
+
+impl Operation for Duotone {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        Colorize {
+            stops: [self.shadows, self.highlights]
+                .map(|color| ColorStop {
+                    color,
+                    position: None,
+                })
+                .to_vec(),
+        }
+        .apply(img)
+    }
+}
+
+/// Map luma onto a gradient through `stops`, black at position 0 and
+/// white at 1, as a gradient map. At least two stops are needed; missing
+/// positions are resolved as for [`Gradient`](crate::Gradient).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Colorize {
+    pub stops: Vec<ColorStop>,
+}
+
+impl Operation for Colorize {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        Ok(LumaMap {
+            stops: resolve_stops(&self.stops)?,
+            amount: 1.0,
+        }
+        .apply(img))
+    }
+}
+
+/// Shift the midtones toward the hue of `color` by `amount`, from 0 for
+/// none to 1 for full strength, while black and white stay as they are.
+///
+/// Only the color's difference from its own luma is added, so lightness
+/// barely changes and the image's own colors show through. The alpha of
+/// `color` is ignored.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tint {
+    pub color: Rgba<u8>,
+    pub amount: f32,
+}
+
+impl Tint {
+    // ---
+
+    /// Tint with `color` at full strength.
+    pub fn new(color: Rgba<u8>) -> Self {
+        // ---
+        Self { color, amount: 1.0 }
+    }
+}
+
+impl Operation for Tint {
+    // ---
+    fn apply(&self, img: DynamicImage) -> Result<DynamicImage> {
+        // ---
+        check_amount("tint", self.amount)?;
+        Ok(map_rgba(with_color(img), self, false))
+    }
+}
+
+impl RgbaMap for Tint {
+    // ---
+    fn map<T>(&self, mut img: RgbaBuffer<T>) -> RgbaBuffer<T>
+    where
+        T: Primitive + Send + Sync,
+        Rgba<T>: Pixel<Subpixel = T>,
+    {
+        // ---
+        let color = [self.color[0], self.color[1], self.color[2]].map(|c| c as f32 / 255.0);
+        let gray = luma(color);
+        let shift = color.map(|c| self.amount * (c - gray));
+
+        img.par_chunks_mut(4).for_each(|pixel| {
+            let rgb = [pixel[0], pixel[1], pixel[2]].map(to_unit);
+            let gray = luma(rgb).clamp(0.0, 1.0);
+            // Strongest at mid gray, none at black and white
+            let weight = 4.0 * gray * (1.0 - gray);
+            for (channel, (value, shift)) in pixel.iter_mut().zip(rgb.iter().zip(shift)) {
+                *channel = from_unit(value + weight * shift);
+            }
+        });
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    // ---
+
+    use super::*;
+    use anyhow::{ensure, Result};
+    use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage, RgbaImage};
+
+    /// A horizontal ramp from black to white.
+    fn ramp() -> DynamicImage {
+        // ---
+        DynamicImage::ImageLuma8(GrayImage::from_fn(256, 1, |x, _| Luma([x as u8])))
+    }
+
+    #[test]
+    fn test_sepia_keeps_lightness() -> Result<()> {
+        // ---
+
+        let toned = Sepia::default().apply(ramp())?;
+        ensure!(toned.color() == ColorType::Rgb8, "Gray inputs get color");
+        let toned = toned.into_rgb8();
+        ensure!(toned.get_pixel(0, 0) == &Rgb([0, 0, 0]));
+        ensure!(toned.get_pixel(255, 0) == &Rgb([255, 255, 255]));
+        // The tone's luma is 72.5 of 255
+        let [r, g, b] = toned.get_pixel(72, 0).0;
+        ensure!(
+            r.abs_diff(0x70) <= 1 && g.abs_diff(0x42) <= 1 && b.abs_diff(0x14) <= 1,
+            "The tone sits at its own luma: {:?}",
+            (r, g, b)
+        );
+        for (x, _, pixel) in toned.enumerate_pixels() {
+            let [r, g, b] = pixel.0.map(|c| c as f32 / 255.0);
+            ensure!(r >= g && g >= b, "Brown at {}: {:?}", x, pixel);
+            ensure!((luma([r, g, b]) - x as f32 / 255.0).abs() < 0.01);
+        }
+
+        let none = Sepia { amount: 0.0 }.apply(ramp())?.into_luma8();
+        ensure!(none == ramp().into_luma8(), "Zero amount changes nothing");
+        ensure!(Sepia { amount: 1.5 }.apply(ramp()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_duotone_and_colorize_map_luma() -> Result<()> {
+        // ---
+
+        let duotone = Duotone {
+            shadows: Rgba([0, 0, 255, 255]),
+            highlights: Rgba([255, 255, 0, 255]),
+        };
+        let toned = duotone.apply(ramp())?.into_rgb8();
+        ensure!(toned.get_pixel(0, 0) == &Rgb([0, 0, 255]));
+        ensure!(toned.get_pixel(255, 0) == &Rgb([255, 255, 0]));
+        ensure!(toned.get_pixel(128, 0) == &Rgb([128, 128, 127]));
+
+        // Colors with about the same luma get about the same tone
+        let img = RgbImage::from_fn(2, 1, |x, _| match x {
+            0 => Rgb([255, 0, 0]),
+            _ => Rgb([54, 54, 54]),
+        });
+        let colorize = Colorize {
+            stops: ["black", "red@25%", "yellow", "white"]
+                .iter()
+                .map(|s| s.parse())
+                .collect::<Result<_>>()?,
+        };
+        let toned = colorize.apply(DynamicImage::ImageRgb8(img))?.into_rgb8();
+        ensure!(toned.get_pixel(0, 0) == &Rgb([217, 0, 0]), "{:?}", toned);
+        ensure!(toned.get_pixel(1, 0) == &Rgb([216, 0, 0]), "{:?}", toned);
+
+        let one = Colorize {
+            stops: vec!["red".parse()?],
+        };
+        ensure!(one.apply(ramp()).is_err(), "Needs two stops");
+        Ok(())
+    }
+
+    #[test]
+    fn test_toning_keeps_depth_and_alpha() -> Result<()> {
+        // ---
+
+        let img = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            2,
+            2,
+            Rgba([30000, 30000, 30000, 1234]),
+        ));
+        let toned = Sepia::default().apply(img.clone())?;
+        ensure!(toned.color() == ColorType::Rgba16);
+        let pixel = *toned.into_rgba16().get_pixel(0, 0);
+        ensure!(pixel[3] == 1234 && pixel[0] % 257 != 0, "{:?}", pixel);
+
+        let toned = Tint::new(Rgba([0, 128, 255, 0])).apply(img)?;
+        ensure!(toned.into_rgba16().get_pixel(0, 0)[3] == 1234);
+
+        // A translucent stop adds alpha
+        let colorize = Colorize {
+            stops: vec!["black".parse()?, "#ffffff00".parse()?],
+        };
+        let toned = colorize.apply(ramp())?;
+        ensure!(toned.color() == ColorType::Rgba8);
+        let toned = toned.into_rgba8();
+        ensure!(toned.get_pixel(0, 0)[3] == 255 && toned.get_pixel(255, 0)[3] == 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_tint_shifts_midtones() -> Result<()> {
+        // ---
+
+        let img = RgbaImage::from_fn(3, 1, |x, _| Rgba([[0, 128, 255][x as usize]; 4]));
+        let blue = Rgba([64, 96, 192, 255]);
+        let tinted = Tint::new(blue)
+            .apply(DynamicImage::ImageRgba8(img))?
+            .into_rgba8();
+        ensure!(tinted.get_pixel(0, 0) == &Rgba([0, 0, 0, 0]), "Black stays");
+        ensure!(tinted.get_pixel(2, 0) == &Rgba([255, 255, 255, 255]));
+        let [r, g, b, _] = tinted.get_pixel(1, 0).0;
+        ensure!(b > 200 && r < 110, "Blue midtones: {:?}", (r, g, b));
+        let lightness = luma([r, g, b].map(|c| c as f32 / 255.0));
+        ensure!(
+            (lightness - 0.5).abs() < 0.01,
+            "Luma is kept: {}",
+            lightness
+        );
+
+        let half = Tint {
+            amount: 0.5,
+            ..Tint::new(blue)
+        }
+        .apply(ramp())?
+        .into_rgb8();
+        ensure!(half.get_pixel(128, 0)[2] < b && half.get_pixel(128, 0)[2] > 128);
+        Ok(())
+    }
+}
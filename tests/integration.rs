@@ -1,5 +1,10 @@
 use anyhow::{ensure, Result};
-use image::{DynamicImage, GenericImageView};
+use image::{DynamicImage, GenericImageView, Rgba};
+use mirage::{
+    Animation, Blur, ColorMode, Coloring, Crop, Fill, FractalParams, Gradient, GradientKind,
+    Grayscale, Invert, Operation, Pipeline,
+};
+use num_complex::Complex64;
 use std::fs;
 use std::path::Path;
 use std::process::{Command, Stdio};
@@ -151,6 +156,58 @@ fn test_grayscale_integration() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_grayscale_options_integration() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let original = image::open(TEST_IMAGE)?;
+
+    // Rec. 601 weights kept as RGB for tinting later
+    let rgb_file = temp_dir.path().join("gray_rgb.png");
+    let success = run_mirage_command(&[
+        "grayscale",
+        TEST_IMAGE,
+        &rgb_file.to_string_lossy(),
+        "--weights",
+        "rec601",
+        "--keep-rgb",
+    ])?;
+    ensure!(success, "Grayscale with --keep-rgb should succeed");
+    let result = image::open(&rgb_file)?;
+    ensure!(
+        result.color() == original.color(),
+        "The color type should be kept, got {:?}",
+        result.color()
+    );
+    verify_grayscale_property(&result)?;
+
+    // Half desaturated with a red-only channel mixer keeps some color
+    let partial_file = temp_dir.path().join("gray_partial.png");
+    let success = run_mirage_command(&[
+        "grayscale",
+        TEST_IMAGE,
+        &partial_file.to_string_lossy(),
+        "--weights",
+        "1,0,0",
+        "--amount",
+        "50",
+    ])?;
+    ensure!(success, "Partial grayscale should succeed");
+    let result = image::open(&partial_file)?;
+    ensure!(result.color() == original.color());
+    ensure!(
+        verify_grayscale_property(&result).is_err(),
+        "Some color should be left"
+    );
+    ensure!(
+        calculate_variance(&result) != calculate_variance(&original),
+        "The image should change"
+    );
+
+    Ok(())
+}
+
 #[test]
 fn test_invert_integration() -> Result<()> {
     // ---
@@ -190,6 +247,247 @@ fn test_invert_integration() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_invert_options_integration() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("diagram.png");
+    // White background, black line, a red box and a half transparent corner
+    let diagram = image::RgbaImage::from_fn(20, 10, |x, y| match (x, y) {
+        (_, 5) => image::Rgba([0, 0, 0, 255]),
+        (2..=6, 1..=3) => image::Rgba([220, 40, 40, 255]),
+        (0, 0) => image::Rgba([255, 255, 255, 100]),
+        _ => image::Rgba([255, 255, 255, 255]),
+    });
+    DynamicImage::ImageRgba8(diagram.clone()).save(&input_file)?;
+
+    let run = |name: &str, args: &[&str]| -> Result<image::RgbaImage> {
+        let output_file = temp_dir.path().join(name);
+        let input = input_file.to_string_lossy().to_string();
+        let output = output_file.to_string_lossy().to_string();
+        let mut command = vec!["invert", &input, &output];
+        command.extend(args);
+        ensure!(
+            run_mirage_command(&command)?,
+            "invert {:?} should succeed",
+            args
+        );
+        Ok(image::open(&output_file)?.into_rgba8())
+    };
+
+    let red_blue = run("rb.png", &["--channels", "r,b"])?;
+    ensure!(red_blue.get_pixel(3, 2).0 == [35, 40, 215, 255]);
+    let alpha = run("alpha.png", &["--alpha"])?;
+    ensure!(
+        alpha.get_pixel(0, 0).0 == [0, 0, 0, 155],
+        "Alpha is inverted too"
+    );
+
+    let dark = run("dark.png", &["--lightness"])?;
+    ensure!(
+        dark.get_pixel(10, 0).0 == [0, 0, 0, 255],
+        "Background turns black"
+    );
+    ensure!(
+        dark.get_pixel(10, 5).0 == [255, 255, 255, 255],
+        "Lines turn white"
+    );
+    ensure!(dark.get_pixel(0, 0)[3] == 100, "Alpha is kept");
+    let red = dark.get_pixel(3, 2);
+    ensure!(
+        red[0] > red[1] && red[0] > red[2],
+        "Red stays red, got {:?}",
+        red
+    );
+
+    ensure!(
+        !run_mirage_command_suppress_output(&[
+            "invert",
+            &input_file.to_string_lossy(),
+            &temp_dir.path().join("x.png").to_string_lossy(),
+            "--lightness",
+            "--alpha",
+        ])?,
+        "Lightness inversion doesn't combine with channels"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_toning_integration() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("ramp.png");
+    // A black to white ramp with a saturated red pixel in the corner
+    let ramp = image::GrayImage::from_fn(256, 4, |x, _| image::Luma([x as u8]));
+    let mut ramp = DynamicImage::ImageLuma8(ramp).into_rgb8();
+    ramp.put_pixel(0, 3, image::Rgb([255, 0, 0]));
+    DynamicImage::ImageRgb8(ramp).save(&input_file)?;
+
+    let run = |command: &str, args: &[&str]| -> Result<image::RgbImage> {
+        let output_file = temp_dir.path().join(format!("{command}.png"));
+        let input = input_file.to_string_lossy().to_string();
+        let output = output_file.to_string_lossy().to_string();
+        let mut arguments = vec![command, &input, &output];
+        arguments.extend(args);
+        ensure!(
+            run_mirage_command(&arguments)?,
+            "{} {:?} should succeed",
+            command,
+            args
+        );
+        Ok(image::open(&output_file)?.into_rgb8())
+    };
+
+    let sepia = run("sepia", &[])?;
+    let [r, g, b] = sepia.get_pixel(128, 0).0;
+    ensure!(r > g && g > b, "Sepia is brown, got {:?}", (r, g, b));
+    ensure!(sepia.get_pixel(255, 0).0 == [255, 255, 255], "White stays");
+
+    let duotone = run("duotone", &["--shadows", "navy", "--highlights", "#ffcc00"])?;
+    ensure!(duotone.get_pixel(0, 0).0 == [0, 0, 128]);
+    ensure!(duotone.get_pixel(255, 0).0 == [255, 204, 0]);
+
+    let tint = run("tint", &["hsl(210, 80%, 50%)", "--amount", "50"])?;
+    let [r, _, b] = tint.get_pixel(128, 0).0;
+    ensure!(
+        b > r,
+        "Midtones turn blue, got {:?}",
+        tint.get_pixel(128, 0)
+    );
+    ensure!(tint.get_pixel(0, 0).0 == [0, 0, 0], "Black stays");
+
+    let colorize = run(
+        "colorize",
+        &[
+            "--stop",
+            "black",
+            "--stop",
+            "rgb(255, 0, 0)@50%",
+            "--stop",
+            "yellow",
+        ],
+    )?;
+    ensure!(colorize.get_pixel(0, 0).0 == [0, 0, 0]);
+    ensure!(colorize.get_pixel(255, 0).0 == [255, 255, 0]);
+    ensure!(
+        colorize.get_pixel(0, 3).0 == [108, 0, 0],
+        "Red is mapped by its luma, got {:?}",
+        colorize.get_pixel(0, 3)
+    );
+    Ok(())
+}
+
+#[test]
+fn test_threshold_integration() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("scan.png");
+    // A page darkening to the right with dark strokes, saved as RGB
+    let text = |x: u32, y: u32| x % 10 == 3 && y % 8 < 5;
+    let scan = image::RgbImage::from_fn(120, 40, |x, y| {
+        let paper = 230 - 140 * x / 119;
+        let value = if text(x, y) { paper - 60 } else { paper } as u8;
+        image::Rgb([value, value, value])
+    });
+    DynamicImage::ImageRgb8(scan).save(&input_file)?;
+
+    let run = |command: &str, args: &[&str]| -> Result<DynamicImage> {
+        let output_file = temp_dir.path().join(format!("{command}.png"));
+        let input = input_file.to_string_lossy().to_string();
+        let output = output_file.to_string_lossy().to_string();
+        let mut arguments = vec![command, &input, &output];
+        arguments.extend(args);
+        ensure!(
+            run_mirage_command(&arguments)?,
+            "{} {:?} should succeed",
+            command,
+            args
+        );
+        Ok(image::open(&output_file)?)
+    };
+
+    for args in [&["50"][..], &["--otsu"]] {
+        let binary = run("threshold", args)?;
+        ensure!(binary.color() == image::ColorType::L8, "Black and white");
+        let binary = binary.into_luma8();
+        ensure!(binary.pixels().all(|p| p[0] == 0 || p[0] == 255));
+        ensure!(binary.get_pixel(0, 7)[0] == 255 && binary.get_pixel(119, 7)[0] == 0);
+    }
+
+    let adaptive = run("adaptive-threshold", &["--window", "15"])?.into_luma8();
+    ensure!(
+        adaptive
+            .enumerate_pixels()
+            .all(|(x, y, p)| (p[0] == 0) == text(x, y)),
+        "Only the strokes are black"
+    );
+    let niblack = run(
+        "adaptive-threshold",
+        &["--method", "niblack", "--k", "-0.3"],
+    )?;
+    ensure!(niblack.into_luma8().get_pixel(113, 0)[0] == 0);
+
+    let poster = run("posterize", &["2"])?.into_rgb8();
+    ensure!(poster
+        .pixels()
+        .all(|p| p.0.iter().all(|&c| c == 0 || c == 255)));
+    Ok(())
+}
+
+#[test]
+fn test_dither_integration() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let run = |name: &str, args: &[&str]| -> Result<DynamicImage> {
+        let output_file = temp_dir.path().join(name);
+        let output = output_file.to_string_lossy().to_string();
+        let mut arguments = vec!["dither", TEST_IMAGE, &output];
+        arguments.extend(args);
+        ensure!(
+            run_mirage_command(&arguments)?,
+            "dither {:?} should succeed",
+            args
+        );
+        Ok(image::open(&output_file)?)
+    };
+    let original = image::open(TEST_IMAGE)?.to_luma8();
+    let mean = |img: &image::GrayImage| {
+        img.pixels().map(|p| p[0] as f64).sum::<f64>() / img.pixels().len() as f64
+    };
+
+    for args in [
+        &[][..],
+        &["--method", "atkinson", "--serpentine"],
+        &["--method", "jjn"],
+        &["--method", "bayer8"],
+    ] {
+        let binary = run("binary.png", args)?;
+        ensure!(binary.dimensions() == original.dimensions());
+        let binary = binary.to_luma8();
+        ensure!(
+            binary.pixels().all(|p| p[0] == 0 || p[0] == 255),
+            "{:?} is black and white",
+            args
+        );
+        let difference = (mean(&binary) - mean(&original)).abs();
+        ensure!(difference < 10.0, "{:?} is off by {}", args, difference);
+    }
+
+    let levels = run("levels.png", &["--bits", "2", "--method", "bayer4"])?.to_luma8();
+    ensure!(levels.pixels().all(|p| [0, 85, 170, 255].contains(&p[0])));
+
+    let palette = run("palette.png", &["--palette", "black", "white", "#ff0000"])?.to_rgba8();
+    ensure!(palette
+        .pixels()
+        .all(|p| { [[0, 0, 0], [255, 255, 255], [255, 0, 0]].contains(&[p[0], p[1], p[2]]) }));
+    Ok(())
+}
+
 #[test]
 fn test_blur_integration() -> Result<()> {
     // ---
@@ -283,6 +581,24 @@ fn test_rotate_integration() -> Result<()> {
         original.dimensions() == rotated_360.dimensions(),
         "After 360 degrees of rotation, dimensions should match original"
     );
+    ensure!(
+        original.to_rgba8() == rotated_360.to_rgba8(),
+        "Quarter turns should be lossless and keep every color"
+    );
+
+    // A single 270 degree turn matches three quarter turns
+    let rotate270_direct = temp_dir.path().join("test_rotate270_direct.png");
+    let success = run_mirage_command(&[
+        "rotate",
+        TEST_IMAGE,
+        &rotate270_direct.to_string_lossy(),
+        "270",
+    ])?;
+    ensure!(success, "270 degree rotation should succeed");
+    ensure!(
+        image::open(&rotate270_direct)? == image::open(&rotate270_file)?,
+        "270 degrees should be three quarter turns"
+    );
 
     // TempDir automatically cleans up when dropped
     Ok(())
@@ -394,67 +710,1572 @@ fn test_fractal_smoke() -> Result<()> {
 }
 
 #[test]
-fn test_generate_placeholder_smoke() -> Result<()> {
+fn test_fractal_mandelbrot_wide() -> Result<()> {
     // ---
 
     let temp_dir = TempDir::new()?;
-    let output_file = temp_dir.path().join("test_generate.png");
+    let output_file = temp_dir.path().join("test_mandelbrot.png");
+
+    let success = run_mirage_command(&[
+        "fractal",
+        &output_file.to_string_lossy(),
+        "240",
+        "120",
+        "--type",
+        "mandelbrot",
+        "--center",
+        "-0.75,0",
+        "--max-iterations",
+        "100",
+    ])?;
+    ensure!(success, "Mandelbrot fractal command should succeed");
 
-    // Test generate command (currently just prints message)
-    let success = run_mirage_command(&["generate", &output_file.to_string_lossy(), "255"])?;
+    let img = image::open(&output_file)?.to_rgb8();
     ensure!(
-        success,
-        "Generate command should succeed (even if not implemented)"
+        img.dimensions() == (240, 120),
+        "Fractal should be 240x120, got {:?}",
+        img.dimensions()
     );
 
-    // Since generate is not implemented, file should NOT exist
+    // The center of the view is inside the set, so it reaches max iterations
     ensure!(
-        !output_file.exists(),
-        "Generate should not create file until implemented"
+        img.get_pixel(120, 60).0[1] == 255,
+        "Center of the Mandelbrot view should be inside the set"
     );
 
+    // The set is symmetric about the real axis; with square pixels the
+    // escape counts mirror exactly across the middle row
+    for x in (0..240).step_by(7) {
+        for y in 0..60 {
+            ensure!(
+                img.get_pixel(x, y).0[1] == img.get_pixel(x, 119 - y).0[1],
+                "Mandelbrot should be symmetric about the real axis at ({}, {})",
+                x,
+                y
+            );
+        }
+    }
+
     // TempDir automatically cleans up when dropped
     Ok(())
 }
 
-// ============================================================================
-// ERROR HANDLING TESTS
-// ============================================================================
+#[test]
+fn test_fractal_coloring_modes() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let map_file = temp_dir.path().join("bw.map");
+    let map: String = (0..=255).map(|i| format!("{i} {i} {i}\n")).collect();
+    fs::write(&map_file, map)?;
+
+    for (mode, palette) in [
+        ("smooth", "fire"),
+        ("histogram", map_file.to_string_lossy().as_ref()),
+        ("orbit-trap", "ocean"),
+    ] {
+        let output_file = temp_dir.path().join(format!("test_{mode}.png"));
+        let success = run_mirage_command(&[
+            "fractal",
+            &output_file.to_string_lossy(),
+            "160",
+            "120",
+            "--type",
+            "mandelbrot",
+            "--coloring",
+            mode,
+            "--palette",
+            palette,
+            "--interior",
+            "#102030",
+        ])?;
+        ensure!(success, "Fractal with {} coloring should succeed", mode);
+
+        let img = image::open(&output_file)?.to_rgb8();
+        let mut colors: Vec<[u8; 3]> = img.pixels().map(|p| p.0).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        ensure!(
+            colors.len() > 50,
+            "{} coloring should produce a range of colors, got {}",
+            mode,
+            colors.len()
+        );
+
+        // The middle of the default Mandelbrot view is inside the set
+        let center = img.get_pixel(80, 60).0;
+        ensure!(
+            (mode == "orbit-trap") != (center == [0x10, 0x20, 0x30]),
+            "{} coloring gave unexpected interior color {:?}",
+            mode,
+            center
+        );
+    }
+
+    let success = run_mirage_command_suppress_output(&[
+        "fractal",
+        &temp_dir.path().join("bad.png").to_string_lossy(),
+        "10",
+        "10",
+        "--palette",
+        "no-such-palette",
+    ])?;
+    ensure!(!success, "Unknown palette should fail");
+
+    // TempDir automatically cleans up when dropped
+    Ok(())
+}
 
 #[test]
-fn test_invalid_input_file() -> Result<()> {
+fn test_generate_solid_smoke() -> Result<()> {
     // ---
 
-    let success =
-        run_mirage_command_suppress_output(&["blur", "nonexistent.png", "output.png", "50"])?;
-    ensure!(!success, "Command with nonexistent input should fail");
+    let temp_dir = TempDir::new()?;
+    let output_file = temp_dir.path().join("test_generate.png");
+
+    let success = run_mirage_command(&[
+        "generate",
+        &output_file.to_string_lossy(),
+        "--width",
+        "40",
+        "--height",
+        "30",
+        "--color",
+        "rgb(255, 128, 0)",
+    ])?;
+    ensure!(success, "Generate command should succeed");
+
+    let img = image::open(&output_file)?.to_rgba8();
+    ensure!(
+        img.dimensions() == (40, 30),
+        "Generated image should be 40x30, got {:?}",
+        img.dimensions()
+    );
+    ensure!(
+        img.pixels().all(|p| *p == Rgba([255, 128, 0, 255])),
+        "Every generated pixel should be orange"
+    );
 
+    // TempDir automatically cleans up when dropped
     Ok(())
 }
 
 #[test]
-fn test_invalid_rotation_degrees() -> Result<()> {
+fn test_generate_gradient_smoke() -> Result<()> {
     // ---
 
-    let success = run_mirage_command_suppress_output(&["rotate", TEST_IMAGE, "output.png", "45"])?;
-    ensure!(!success, "Invalid rotation degrees should fail");
+    let temp_dir = TempDir::new()?;
+    let output_file = temp_dir.path().join("test_gradient.png");
 
-    let success = run_mirage_command_suppress_output(&["rotate", TEST_IMAGE, "output.png", "360"])?;
-    ensure!(!success, "Invalid rotation degrees should fail");
+    // Left to right from black to white
+    let success = run_mirage_command(&[
+        "generate",
+        &output_file.to_string_lossy(),
+        "--width",
+        "100",
+        "--height",
+        "10",
+        "--gradient",
+        "linear",
+        "--angle",
+        "90",
+        "--stop",
+        "black",
+        "--stop",
+        "white",
+    ])?;
+    ensure!(success, "Gradient generate command should succeed");
 
+    let img = image::open(&output_file)?.to_rgba8();
+    let left = img.get_pixel(0, 5).0[0];
+    let middle = img.get_pixel(50, 5).0[0];
+    let right = img.get_pixel(99, 5).0[0];
+    ensure!(
+        left < 5 && right > 250 && (120..=135).contains(&middle),
+        "Gradient should run dark to light, got {} {} {}",
+        left,
+        middle,
+        right
+    );
+
+    // TempDir automatically cleans up when dropped
     Ok(())
 }
 
 #[test]
-fn test_invalid_blur_percentage() -> Result<()> {
+fn test_animate_outputs_smoke() -> Result<()> {
     // ---
 
-    let success = run_mirage_command_suppress_output(&["blur", TEST_IMAGE, "output.png", "150"])?;
-    ensure!(!success, "Blur percentage over 100 should fail");
+    use image::codecs::gif::GifDecoder;
+    use image::codecs::png::PngDecoder;
+    use image::AnimationDecoder;
+    use std::fs::File;
+
+    let temp_dir = TempDir::new()?;
+    let zoom_args = [
+        "32",
+        "24",
+        "--type",
+        "mandelbrot",
+        "--to-center=-0.7436,0.1318",
+        "--to-zoom",
+        "100",
+        "--frames",
+        "4",
+    ];
+
+    let gif_file = temp_dir.path().join("zoom.gif");
+    let gif_name = gif_file.to_string_lossy();
+    let success = run_mirage_command(&[&["animate", &gif_name][..], &zoom_args].concat())?;
+    ensure!(success, "Animate to GIF should succeed");
+    let frames = GifDecoder::new(File::open(&gif_file)?)?
+        .into_frames()
+        .collect_frames()?;
+    ensure!(
+        frames.len() == 4,
+        "GIF should have 4 frames, got {}",
+        frames.len()
+    );
+
+    let png_file = temp_dir.path().join("zoom.png");
+    let png_name = png_file.to_string_lossy();
+    let success = run_mirage_command(&[&["animate", &png_name][..], &zoom_args].concat())?;
+    ensure!(success, "Animate to APNG should succeed");
+    let decoder = PngDecoder::new(File::open(&png_file)?)?;
+    ensure!(decoder.is_apng(), "PNG output should be animated");
+    let frames = decoder.apng().into_frames().collect_frames()?;
+    ensure!(
+        frames.len() == 4,
+        "APNG should have 4 frames, got {}",
+        frames.len()
+    );
+    ensure!(
+        frames[0].buffer() != frames[3].buffer(),
+        "First and last frames should differ"
+    );
 
+    let pattern = temp_dir.path().join("frame_%05d.png");
     let success =
-        run_mirage_command_suppress_output(&["blur", TEST_IMAGE, "output.png", "--", "-10"])?;
-    ensure!(!success, "Negative blur percentage should fail");
+        run_mirage_command(&[&["animate", &pattern.to_string_lossy()][..], &zoom_args].concat())?;
+    ensure!(success, "Animate to numbered frames should succeed");
+    for index in 0..4 {
+        let frame = temp_dir.path().join(format!("frame_{index:05}.png"));
+        ensure!(frame.exists(), "Missing frame {}", frame.display());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rerender_from_stored_parameters() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let original = temp_dir.path().join("original.png");
+    let fractal_args = [
+        "--type",
+        "mandelbrot",
+        "--center=-0.74,0.13",
+        "--zoom",
+        "4",
+        "--coloring",
+        "smooth",
+        "--palette",
+        "fire",
+    ];
+    let success = run_mirage_command_suppress_output(
+        &[
+            &["fractal", &original.to_string_lossy(), "60", "40"][..],
+            &fractal_args,
+        ]
+        .concat(),
+    )?;
+    ensure!(success, "Fractal command should succeed");
+
+    let rerendered = temp_dir.path().join("rerendered.png");
+    let success = run_mirage_command_suppress_output(&[
+        "rerender",
+        &original.to_string_lossy(),
+        &rerendered.to_string_lossy(),
+        "--width",
+        "120",
+    ])?;
+    ensure!(success, "Rerender should succeed");
+
+    let direct = temp_dir.path().join("direct.png");
+    let success = run_mirage_command_suppress_output(
+        &[
+            &["fractal", &direct.to_string_lossy(), "120", "80"][..],
+            &fractal_args,
+        ]
+        .concat(),
+    )?;
+    ensure!(success, "Fractal command should succeed");
+
+    let rerendered = image::open(&rerendered)?;
+    ensure!(
+        rerendered.dimensions() == (120, 80),
+        "Rerender should keep the aspect ratio, got {:?}",
+        rerendered.dimensions()
+    );
+    ensure!(
+        rerendered == image::open(&direct)?,
+        "Rerender should match rendering the same parameters directly"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rotate_arbitrary_angle_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("input.png");
+    write_test_image(&input_file, 64, 32)?;
+
+    let expanded_file = temp_dir.path().join("expanded.png");
+    let success = run_mirage_command(&[
+        "rotate",
+        &input_file.to_string_lossy(),
+        &expanded_file.to_string_lossy(),
+        "30",
+        "--background",
+        "transparent",
+    ])?;
+    ensure!(success, "30 degree rotation should succeed");
+    let expanded = image::open(&expanded_file)?.to_rgba8();
+    ensure!(
+        expanded.dimensions() == (72, 60),
+        "Canvas should grow to fit, got {:?}",
+        expanded.dimensions()
+    );
+    ensure!(
+        expanded.get_pixel(0, 0)[3] == 0,
+        "Corners should be transparent"
+    );
+
+    let cropped_file = temp_dir.path().join("cropped.png");
+    let success = run_mirage_command(&[
+        "rotate",
+        &input_file.to_string_lossy(),
+        &cropped_file.to_string_lossy(),
+        "-30",
+        "--canvas",
+        "crop",
+        "--interpolation",
+        "bilinear",
+    ])?;
+    ensure!(success, "Cropped rotation should succeed");
+    ensure!(
+        image::open(&cropped_file)?.dimensions() == (64, 32),
+        "Cropped rotation should keep the size"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_hue_keeps_alpha_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("input.png");
+    let output_file = temp_dir.path().join("hue.png");
+    let input = image::RgbaImage::from_fn(16, 16, |x, y| {
+        Rgba([255, (x * 16) as u8, (y * 16) as u8, (x * 16 + y) as u8])
+    });
+    input.save(&input_file)?;
+
+    let success = run_mirage_command(&[
+        "hue",
+        &input_file.to_string_lossy(),
+        &output_file.to_string_lossy(),
+        "-90",
+    ])?;
+    ensure!(success, "Hue command should succeed");
+
+    let output = image::open(&output_file)?.to_rgba8();
+    ensure!(
+        output
+            .pixels()
+            .zip(input.pixels())
+            .all(|(o, i)| o[3] == i[3]),
+        "Alpha should be unchanged"
+    );
+    ensure!(output != input, "Colors should change");
+
+    let restored_file = temp_dir.path().join("restored.png");
+    let success = run_mirage_command(&[
+        "modulate",
+        &output_file.to_string_lossy(),
+        &restored_file.to_string_lossy(),
+        "--hue",
+        "90",
+    ])?;
+    ensure!(success, "Modulate command should succeed");
+    let restored = image::open(&restored_file)?.to_rgba8();
+    ensure!(
+        restored.pixels().zip(input.pixels()).all(|(r, i)| r
+            .0
+            .iter()
+            .zip(i.0)
+            .all(|(a, b)| a.abs_diff(b) <= 1)),
+        "Rotating back should restore the colors"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_resize_thumbnails_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_dir = temp_dir.path().join("photos");
+    let output_dir = temp_dir.path().join("thumbs");
+    write_test_image(&input_dir.join("wide.png"), 120, 60)?;
+    write_test_image(&input_dir.join("small.png"), 20, 30)?;
+
+    let success = run_mirage_command(&[
+        "resize",
+        &input_dir.to_string_lossy(),
+        &output_dir.to_string_lossy(),
+        "40x40",
+        "--fit",
+        "cover",
+        "--shrink-only",
+    ])?;
+    ensure!(success, "Resize command should succeed");
+    ensure!(
+        image::image_dimensions(output_dir.join("wide.png"))? == (40, 40),
+        "Large images are cropped to fill the box"
+    );
+    ensure!(
+        image::image_dimensions(output_dir.join("small.png"))? == (20, 30),
+        "Small images are never enlarged"
+    );
+
+    let letterbox = temp_dir.path().join("letterbox.png");
+    let success = run_mirage_command(&[
+        "resize",
+        &input_dir.join("wide.png").to_string_lossy(),
+        &letterbox.to_string_lossy(),
+        "90x90",
+        "--fit",
+        "contain",
+        "--background",
+        "white",
+    ])?;
+    ensure!(success, "Contain should succeed");
+    let img = image::open(&letterbox)?.to_rgb8();
+    ensure!(img.dimensions() == (90, 90));
+    ensure!(
+        *img.get_pixel(45, 0) == image::Rgb([255, 255, 255]),
+        "Letterbox above the image"
+    );
+
+    let half = temp_dir.path().join("half.png");
+    let success = run_mirage_command(&[
+        "resize",
+        &input_dir.join("wide.png").to_string_lossy(),
+        &half.to_string_lossy(),
+        "50%",
+    ])?;
+    ensure!(success, "Percentage should succeed");
+    ensure!(image::image_dimensions(&half)? == (60, 30));
+
+    Ok(())
+}
+
+#[test]
+fn test_flip_and_transpose_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("input.png");
+    write_test_image(&input_file, 12, 8)?;
+    let input = image::open(&input_file)?;
+
+    for (command, expected) in [
+        ("flip", input.flipv()),
+        ("flop", input.fliph()),
+        ("transpose", input.rotate90().fliph()),
+        ("transverse", input.rotate270().fliph()),
+    ] {
+        let output_file = temp_dir.path().join(format!("{command}.png"));
+        let success = run_mirage_command(&[
+            command,
+            &input_file.to_string_lossy(),
+            &output_file.to_string_lossy(),
+        ])?;
+        ensure!(success, "{} command should succeed", command);
+        ensure!(
+            image::open(&output_file)? == expected,
+            "{} should move pixels losslessly",
+            command
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_auto_orient_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("phone.jpg");
+    write_oriented_jpeg(&input_file, 40, 24, 8)?;
+
+    let upright = temp_dir.path().join("upright.png");
+    let success = run_mirage_command(&[
+        "grayscale",
+        &input_file.to_string_lossy(),
+        &upright.to_string_lossy(),
+    ])?;
+    ensure!(success, "Grayscale command should succeed");
+    ensure!(
+        image::image_dimensions(&upright)? == (24, 40),
+        "EXIF orientation 8 should be applied by default"
+    );
+
+    let stored = temp_dir.path().join("stored.png");
+    let success = run_mirage_command(&[
+        "grayscale",
+        &input_file.to_string_lossy(),
+        &stored.to_string_lossy(),
+        "--no-auto-orient",
+    ])?;
+    ensure!(success, "Grayscale command should succeed");
+    ensure!(image::image_dimensions(&stored)? == (40, 24));
+
+    Ok(())
+}
+
+#[test]
+fn test_crop_geometry_and_aspect_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("input.png");
+    write_test_image(&input_file, 200, 100)?;
+    let input = image::open(&input_file)?;
+
+    let crop = |name: &str, region: &[&str]| -> Result<DynamicImage> {
+        let output_file = temp_dir.path().join(name);
+        let input = input_file.to_string_lossy();
+        let output = output_file.to_string_lossy();
+        let mut args = vec!["crop", &input, &output];
+        args.extend(region);
+        ensure!(
+            run_mirage_command(&args)?,
+            "Crop {:?} should succeed",
+            region
+        );
+        Ok(image::open(&output_file)?)
+    };
+
+    ensure!(
+        crop("geometry.png", &["80x60+10+20"])? == input.crop_imm(10, 20, 80, 60),
+        "Geometry is WxH+X+Y"
+    );
+    ensure!(
+        crop("percent.png", &["25%", "0", "50%", "100%"])? == input.crop_imm(50, 0, 100, 100),
+        "Percentages of the image size"
+    );
+    ensure!(
+        crop("square.png", &["--aspect", "1:1", "--gravity", "center"])?
+            == input.crop_imm(50, 0, 100, 100),
+        "Largest centered square"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_trim_and_smartcrop_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let scan_file = temp_dir.path().join("scan.png");
+    // A test image pasted on a slightly noisy white page
+    let mut scan =
+        image::RgbImage::from_fn(100, 80, |x, y| image::Rgb([255 - ((x + y) % 3) as u8; 3]));
+    write_test_image(&temp_dir.path().join("content.png"), 60, 30)?;
+    let content = image::open(temp_dir.path().join("content.png"))?.to_rgb8();
+    image::imageops::replace(&mut scan, &content, 15, 20);
+    scan.save(&scan_file)?;
+
+    let trimmed_file = temp_dir.path().join("trimmed.png");
+    let success = run_mirage_command(&[
+        "trim",
+        &scan_file.to_string_lossy(),
+        &trimmed_file.to_string_lossy(),
+        "--fuzz",
+        "2%",
+    ])?;
+    ensure!(success, "Trim command should succeed");
+    ensure!(
+        image::open(&trimmed_file)?.to_rgb8() == content,
+        "Only the pasted image should be left"
+    );
+
+    let square_file = temp_dir.path().join("square.png");
+    let success = run_mirage_command(&[
+        "smartcrop",
+        &scan_file.to_string_lossy(),
+        &square_file.to_string_lossy(),
+        "--aspect",
+        "1:1",
+    ])?;
+    ensure!(success, "Smartcrop command should succeed");
+    ensure!(image::image_dimensions(&square_file)? == (80, 80));
+
+    Ok(())
+}
+
+#[test]
+fn test_sharpen_keeps_alpha_and_depth_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("input.png");
+    let input = image::ImageBuffer::from_fn(24, 8, |x, _| match x < 12 {
+        true => Rgba([12000u16, 20000, 30000, 65535]),
+        false => Rgba([50000u16, 45000, 40000, 30000]),
+    });
+    DynamicImage::ImageRgba16(input.clone()).save(&input_file)?;
+
+    for (name, args) in [
+        ("sharpen.png", &["sharpen", "2", "--amount", "150"][..]),
+        ("unsharp.png", &["unsharp", "1.5", "120", "1"]),
+    ] {
+        let output_file = temp_dir.path().join(name);
+        let input_arg = input_file.to_string_lossy();
+        let output_arg = output_file.to_string_lossy();
+        let mut command = vec![args[0], &input_arg, &output_arg];
+        command.extend(&args[1..]);
+        ensure!(run_mirage_command(&command)?, "{} should succeed", args[0]);
+
+        let output = image::open(&output_file)?;
+        ensure!(output.color() == image::ColorType::Rgba16, "Depth is kept");
+        let output = output.into_rgba16();
+        ensure!(
+            output
+                .pixels()
+                .zip(input.pixels())
+                .all(|(o, i)| o[3] == i[3]),
+            "Alpha is kept"
+        );
+        ensure!(
+            output.get_pixel(11, 0)[0] < 12000,
+            "The dark side of the edge gets darker"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_tone_adjustments_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let input_file = temp_dir.path().join("ramp.png");
+    // Every 16-bit value from 0 to 65535 in steps of 257 plus 1
+    let ramp = image::ImageBuffer::from_fn(256, 1, |x, _| image::Luma([(x * 257) as u16]));
+    DynamicImage::ImageLuma16(ramp.clone()).save(&input_file)?;
+
+    let run = |name: &str, args: &[&str]| -> Result<DynamicImage> {
+        let output_file = temp_dir.path().join(name);
+        let input_arg = input_file.to_string_lossy();
+        let output_arg = output_file.to_string_lossy();
+        let mut command = vec![args[0], &input_arg, &output_arg];
+        command.extend(&args[1..]);
+        ensure!(run_mirage_command(&command)?, "{} should succeed", args[0]);
+        Ok(image::open(&output_file)?)
+    };
+
+    let levels = run("levels.png", &["levels", "0", "50"])?;
+    ensure!(levels.color() == image::ColorType::L16, "16-bit is kept");
+    let levels = levels.into_luma16();
+    ensure!(levels.get_pixel(64, 0)[0] == 32896, "Values double exactly");
+    ensure!(
+        levels.get_pixel(200, 0)[0] == 65535,
+        "Above the white point clips"
+    );
+
+    let identity = run("curves.png", &["curves", "0,0", "100,100"])?;
+    ensure!(
+        identity.into_luma16() == ramp,
+        "A straight curve changes nothing"
+    );
+
+    let flat = run("contrast.png", &["contrast", "-100"])?.into_luma16();
+    ensure!(
+        flat.pixels().all(|p| p[0] == 32768),
+        "No contrast is flat gray"
+    );
+
+    let gamma = run("gamma.png", &["gamma", "2"])?.into_luma16();
+    ensure!(gamma.get_pixel(64, 0)[0] > ramp.get_pixel(128, 0)[0] - 300);
+
+    Ok(())
+}
+
+#[test]
+fn test_brighten_modes_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let gray = temp_dir.path().join("gray.png");
+    DynamicImage::ImageRgb16(image::ImageBuffer::from_pixel(
+        4,
+        4,
+        image::Rgb([32768u16; 3]),
+    ))
+    .save(&gray)?;
+
+    let run = |name: &str, args: &[&str]| -> Result<image::Rgb<u16>> {
+        let output_file = temp_dir.path().join(name);
+        let mut command = vec![
+            "brighten".to_string(),
+            gray.to_string_lossy().to_string(),
+            output_file.to_string_lossy().to_string(),
+        ];
+        command.extend(args.iter().map(|arg| arg.to_string()));
+        let command: Vec<&str> = command.iter().map(String::as_str).collect();
+        ensure!(
+            run_mirage_command(&command)?,
+            "brighten {:?} should succeed",
+            args
+        );
+        Ok(*image::open(&output_file)?.into_rgb16().get_pixel(0, 0))
+    };
+
+    // A percentage is the same step at any depth, unlike a raw amount
+    ensure!(run("percent.png", &["--percent", "10"])?.0 == [39322; 3]);
+    ensure!(run("raw.png", &["10"])?.0 == [32778; 3]);
+    ensure!(run("channels.png", &["--red", "10", "--blue", "-5"])?.0 == [39322, 32768, 29491]);
+    let exposed = run("exposure.png", &["--exposure", "1"])?;
+    ensure!(
+        exposed[0] > 44000 && exposed[0] < 46000,
+        "One stop up in linear light"
+    );
+
+    ensure!(
+        !run_mirage_command_suppress_output(&[
+            "brighten",
+            &gray.to_string_lossy(),
+            &temp_dir.path().join("none.png").to_string_lossy(),
+        ])?,
+        "A brighten mode is required"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_blur_kernels_smoke() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let original = image::open(TEST_IMAGE)?;
+    let orig_variance = calculate_variance(&original);
+
+    let runs: [&[&str]; 6] = [
+        &["blur", "3", "--unit", "radius", "--kernel", "box"],
+        &["blur", "1", "--unit", "percent"],
+        &["blur", "2", "--kernel", "motion", "--angle", "-45"],
+        &["blur", "2", "--kernel", "bokeh"],
+        &["spin-blur", "5", "--center", "0.25,0.5"],
+        &["zoom-blur", "10"],
+    ];
+    for (index, args) in runs.iter().enumerate() {
+        let output_file = temp_dir.path().join(format!("blur_{}.png", index));
+        let input = TEST_IMAGE.to_string();
+        let output = output_file.to_string_lossy().to_string();
+        let mut command = vec![args[0], &input, &output];
+        command.extend(&args[1..]);
+        ensure!(run_mirage_command(&command)?, "{:?} should succeed", args);
+
+        let blurred = image::open(&output_file)?;
+        ensure!(blurred.dimensions() == original.dimensions());
+        ensure!(
+            calculate_variance(&blurred) < orig_variance,
+            "{:?} should smooth the image",
+            args
+        );
+    }
+    Ok(())
+}
+
+// ============================================================================
+// BATCH TESTS: Directory and glob inputs
+// ============================================================================
+
+// Write a small RGB test image at `path`, creating parent directories
+fn write_test_image(path: &Path, width: u32, height: u32) -> Result<()> {
+    // ---
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let img = image::RgbImage::from_fn(width, height, |x, y| {
+        image::Rgb([(x * 7) as u8, (y * 5) as u8, ((x + y) * 3) as u8])
+    });
+    img.save(path)?;
+    Ok(())
+}
+
+// Write a JPEG test image at `path` whose EXIF Orientation tag is `value`
+fn write_oriented_jpeg(path: &Path, width: u32, height: u32, value: u16) -> Result<()> {
+    // ---
+
+    let jpeg = path.with_extension("tmp.jpg");
+    write_test_image(&jpeg, width, height)?;
+    let mut bytes = fs::read(&jpeg)?;
+    fs::remove_file(&jpeg)?;
+
+    // APP1 segment after the SOI marker: a little-endian TIFF header and
+    // a single Orientation entry
+    let mut exif = b"Exif\0\0II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+    exif.extend(value.to_le_bytes());
+    exif.extend([0; 6]);
+    let mut segment = vec![0xff, 0xe1];
+    segment.extend((exif.len() as u16 + 2).to_be_bytes());
+    segment.extend(exif);
+    bytes.splice(2..2, segment);
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+#[test]
+fn test_batch_directory_recursive() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let in_dir = temp_dir.path().join("in");
+    let out_dir = temp_dir.path().join("out");
+
+    write_test_image(&in_dir.join("a.png"), 20, 10)?;
+    write_test_image(&in_dir.join("b.png"), 30, 15)?;
+    write_test_image(&in_dir.join("nested/c.png"), 12, 12)?;
+    fs::write(in_dir.join("broken.png"), b"not an image")?;
+    fs::write(in_dir.join("notes.txt"), b"ignored")?;
+
+    let success = run_mirage_command(&[
+        "invert",
+        &in_dir.to_string_lossy(),
+        &out_dir.to_string_lossy(),
+        "--recursive",
+        "--output-name",
+        "{stem}_{op}.{ext}",
+    ])?;
+    ensure!(
+        !success,
+        "Batch with an unreadable file should report failure"
+    );
+
+    // The failure must not stop the remaining files
+    for name in ["a_invert.png", "b_invert.png", "nested/c_invert.png"] {
+        verify_output_file(&out_dir.join(name), 0)?;
+    }
+    ensure!(
+        !out_dir.join("broken_invert.png").exists(),
+        "Unreadable input should not produce an output"
+    );
+    ensure!(
+        image::open(out_dir.join("b_invert.png"))?.dimensions() == (30, 15),
+        "Batch output should keep each image's dimensions"
+    );
+
+    // TempDir automatically cleans up when dropped
+    Ok(())
+}
+
+#[test]
+fn test_batch_glob() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let in_dir = temp_dir.path().join("in");
+    let out_dir = temp_dir.path().join("out");
+
+    write_test_image(&in_dir.join("one.png"), 20, 10)?;
+    write_test_image(&in_dir.join("two.png"), 20, 10)?;
+    write_test_image(&in_dir.join("skip.bmp"), 20, 10)?;
+    write_test_image(&in_dir.join("sub/three.png"), 20, 10)?;
+
+    let pattern = in_dir.join("*.png");
+    let success = run_mirage_command(&[
+        "grayscale",
+        &pattern.to_string_lossy(),
+        &out_dir.to_string_lossy(),
+    ])?;
+    ensure!(success, "Batch grayscale over a glob should succeed");
+
+    for name in ["one.png", "two.png"] {
+        let result = image::open(out_dir.join(name))?;
+        verify_grayscale_property(&result)?;
+    }
+    ensure!(
+        !out_dir.join("skip.bmp").exists() && !out_dir.join("sub").exists(),
+        "Only files matching the glob should be processed"
+    );
+
+    // TempDir automatically cleans up when dropped
+    Ok(())
+}
+
+#[test]
+fn test_batch_output_inside_input_tree() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let in_dir = temp_dir.path().join("in");
+    let out_dir = in_dir.join("out");
+
+    write_test_image(&in_dir.join("a.png"), 20, 10)?;
+    write_test_image(&in_dir.join("nested/b.png"), 12, 12)?;
+
+    // A second run must not pick up the results of the first
+    for _ in 0..2 {
+        let success = run_mirage_command(&[
+            "invert",
+            &in_dir.to_string_lossy(),
+            &out_dir.to_string_lossy(),
+            "--recursive",
+            "--output-name",
+            "{stem}_{op}.{ext}",
+        ])?;
+        ensure!(
+            success,
+            "Batch into a directory of the input tree should succeed"
+        );
+    }
+    for name in ["a_invert.png", "nested/b_invert.png"] {
+        verify_output_file(&out_dir.join(name), 0)?;
+    }
+    ensure!(
+        !out_dir.join("out").exists() && !out_dir.join("a_invert_invert.png").exists(),
+        "Outputs should not be processed as inputs"
+    );
+
+    // TempDir automatically cleans up when dropped
+    Ok(())
+}
+
+#[test]
+fn test_batch_refuses_to_overwrite_inputs() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let in_dir = temp_dir.path().join("in");
+    let out_dir = temp_dir.path().join("out");
+    let dir = in_dir.to_string_lossy();
+
+    write_test_image(&in_dir.join("one.png"), 20, 10)?;
+    let original = std::fs::read(in_dir.join("one.png"))?;
+    let success = run_mirage_command_suppress_output(&["invert", &dir, &dir])?;
+    ensure!(!success, "Writing over the inputs should need --force");
+    ensure!(
+        std::fs::read(in_dir.join("one.png"))? == original,
+        "The input should be left alone"
+    );
+
+    let success = run_mirage_command(&["invert", &dir, &dir, "--force"])?;
+    ensure!(success, "--force should allow replacing the inputs");
+    ensure!(std::fs::read(in_dir.join("one.png"))? != original);
+
+    // Both files would be written to out/one.png
+    write_test_image(&in_dir.join("one.jpg"), 20, 10)?;
+    let success = run_mirage_command_suppress_output(&[
+        "invert",
+        &dir,
+        &out_dir.to_string_lossy(),
+        "--output-name",
+        "{stem}.png",
+    ])?;
+    ensure!(!success, "Clashing output names should be reported");
+    ensure!(!out_dir.exists(), "Nothing should be written on a clash");
+
+    let pattern = in_dir.join("*.png");
+    let success = run_mirage_command_suppress_output(&[
+        "invert",
+        &pattern.to_string_lossy(),
+        &out_dir.to_string_lossy(),
+        "--recursive",
+    ])?;
+    ensure!(!success, "--recursive should be rejected for a glob");
+
+    // TempDir automatically cleans up when dropped
+    Ok(())
+}
+
+// ============================================================================
+// LIBRARY TESTS: Operations applied in memory through the mirage crate
+// ============================================================================
+
+#[test]
+fn test_library_grayscale() -> Result<()> {
+    // ---
+
+    let original = image::open(TEST_IMAGE)?;
+    let result = Grayscale.apply(original.clone())?;
+
+    ensure!(
+        original.dimensions() == result.dimensions(),
+        "Grayscale image should have same dimensions as original"
+    );
+    verify_grayscale_property(&result)?;
+    Ok(())
+}
+
+#[test]
+fn test_library_invert_roundtrip() -> Result<()> {
+    // ---
+
+    let original = image::open(TEST_IMAGE)?;
+    let inverted = Invert.apply(original.clone())?;
+    ensure!(
+        inverted.to_rgba8() != original.to_rgba8(),
+        "Inverted image should differ from original"
+    );
+
+    // Without an encode/decode step in between, double inversion is exact
+    let restored = Invert.apply(inverted)?;
+    ensure!(
+        restored.to_rgba8() == original.to_rgba8(),
+        "Double inversion should restore the original pixels"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_blur_reduces_variance() -> Result<()> {
+    // ---
+
+    let original = image::open(TEST_IMAGE)?;
+    let blurred = Blur { sigma: 5.0 }.apply(original.clone())?;
+
+    let orig_variance = calculate_variance(&original);
+    let blur_variance = calculate_variance(&blurred);
+    ensure!(
+        blur_variance < orig_variance,
+        "Blurred image should have lower variance than original (original: {:.2}, blurred: {:.2})",
+        orig_variance,
+        blur_variance
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_pipeline_matches_individual_operations() -> Result<()> {
+    // ---
+
+    let original = image::open(TEST_IMAGE)?;
+    let crop = Crop {
+        x: 10,
+        y: 10,
+        width: 120,
+        height: 90,
+    };
+
+    let piped = Pipeline::new()
+        .then(crop)
+        .then(Invert)
+        .then(Grayscale)
+        .apply(original.clone())?;
+
+    let expected = Grayscale.apply(Invert.apply(crop.apply(original)?)?)?;
+
+    ensure!(
+        piped.dimensions() == (120, 90),
+        "Pipeline output should have the cropped size, got {:?}",
+        piped.dimensions()
+    );
+    ensure!(
+        piped == expected,
+        "Pipeline should equal the operations applied one by one"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_fractal_is_deterministic() -> Result<()> {
+    // ---
+
+    let params = FractalParams::default();
+    let first = mirage::fractal(120, 80, &params)?;
+    let second = mirage::fractal(120, 80, &params)?;
+
+    ensure!(
+        first.dimensions() == (120, 80),
+        "Fractal should be 120x80, got {:?}",
+        first.dimensions()
+    );
+    ensure!(first == second, "Fractal rendering should be deterministic");
+    Ok(())
+}
+
+#[test]
+fn test_library_generate_solid_color() -> Result<()> {
+    // ---
+
+    let color = Rgba([10, 20, 30, 255]);
+    let img = mirage::generate(16, 8, &Fill::Solid(color))?;
+
+    ensure!(
+        img.dimensions() == (16, 8),
+        "Generated image should be 16x8, got {:?}",
+        img.dimensions()
+    );
+    ensure!(
+        img.pixels().all(|p| *p == color),
+        "Every generated pixel should have the requested color"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_parallel_fractal_matches_single_thread() -> Result<()> {
+    // ---
+
+    for coloring in [ColorMode::Classic, ColorMode::Smooth, ColorMode::Histogram] {
+        let params = FractalParams {
+            max_iterations: 300,
+            coloring: Coloring {
+                mode: coloring,
+                ..Coloring::default()
+            },
+            ..FractalParams::mandelbrot()
+        };
+
+        let single = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()?
+            .install(|| mirage::fractal(157, 93, &params))?;
+        let parallel = rayon::ThreadPoolBuilder::new()
+            .num_threads(4)
+            .build()?
+            .install(|| mirage::fractal(157, 93, &params))?;
+
+        ensure!(
+            single == parallel,
+            "{:?} render should not depend on the thread count",
+            coloring
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_library_fractal_progress_reports_every_row() -> Result<()> {
+    // ---
+
+    let rows = std::sync::Mutex::new(Vec::new());
+    mirage::fractal_with_progress(20, 15, &FractalParams::default(), |done, total| {
+        rows.lock().unwrap().push((done, total));
+    })?;
+
+    let mut rows = rows.into_inner().unwrap();
+    rows.sort_unstable();
+    let expected: Vec<(u32, u32)> = (1..=15).map(|done| (done, 15)).collect();
+    ensure!(rows == expected, "Progress should count every row once");
+    Ok(())
+}
+
+#[test]
+fn test_library_fractal_rejects_bad_sizes_before_rendering() -> Result<()> {
+    // ---
+
+    let rendered = std::sync::atomic::AtomicBool::new(false);
+    for (width, height) in [(0, 10), (10, 0), (100_000, 100_000), (40_000, 40_000)] {
+        let result =
+            mirage::fractal_with_progress(width, height, &FractalParams::default(), |_, _| {
+                rendered.store(true, std::sync::atomic::Ordering::Relaxed);
+            });
+        ensure!(result.is_err(), "{}x{} should be rejected", width, height);
+    }
+    ensure!(
+        !rendered.into_inner(),
+        "Nothing should be rendered for a bad size"
+    );
+    Ok(())
+}
+
+// Number of distinct escape counts in a classic-colored fractal
+fn distinct_escape_counts(img: &image::RgbImage) -> usize {
+    // ---
+
+    let mut counts: Vec<u8> = img.pixels().map(|p| p.0[1]).collect();
+    counts.sort_unstable();
+    counts.dedup();
+    counts.len()
+}
+
+#[test]
+fn test_library_deep_zoom_matches_plain_render() -> Result<()> {
+    // ---
+
+    // At a zoom f64 handles well both renderers must agree
+    let plain = FractalParams {
+        center: Complex64::new(-0.743, 0.131),
+        zoom: 500.0,
+        max_iterations: 255,
+        ..FractalParams::mandelbrot()
+    };
+    let deep = FractalParams {
+        deep_center: Some("-0.743,0.131".parse()?),
+        ..plain.clone()
+    };
+
+    let expected = mirage::fractal(120, 90, &plain)?;
+    let actual = mirage::fractal(120, 90, &deep)?;
+    let matching = expected
+        .pixels()
+        .zip(actual.pixels())
+        .filter(|(a, b)| a == b)
+        .count();
+    ensure!(
+        distinct_escape_counts(&expected) > 20,
+        "Test view should contain detail"
+    );
+    ensure!(
+        matching * 100 >= 99 * (120 * 90),
+        "Deep zoom should match the plain render, only {} of {} pixels agree",
+        matching,
+        120 * 90
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_deep_zoom_resolves_detail() -> Result<()> {
+    // ---
+
+    // c = i is on the boundary of the set, with structure at every scale.
+    // At zoom 1e40 f64 sees a single point, perturbation still resolves it.
+    let center = "0.0000000000000000000000000000000000000000001234,1.0000000000000000000000000000000000000000004321";
+    let deep = FractalParams {
+        center: Complex64::new(0.0, 1.0),
+        zoom: 1e40,
+        max_iterations: 1000,
+        coloring: Coloring {
+            mode: ColorMode::Smooth,
+            ..Coloring::default()
+        },
+        deep_center: Some(center.parse()?),
+        ..FractalParams::mandelbrot()
+    };
+    let plain = FractalParams {
+        deep_center: None,
+        ..deep.clone()
+    };
+
+    let distinct_colors = |img: image::RgbImage| {
+        let mut colors: Vec<[u8; 3]> = img.pixels().map(|p| p.0).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        colors.len()
+    };
+    let deep_colors = distinct_colors(mirage::fractal(80, 60, &deep)?);
+    let plain_colors = distinct_colors(mirage::fractal(80, 60, &plain)?);
+    ensure!(
+        plain_colors == 1,
+        "f64 render should collapse to one color at this zoom, got {}",
+        plain_colors
+    );
+    ensure!(
+        deep_colors > 100,
+        "Deep zoom should resolve detail, got {} distinct colors",
+        deep_colors
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_radial_gradient() -> Result<()> {
+    // ---
+
+    let stops = vec!["white".parse()?, "#000000".parse()?];
+    let gradient = Gradient::new(GradientKind::Radial, stops);
+    let img = mirage::generate(101, 101, &gradient.into())?;
+
+    let center = img.get_pixel(50, 50).0;
+    let corner = img.get_pixel(0, 0).0;
+    ensure!(
+        center[0] > 250 && corner[0] < 5,
+        "Radial gradient should be white in the center and black in the corners, got {:?} {:?}",
+        center,
+        corner
+    );
+    ensure!(
+        img.get_pixel(50, 10) == img.get_pixel(10, 50),
+        "Radial gradient should be symmetric"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_library_animation_palette_cycle() -> Result<()> {
+    // ---
+
+    let base = FractalParams {
+        coloring: Coloring {
+            mode: ColorMode::Smooth,
+            ..Coloring::default()
+        },
+        ..FractalParams::mandelbrot()
+    };
+    let mut animation = Animation::new(&base, 4);
+    animation.palette_cycles = 1.0;
+
+    let frames = (0..4)
+        .map(|index| mirage::fractal(24, 16, &animation.params_at(&base, index)))
+        .collect::<Result<Vec<_>>>()?;
+    ensure!(
+        frames[0] == mirage::fractal(24, 16, &base)?,
+        "First frame should be the still image"
+    );
+    ensure!(
+        frames.windows(2).all(|pair| pair[0] != pair[1]),
+        "Each frame should shift the palette"
+    );
+
+    Ok(())
+}
+
+// ============================================================================
+// ERROR HANDLING TESTS
+// ============================================================================
+
+#[test]
+fn test_invalid_input_file() -> Result<()> {
+    // ---
+
+    let success =
+        run_mirage_command_suppress_output(&["blur", "nonexistent.png", "output.png", "50"])?;
+    ensure!(!success, "Command with nonexistent input should fail");
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_rotation_degrees() -> Result<()> {
+    // ---
+
+    let success = run_mirage_command_suppress_output(&["rotate", TEST_IMAGE, "output.png", "abc"])?;
+    ensure!(!success, "Invalid rotation degrees should fail");
+
+    let success = run_mirage_command_suppress_output(&["rotate", TEST_IMAGE, "output.png", "inf"])?;
+    ensure!(!success, "Invalid rotation degrees should fail");
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_pipeline_step() -> Result<()> {
+    // ---
+
+    let success = run_mirage_command_suppress_output(&[
+        "pipeline",
+        TEST_IMAGE,
+        "output.png",
+        "--",
+        "blur",
+        "5",
+        ":",
+        "fractal",
+    ])?;
+    ensure!(!success, "Non image operation in a pipeline should fail");
+
+    Ok(())
+}
+
+#[test]
+fn test_batch_options_require_batch_input() -> Result<()> {
+    // ---
+
+    let success =
+        run_mirage_command_suppress_output(&["invert", TEST_IMAGE, "output.png", "--recursive"])?;
+    ensure!(!success, "--recursive with a single input file should fail");
+
+    Ok(())
+}
+
+#[test]
+fn test_deep_zoom_requires_mandelbrot() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let output_file = temp_dir.path().join("deep_julia.png");
+    let success = run_mirage_command_suppress_output(&[
+        "fractal",
+        &output_file.to_string_lossy(),
+        "10",
+        "10",
+        "--type",
+        "julia",
+        "--deep",
+    ])?;
+    ensure!(!success, "Deep zoom of a Julia set should fail");
+
+    Ok(())
+}
+
+#[test]
+fn test_animate_requires_something_to_animate() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let output_file = temp_dir.path().join("still.gif");
+    let success = run_mirage_command_suppress_output(&[
+        "animate",
+        &output_file.to_string_lossy(),
+        "10",
+        "10",
+    ])?;
+    ensure!(!success, "An animation without any change should fail");
+    ensure!(!output_file.exists(), "No file should be written");
+
+    Ok(())
+}
+
+#[test]
+fn test_rerender_requires_stored_parameters() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let output_file = temp_dir.path().join("rerender.png");
+    let success = run_mirage_command_suppress_output(&[
+        "rerender",
+        TEST_IMAGE,
+        &output_file.to_string_lossy(),
+    ])?;
+    ensure!(!success, "An image without parameters can't be rerendered");
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_resize_size() -> Result<()> {
+    // ---
+
+    for size in ["0x10", "abc", "-50%", "1000000%"] {
+        let success =
+            run_mirage_command_suppress_output(&["resize", TEST_IMAGE, "output.png", size])?;
+        ensure!(!success, "Size {} should be rejected", size);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_crop_outside_image_fails() -> Result<()> {
+    // ---
+
+    let temp_dir = TempDir::new()?;
+    let output_file = temp_dir.path().join("crop.png");
+    for region in [
+        &["900", "0", "100", "100"][..],
+        &["10x10+0+638"],
+        &["0x0"],
+        &["1", "2"],
+    ] {
+        let mut args = vec!["crop", TEST_IMAGE, output_file.to_str().unwrap_or_default()];
+        args.extend(region);
+        let success = run_mirage_command_suppress_output(&args)?;
+        ensure!(!success, "Crop {:?} should be rejected", region);
+    }
+    ensure!(!output_file.exists(), "Nothing should be written");
+
+    Ok(())
+}
+
+#[test]
+fn test_invalid_blur_percentage() -> Result<()> {
+    // ---
+
+    let success = run_mirage_command_suppress_output(&[
+        "blur",
+        TEST_IMAGE,
+        "output.png",
+        "150",
+        "--unit",
+        "percent",
+    ])?;
+    ensure!(!success, "Blur percentage over 100 should fail");
+
+    let success =
+        run_mirage_command_suppress_output(&["blur", TEST_IMAGE, "output.png", "--", "-10"])?;
+    ensure!(!success, "Negative blur percentage should fail");
+
+    let success =
+        run_mirage_command_suppress_output(&["zoom-blur", TEST_IMAGE, "output.png", "120"])?;
+    ensure!(!success, "Zooming past the center should fail");
+
+    let success = run_mirage_command_suppress_output(&[
+        "grayscale",
+        TEST_IMAGE,
+        "output.png",
+        "--weights",
+        "0.3,0.6",
+    ])?;
+    ensure!(!success, "Two channel mixer weights should fail");
+
+    let success = run_mirage_command_suppress_output(&[
+        "sepia",
+        TEST_IMAGE,
+        "output.png",
+        "--amount",
+        "150",
+    ])?;
+    ensure!(!success, "Sepia over 100% should fail");
+
+    let success = run_mirage_command_suppress_output(&[
+        "colorize",
+        TEST_IMAGE,
+        "output.png",
+        "--stop",
+        "red",
+    ])?;
+    ensure!(!success, "Colorize with one stop should fail");
+
+    let success =
+        run_mirage_command_suppress_output(&["tint", TEST_IMAGE, "output.png", "bluish"])?;
+    ensure!(!success, "Unknown tint colors should fail");
+
+    let success =
+        run_mirage_command_suppress_output(&["threshold", TEST_IMAGE, "output.png", "120"])?;
+    ensure!(!success, "Threshold over 100% should fail");
+
+    let success = run_mirage_command_suppress_output(&[
+        "threshold",
+        TEST_IMAGE,
+        "output.png",
+        "50",
+        "--otsu",
+    ])?;
+    ensure!(!success, "A level and --otsu should fail");
+
+    let success =
+        run_mirage_command_suppress_output(&["posterize", TEST_IMAGE, "output.png", "1"])?;
+    ensure!(!success, "Posterizing to one level should fail");
+
+    let success =
+        run_mirage_command_suppress_output(&["dither", TEST_IMAGE, "output.png", "--bits", "3"])?;
+    ensure!(!success, "Three bits per pixel should fail");
+
+    let success = run_mirage_command_suppress_output(&[
+        "dither",
+        TEST_IMAGE,
+        "output.png",
+        "--palette",
+        "black",
+    ])?;
+    ensure!(!success, "A one color palette should fail");
 
     Ok(())
 }